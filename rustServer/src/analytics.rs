@@ -0,0 +1,340 @@
+use actix_session::Session;
+use actix_web::{web, HttpRequest, HttpResponse};
+use bytes::Bytes;
+use futures_util::Stream;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::SqlitePool;
+use std::pin::Pin;
+use std::sync::OnceLock;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use crate::auth::require_admin;
+use crate::config::Config;
+use crate::storage::ByteStream;
+
+/// How often expired raw download events are purged.
+const RETENTION_SWEEP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Serialize)]
+pub struct FileStats {
+    pub file_id: Option<String>,
+    pub file_path: String,
+    pub display_name: Option<String>,
+    pub downloads: i64,
+    pub completed: i64,
+    pub bytes_sent: i64,
+}
+
+#[derive(Serialize)]
+pub struct DailyStats {
+    pub day: String,
+    pub downloads: i64,
+    pub completed: i64,
+    pub bytes_sent: i64,
+}
+
+#[derive(Deserialize)]
+pub struct DailyQuery {
+    pub days: Option<u32>,
+}
+
+/// Context of a single download, recorded once its body stops streaming.
+pub struct DownloadRecorder {
+    pool: SqlitePool,
+    file_id: Option<String>,
+    file_path: String,
+    user_id: Option<String>,
+    ip_hash: String,
+}
+
+impl DownloadRecorder {
+    pub fn new(
+        pool: SqlitePool,
+        file_id: Option<String>,
+        file_path: &str,
+        user_id: Option<String>,
+        ip_hash: String,
+    ) -> Self {
+        Self {
+            pool,
+            file_id,
+            file_path: file_path.to_string(),
+            user_id,
+            ip_hash,
+        }
+    }
+
+    /// Wraps a response body so the bytes actually sent, and whether the
+    /// transfer reached `expected_len`, are recorded when it is dropped.
+    pub fn wrap(self, body: ByteStream, expected_len: u64) -> RecordingStream {
+        RecordingStream {
+            inner: body,
+            bytes_sent: 0,
+            expected_len,
+            recorder: Some(self),
+        }
+    }
+
+    async fn record(self, bytes_sent: u64, completed: bool) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO download_events (file_id, file_path, user_id, ip_hash, bytes_sent, completed)
+            VALUES (?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(&self.file_id)
+        .bind(&self.file_path)
+        .bind(&self.user_id)
+        .bind(&self.ip_hash)
+        .bind(bytes_sent as i64)
+        .bind(completed as i32)
+        .execute(&mut *tx)
+        .await?;
+
+        // Daily counters outlive the raw events' retention window
+        sqlx::query(
+            r#"
+            INSERT INTO download_stats_daily (file_path, day, downloads, completed, bytes_sent)
+            VALUES (?, date('now'), 1, ?, ?)
+            ON CONFLICT (file_path, day) DO UPDATE SET
+                downloads = downloads + 1,
+                completed = completed + excluded.completed,
+                bytes_sent = bytes_sent + excluded.bytes_sent
+            "#,
+        )
+        .bind(&self.file_path)
+        .bind(completed as i32)
+        .bind(bytes_sent as i64)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await
+    }
+}
+
+pub struct RecordingStream {
+    inner: ByteStream,
+    bytes_sent: u64,
+    expected_len: u64,
+    recorder: Option<DownloadRecorder>,
+}
+
+impl Stream for RecordingStream {
+    type Item = std::io::Result<Bytes>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let poll = self.inner.as_mut().poll_next(cx);
+        if let Poll::Ready(Some(Ok(chunk))) = &poll {
+            self.bytes_sent += chunk.len() as u64;
+        }
+        poll
+    }
+}
+
+impl Drop for RecordingStream {
+    fn drop(&mut self) {
+        let Some(recorder) = self.recorder.take() else {
+            return;
+        };
+        let bytes_sent = self.bytes_sent;
+        let completed = bytes_sent >= self.expected_len;
+
+        if let Ok(handle) = tokio::runtime::Handle::try_current() {
+            handle.spawn(async move {
+                let file_path = recorder.file_path.clone();
+                if let Err(e) = recorder.record(bytes_sent, completed).await {
+                    tracing::error!("Failed to record download of {}: {}", file_path, e);
+                }
+            });
+        }
+    }
+}
+
+/// Hashes the client address with the configured salt so visitors can be
+/// told apart without storing their IP.
+pub fn client_ip_hash(req: &HttpRequest, config: &Config) -> String {
+    let ip = if config.trust_proxy_headers {
+        req.connection_info().realip_remote_addr().map(str::to_string)
+    } else {
+        req.peer_addr().map(|addr| addr.ip().to_string())
+    }
+    .unwrap_or_default();
+
+    let mut hasher = Sha256::new();
+    hasher.update(ip_hash_salt(config).as_bytes());
+    hasher.update(ip.as_bytes());
+    hex::encode(hasher.finalize())
+}
+
+/// The configured salt, or a random one for the lifetime of the process.
+fn ip_hash_salt(config: &Config) -> &str {
+    static FALLBACK_SALT: OnceLock<String> = OnceLock::new();
+    match config.ip_hash_salt.as_deref() {
+        Some(salt) => salt,
+        None => FALLBACK_SALT.get_or_init(|| uuid::Uuid::new_v4().to_string()),
+    }
+}
+
+/// Periodically deletes raw download events older than the retention window.
+pub fn spawn_retention_task(pool: SqlitePool, retention_days: u32) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(RETENTION_SWEEP_INTERVAL);
+        loop {
+            interval.tick().await;
+            let result = sqlx::query("DELETE FROM download_events WHERE created_at < datetime('now', ?)")
+                .bind(format!("-{} days", retention_days))
+                .execute(&pool)
+                .await;
+
+            match result {
+                Ok(r) if r.rows_affected() > 0 => {
+                    tracing::info!("Purged {} expired download events", r.rows_affected());
+                }
+                Ok(_) => {}
+                Err(e) => tracing::error!("Failed to purge download events: {}", e),
+            }
+        }
+    });
+}
+
+/// Lifetime download totals per file, most downloaded first.
+pub async fn file_stats(pool: web::Data<SqlitePool>, session: Session) -> HttpResponse {
+    if let Err(response) = require_admin(pool.get_ref(), &session).await {
+        return response;
+    }
+
+    let rows = sqlx::query_as::<_, (Option<String>, String, Option<String>, i64, i64, i64)>(
+        r#"
+        SELECT df.id, s.file_path, df.display_name,
+               SUM(s.downloads), SUM(s.completed), SUM(s.bytes_sent)
+        FROM download_stats_daily s
+        LEFT JOIN download_files df ON df.file_path = s.file_path
+        GROUP BY s.file_path
+        ORDER BY SUM(s.downloads) DESC
+        "#,
+    )
+    .fetch_all(pool.get_ref())
+    .await;
+
+    match rows {
+        Ok(rows) => {
+            let stats: Vec<FileStats> = rows
+                .into_iter()
+                .map(|(file_id, file_path, display_name, downloads, completed, bytes_sent)| FileStats {
+                    file_id,
+                    file_path,
+                    display_name,
+                    downloads,
+                    completed,
+                    bytes_sent,
+                })
+                .collect();
+            HttpResponse::Ok().json(stats)
+        }
+        Err(e) => {
+            tracing::error!("Database error reading download stats: {}", e);
+            HttpResponse::InternalServerError().body("Database error")
+        }
+    }
+}
+
+/// Per-day download totals for one file over the last `days` days (default 30).
+pub async fn file_daily_stats(
+    pool: web::Data<SqlitePool>,
+    session: Session,
+    path: web::Path<String>,
+    query: web::Query<DailyQuery>,
+) -> HttpResponse {
+    if let Err(response) = require_admin(pool.get_ref(), &session).await {
+        return response;
+    }
+
+    let file_id = path.into_inner();
+    let days = query.days.unwrap_or(30);
+
+    let rows = sqlx::query_as::<_, (String, i64, i64, i64)>(
+        r#"
+        SELECT s.day, s.downloads, s.completed, s.bytes_sent
+        FROM download_stats_daily s
+        JOIN download_files df ON df.file_path = s.file_path
+        WHERE df.id = ? AND s.day >= date('now', ?)
+        ORDER BY s.day
+        "#,
+    )
+    .bind(&file_id)
+    .bind(format!("-{} days", days))
+    .fetch_all(pool.get_ref())
+    .await;
+
+    match rows {
+        Ok(rows) => {
+            let stats: Vec<DailyStats> = rows
+                .into_iter()
+                .map(|(day, downloads, completed, bytes_sent)| DailyStats {
+                    day,
+                    downloads,
+                    completed,
+                    bytes_sent,
+                })
+                .collect();
+            HttpResponse::Ok().json(stats)
+        }
+        Err(e) => {
+            tracing::error!("Database error reading download stats: {}", e);
+            HttpResponse::InternalServerError().body("Database error")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::{stream, StreamExt};
+
+    async fn download(pool: &SqlitePool, chunks: usize, expected_len: u64) {
+        let body = stream::iter((0..chunks).map(|_| Ok(Bytes::from_static(b"12345")))).boxed();
+        let recorder = DownloadRecorder::new(pool.clone(), None, "app.zip", None, "hash".into());
+        let mut recording = recorder.wrap(body, expected_len);
+        while recording.next().await.is_some() {}
+        drop(recording);
+    }
+
+    #[tokio::test]
+    async fn test_recording_stream_counts_bytes_and_completion() {
+        let pool = crate::db::test_pool().await;
+
+        download(&pool, 2, 10).await;
+        download(&pool, 1, 10).await;
+        // Recording happens on spawned tasks; wait for both to land
+        for _ in 0..100 {
+            let count = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM download_events")
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+            if count == 2 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        let events = sqlx::query_as::<_, (i64, i32)>(
+            "SELECT bytes_sent, completed FROM download_events ORDER BY id",
+        )
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+        assert_eq!(events, [(10, 1), (5, 0)]);
+
+        let daily = sqlx::query_as::<_, (i64, i64, i64)>(
+            "SELECT downloads, completed, bytes_sent FROM download_stats_daily WHERE file_path = 'app.zip'",
+        )
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        assert_eq!(daily, (2, 1, 15));
+    }
+}
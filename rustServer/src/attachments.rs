@@ -0,0 +1,217 @@
+use actix_multipart::{Field, Multipart};
+use actix_web::HttpResponse;
+use futures_util::TryStreamExt;
+
+use crate::handlers::EmailRequest;
+use crate::mail::Attachment;
+use crate::validation::{single_line, FieldError, ValidationErrors};
+
+/// Largest accepted text field in a multipart submission; the form's own
+/// length checks apply afterwards.
+const MAX_TEXT_FIELD_BYTES: usize = 64 * 1024;
+const MAX_FILENAME_LEN: usize = 100;
+/// Room in a multipart submission for its text fields and part headers.
+const FORM_FIELDS_ALLOWANCE: usize = 16 * MAX_TEXT_FIELD_BYTES;
+
+/// What visitors may attach to the contact form (`CONTACT_MAX_ATTACHMENTS`,
+/// `CONTACT_MAX_ATTACHMENT_BYTES`, `CONTACT_ATTACHMENT_TYPES`).
+#[derive(Clone, Debug)]
+pub struct AttachmentLimits {
+    /// Files per submission; 0 turns attachments off
+    pub max_count: usize,
+    /// Size of a single file
+    pub max_bytes: usize,
+    /// MIME types, determined from the file extension
+    pub allowed_types: Vec<String>,
+}
+
+impl Default for AttachmentLimits {
+    fn default() -> Self {
+        Self {
+            max_count: 3,
+            max_bytes: 5 * 1024 * 1024,
+            allowed_types: [
+                "application/pdf",
+                "application/msword",
+                "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+                "image/gif",
+                "image/jpeg",
+                "image/png",
+                "image/webp",
+                "text/plain",
+            ]
+            .map(String::from)
+            .to_vec(),
+        }
+    }
+}
+
+impl AttachmentLimits {
+    /// Largest multipart submission these limits allow: every file at its
+    /// maximum, plus room for the text fields and part headers.
+    pub fn max_form_bytes(&self) -> usize {
+        self.max_count * self.max_bytes + FORM_FIELDS_ALLOWANCE
+    }
+}
+
+/// Reads a `multipart/form-data` contact submission: the JSON body's fields
+/// as text parts, plus files in parts with a filename. Files are checked
+/// against `limits` while they stream in.
+pub async fn read_contact_form(
+    mut payload: Multipart,
+    limits: &AttachmentLimits,
+) -> Result<(EmailRequest, Vec<Attachment>), HttpResponse> {
+    let mut request = EmailRequest::default();
+    let mut attachments = Vec::new();
+
+    while let Some(field) = payload.try_next().await.map_err(bad_request)? {
+        let filename = field
+            .content_disposition()
+            .and_then(|disposition| disposition.get_filename())
+            .map(str::to_string);
+        match filename {
+            // Browsers send an empty file part when no file was chosen
+            Some(filename) if filename.is_empty() => drain(field).await?,
+            Some(filename) => {
+                if attachments.len() >= limits.max_count {
+                    return Err(rejection(
+                        "too_many",
+                        match limits.max_count {
+                            0 => "Attachments are not accepted".to_string(),
+                            max => format!("At most {} files can be attached", max),
+                        },
+                    ));
+                }
+                attachments.push(read_attachment(field, &filename, limits).await?);
+            }
+            None => {
+                let name = field.name().unwrap_or_default().to_string();
+                let value = read_text(field).await?;
+                match name.as_str() {
+                    "sender" => request.sender = value,
+                    "firstName" => request.first_name = value,
+                    "lastName" => request.last_name = value,
+                    "message" => request.message = value,
+                    "topic" => request.topic = Some(value),
+                    "website" => request.website = Some(value),
+                    "formToken" => request.form_token = Some(value),
+                    "powSolution" => request.pow_solution = Some(value),
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    Ok((request, attachments))
+}
+
+async fn read_attachment(mut field: Field, filename: &str, limits: &AttachmentLimits) -> Result<Attachment, HttpResponse> {
+    let filename = sanitize_filename(filename);
+    let content_type = mime_guess::from_path(&filename)
+        .first()
+        .map(|mime| mime.essence_str().to_string())
+        .filter(|mime| limits.allowed_types.contains(mime))
+        .ok_or_else(|| rejection("unsupported_type", format!("{} is not an accepted file type", filename)))?;
+
+    let mut content = Vec::new();
+    while let Some(chunk) = field.try_next().await.map_err(bad_request)? {
+        if content.len() + chunk.len() > limits.max_bytes {
+            return Err(rejection(
+                "too_large",
+                format!("{} is larger than {} bytes", filename, limits.max_bytes),
+            ));
+        }
+        content.extend_from_slice(&chunk);
+    }
+
+    if !content_matches_type(&content_type, &content) {
+        return Err(rejection(
+            "unsupported_type",
+            format!("{} does not look like a {} file", filename, content_type),
+        ));
+    }
+
+    Ok(Attachment {
+        filename,
+        content_type,
+        content,
+    })
+}
+
+async fn read_text(mut field: Field) -> Result<String, HttpResponse> {
+    let mut value = Vec::new();
+    while let Some(chunk) = field.try_next().await.map_err(bad_request)? {
+        if value.len() + chunk.len() > MAX_TEXT_FIELD_BYTES {
+            return Err(HttpResponse::PayloadTooLarge().body("Form field too large"));
+        }
+        value.extend_from_slice(&chunk);
+    }
+    String::from_utf8(value).map_err(|_| HttpResponse::BadRequest().body("Form fields must be UTF-8"))
+}
+
+async fn drain(mut field: Field) -> Result<(), HttpResponse> {
+    while field.try_next().await.map_err(bad_request)?.is_some() {}
+    Ok(())
+}
+
+/// The file's base name without characters that could break headers.
+fn sanitize_filename(filename: &str) -> String {
+    let base = filename.rsplit(['/', '\\']).next().unwrap_or_default();
+    let name: String = single_line(base)
+        .chars()
+        .filter(|c| !matches!(c, '"' | '<' | '>' | ':' | '|' | '?' | '*'))
+        .take(MAX_FILENAME_LEN)
+        .collect();
+    match name.trim() {
+        "" | "." | ".." => "attachment".to_string(),
+        name => name.to_string(),
+    }
+}
+
+/// Whether the content starts the way files of `content_type` do, so a
+/// renamed executable isn't passed off as a PDF. Types without a known
+/// signature are accepted as they are.
+fn content_matches_type(content_type: &str, content: &[u8]) -> bool {
+    match content_type {
+        "application/pdf" => content.starts_with(b"%PDF-"),
+        "application/msword" => content.starts_with(&[0xD0, 0xCF, 0x11, 0xE0, 0xA1, 0xB1, 0x1A, 0xE1]),
+        "application/vnd.openxmlformats-officedocument.wordprocessingml.document" => content.starts_with(b"PK\x03\x04"),
+        "image/gif" => content.starts_with(b"GIF87a") || content.starts_with(b"GIF89a"),
+        "image/jpeg" => content.starts_with(&[0xFF, 0xD8, 0xFF]),
+        "image/png" => content.starts_with(b"\x89PNG\r\n\x1a\n"),
+        "image/webp" => content.len() >= 12 && content.starts_with(b"RIFF") && &content[8..12] == b"WEBP",
+        "text/plain" => std::str::from_utf8(content).is_ok(),
+        _ => true,
+    }
+}
+
+fn rejection(code: &'static str, message: String) -> HttpResponse {
+    ValidationErrors::single(FieldError {
+        field: "attachments",
+        code,
+        message,
+    })
+    .into_response()
+}
+
+fn bad_request(e: actix_multipart::MultipartError) -> HttpResponse {
+    HttpResponse::BadRequest().body(format!("Invalid multipart body: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filenames_and_signatures() {
+        assert_eq!(sanitize_filename("C:\\Users\\ada\\My \"CV\".pdf"), "My CV.pdf");
+        assert_eq!(sanitize_filename("../../etc/passwd"), "passwd");
+        assert_eq!(sanitize_filename("bad\r\nname.txt"), "badname.txt");
+        assert_eq!(sanitize_filename("/"), "attachment");
+
+        assert!(content_matches_type("application/pdf", b"%PDF-1.7 ..."));
+        assert!(!content_matches_type("application/pdf", b"MZ\x90\x00"));
+        assert!(content_matches_type("image/webp", b"RIFF\x00\x00\x00\x00WEBPVP8 "));
+        assert!(!content_matches_type("text/plain", &[0xFF, 0xFE, 0x00]));
+    }
+}
@@ -8,6 +8,8 @@ use serde::{Deserialize, Serialize};
 use sqlx::SqlitePool;
 use uuid::Uuid;
 
+use crate::notify::{Notification, Notifications};
+
 #[derive(Deserialize)]
 pub struct LoginRequest {
     pub username: String,
@@ -28,6 +30,7 @@ pub struct UserInfo {
 
 pub async fn login(
     pool: web::Data<SqlitePool>,
+    notifications: web::Data<Notifications>,
     session: Session,
     body: web::Json<LoginRequest>,
 ) -> HttpResponse {
@@ -41,6 +44,7 @@ pub async fn login(
     let user = match user {
         Ok(Some(u)) => u,
         Ok(None) => {
+            notifications.login_failed(&body.username);
             return HttpResponse::Unauthorized().json(AuthResponse {
                 success: false,
                 message: "Invalid credentials".to_string(),
@@ -72,6 +76,7 @@ pub async fn login(
         .verify_password(body.password.as_bytes(), &parsed_hash)
         .is_err()
     {
+        notifications.login_failed(&body.username);
         return HttpResponse::Unauthorized().json(AuthResponse {
             success: false,
             message: "Invalid credentials".to_string(),
@@ -125,6 +130,32 @@ pub fn get_user_id(session: &Session) -> Option<String> {
     session.get::<String>("user_id").ok().flatten()
 }
 
+/// Returns the session's user id if that user is an administrator,
+/// otherwise the response to send back.
+pub async fn require_admin(pool: &SqlitePool, session: &Session) -> Result<String, HttpResponse> {
+    let user_id = match get_user_id(session) {
+        Some(id) => id,
+        None => return Err(HttpResponse::Unauthorized().body("Authentication required")),
+    };
+
+    match is_admin(pool, &user_id).await {
+        Ok(true) => Ok(user_id),
+        Ok(false) => Err(HttpResponse::Forbidden().body("Administrator access required")),
+        Err(e) => {
+            tracing::error!("Database error checking admin: {}", e);
+            Err(HttpResponse::InternalServerError().body("Database error"))
+        }
+    }
+}
+
+pub async fn is_admin(pool: &SqlitePool, user_id: &str) -> Result<bool, sqlx::Error> {
+    let flag = sqlx::query_scalar::<_, i32>("SELECT is_admin FROM users WHERE id = ?")
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await?;
+    Ok(flag.is_some_and(|f| f != 0))
+}
+
 pub fn hash_password(password: &str) -> Result<String, argon2::password_hash::Error> {
     let salt = SaltString::generate(&mut OsRng);
     let argon2 = Argon2::default();
@@ -132,8 +163,22 @@ pub fn hash_password(password: &str) -> Result<String, argon2::password_hash::Er
     Ok(hash.to_string())
 }
 
+/// Checks a password against a stored Argon2 hash.
+pub fn verify_password(password: &str, password_hash: &str) -> bool {
+    match PasswordHash::new(password_hash) {
+        Ok(parsed) => Argon2::default()
+            .verify_password(password.as_bytes(), &parsed)
+            .is_ok(),
+        Err(_) => false,
+    }
+}
+
+/// Creates an account and announces it to webhooks receiving the
+/// `registration` event. Nothing creates accounts over HTTP yet.
+#[allow(dead_code)]
 pub async fn create_user(
     pool: &SqlitePool,
+    notifications: &Notifications,
     username: &str,
     password: &str,
 ) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
@@ -147,5 +192,52 @@ pub async fn create_user(
         .execute(pool)
         .await?;
 
+    notifications.dispatch(Notification::registration(username));
     Ok(id)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notify::{Event, LoginAlertPolicy, Notifier, NotifyError};
+    use crate::outbox::RetryPolicy;
+    use async_trait::async_trait;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Default)]
+    struct Recorded(Mutex<Vec<Notification>>);
+
+    #[async_trait]
+    impl Notifier for Recorded {
+        async fn notify(&self, notification: &Notification) -> Result<(), NotifyError> {
+            self.0.lock().unwrap().push(notification.clone());
+            Ok(())
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_create_user_notifies_registration() {
+        let pool = crate::db::test_pool().await;
+        let recorded = Arc::new(Recorded::default());
+        let notifications = Notifications::new(RetryPolicy::default(), LoginAlertPolicy::default())
+            .route(recorded.clone(), vec![Event::Registration]);
+
+        create_user(&pool, &notifications, "grace", "long enough").await.unwrap();
+        let hash: String = sqlx::query_scalar("SELECT password_hash FROM users WHERE username = 'grace'")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert!(verify_password("long enough", &hash));
+
+        // Notifications are delivered in the background
+        for _ in 0..50 {
+            if !recorded.0.lock().unwrap().is_empty() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        let notified = recorded.0.lock().unwrap();
+        assert_eq!(notified.len(), 1);
+        assert_eq!(notified[0].body, "User grace signed up");
+    }
+}
@@ -0,0 +1,241 @@
+use sqlx::SqlitePool;
+use std::time::Duration;
+
+use crate::mail::{ContactRouting, EmailMessage, MailError, Templates};
+use crate::outbox::Outbox;
+use crate::suppressions;
+use crate::validation::ContactForm;
+
+/// Acknowledgements sent to contact form senders (`AUTO_REPLY_ENABLED`,
+/// `AUTO_REPLY_WINDOW_HOURS`, `AUTO_REPLY_MAX_PER_IP_HOUR`,
+/// `AUTO_REPLY_MAX_PER_HOUR`), rendered from the `auto_reply` templates.
+#[derive(Clone, Debug)]
+pub struct AutoReplyConfig {
+    pub enabled: bool,
+    /// An address gets at most one acknowledgement within this window
+    pub window: Duration,
+    /// Acknowledgements per client IP and hour
+    pub max_per_ip_hour: u32,
+    /// Acknowledgements per hour in total
+    pub max_per_hour: u32,
+}
+
+impl Default for AutoReplyConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            window: Duration::from_secs(24 * 60 * 60),
+            max_per_ip_hour: 3,
+            max_per_hour: 50,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Outcome {
+    Queued,
+    Disabled,
+    Suppressed,
+    /// The address was already acknowledged within the window
+    RecentlySent,
+    /// The hourly cap for the client IP or in total was reached
+    RateLimited,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum AutoReplyError {
+    #[error("Database error: {0}")]
+    Database(#[from] sqlx::Error),
+    #[error(transparent)]
+    Mail(#[from] MailError),
+}
+
+/// Queues an acknowledgement to the sender of a submission that was
+/// accepted as genuine, unless the address is suppressed or was
+/// acknowledged recently, or the hourly caps are reached. The sender's
+/// address is unverified, so the acknowledgement is fixed text that
+/// contains nothing from the submission.
+pub async fn send(
+    pool: &SqlitePool,
+    outbox: &Outbox,
+    templates: &Templates,
+    config: &AutoReplyConfig,
+    routing: &ContactRouting,
+    form: &ContactForm,
+    ip_hash: &str,
+) -> Result<Outcome, AutoReplyError> {
+    if !config.enabled {
+        return Ok(Outcome::Disabled);
+    }
+    if suppressions::is_suppressed(pool, &form.sender).await? {
+        return Ok(Outcome::Suppressed);
+    }
+
+    let rendered = templates.render("auto_reply", serde_json::json!({}))?;
+    let recipients = routing
+        .recipients(form.topic.as_deref())
+        .unwrap_or(&routing.default);
+    let message = EmailMessage {
+        from: routing.from.clone(),
+        to: vec![form.sender.clone()],
+        reply_to: recipients.to.first().cloned(),
+        subject: rendered.subject,
+        text: rendered.text,
+        html: Some(rendered.html),
+        ..Default::default()
+    };
+
+    if !claim(pool, &form.sender, config.window).await? {
+        return Ok(Outcome::RecentlySent);
+    }
+    let slot = match reserve_slot(pool, config, ip_hash).await {
+        Ok(Some(slot)) => slot,
+        Ok(None) => {
+            unclaim(pool, &form.sender).await?;
+            return Ok(Outcome::RateLimited);
+        }
+        Err(e) => {
+            unclaim(pool, &form.sender).await?;
+            return Err(e.into());
+        }
+    };
+    // Undo the claim and the slot so a failed enqueue doesn't hold up a later acknowledgement
+    if let Err(e) = outbox.enqueue(&message).await {
+        unclaim(pool, &form.sender).await?;
+        sqlx::query("DELETE FROM auto_reply_sends WHERE rowid = ?")
+            .bind(slot)
+            .execute(pool)
+            .await?;
+        return Err(e.into());
+    }
+    Ok(Outcome::Queued)
+}
+
+/// Records an acknowledgement to `address`, returning false if one was
+/// already sent within `window`.
+async fn claim(pool: &SqlitePool, address: &str, window: Duration) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query(
+        r#"
+        INSERT INTO auto_reply_log (address) VALUES (?)
+        ON CONFLICT (address) DO UPDATE SET sent_at = datetime('now')
+        WHERE sent_at <= datetime('now', ?)
+        "#,
+    )
+    .bind(address.trim().to_lowercase())
+    .bind(format!("-{} seconds", window.as_secs()))
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() == 1)
+}
+
+async fn unclaim(pool: &SqlitePool, address: &str) -> Result<(), sqlx::Error> {
+    sqlx::query("DELETE FROM auto_reply_log WHERE address = ?")
+        .bind(address.trim().to_lowercase())
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Takes one of this hour's acknowledgements for `ip_hash`, returning its
+/// row id, or `None` if the per-IP or global cap is reached.
+async fn reserve_slot(pool: &SqlitePool, config: &AutoReplyConfig, ip_hash: &str) -> Result<Option<i64>, sqlx::Error> {
+    sqlx::query("DELETE FROM auto_reply_sends WHERE sent_at <= datetime('now', '-1 hour')")
+        .execute(pool)
+        .await?;
+    let result = sqlx::query(
+        r#"
+        INSERT INTO auto_reply_sends (ip_hash)
+        SELECT ?
+        WHERE (SELECT COUNT(*) FROM auto_reply_sends WHERE ip_hash = ?) < ?
+          AND (SELECT COUNT(*) FROM auto_reply_sends) < ?
+        "#,
+    )
+    .bind(ip_hash)
+    .bind(ip_hash)
+    .bind(config.max_per_ip_hour)
+    .bind(config.max_per_hour)
+    .execute(pool)
+    .await?;
+    Ok((result.rows_affected() == 1).then(|| result.last_insert_rowid()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn form(sender: &str) -> ContactForm {
+        ContactForm {
+            sender: sender.into(),
+            first_name: "Ada".into(),
+            last_name: "Lovelace".into(),
+            message: "Hello!".into(),
+            topic: None,
+            attachments: Vec::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_auto_reply_safeguards() {
+        let pool = crate::db::test_pool().await;
+        let outbox = Outbox::new(pool.clone());
+        let templates = Templates::new(None);
+        let routing = ContactRouting::default();
+        let config = AutoReplyConfig {
+            enabled: true,
+            ..Default::default()
+        };
+        let send = |config: AutoReplyConfig, sender: &'static str| {
+            let (pool, outbox, templates, routing) = (&pool, &outbox, &templates, &routing);
+            async move { send(pool, outbox, templates, &config, routing, &form(sender), "ip").await.unwrap() }
+        };
+
+        assert_eq!(send(AutoReplyConfig::default(), "ada@example.com").await, Outcome::Disabled);
+        assert_eq!(send(config.clone(), "ada@example.com").await, Outcome::Queued);
+        assert_eq!(send(config.clone(), "ADA@example.com").await, Outcome::RecentlySent);
+
+        let payload: String = sqlx::query_scalar("SELECT message FROM mail_outbox").fetch_one(&pool).await.unwrap();
+        let queued: EmailMessage = serde_json::from_str(&payload).unwrap();
+        assert_eq!(queued.to, ["ada@example.com"]);
+        assert_eq!(queued.reply_to.as_deref(), Some("LoganTCarpenter@gmail.com"));
+        assert!(queued.text.contains("Thanks for getting in touch"));
+        assert!(!queued.text.contains("Hello!"), "nothing the visitor wrote is sent back");
+        assert!(!queued.html.unwrap().contains("Hello!"));
+
+        // Once the window has passed the address is acknowledged again
+        sqlx::query("UPDATE auto_reply_log SET sent_at = datetime('now', '-2 days')")
+            .execute(&pool)
+            .await
+            .unwrap();
+        assert_eq!(send(config.clone(), "ada@example.com").await, Outcome::Queued);
+
+        suppressions::suppress(&pool, "Blocked@Example.com", "manual").await.unwrap();
+        assert_eq!(send(config, "blocked@example.com").await, Outcome::Suppressed);
+    }
+
+    #[tokio::test]
+    async fn test_auto_reply_hourly_caps() {
+        let pool = crate::db::test_pool().await;
+        let outbox = Outbox::new(pool.clone());
+        let templates = Templates::new(None);
+        let routing = ContactRouting::default();
+        let config = AutoReplyConfig {
+            enabled: true,
+            max_per_ip_hour: 2,
+            max_per_hour: 3,
+            ..Default::default()
+        };
+        let send = |sender: &'static str, ip: &'static str| {
+            let (pool, outbox, templates, routing, config) = (&pool, &outbox, &templates, &routing, &config);
+            async move { send(pool, outbox, templates, config, routing, &form(sender), ip).await.unwrap() }
+        };
+
+        assert_eq!(send("a@example.com", "ip1").await, Outcome::Queued);
+        assert_eq!(send("b@example.com", "ip1").await, Outcome::Queued);
+        assert_eq!(send("c@example.com", "ip1").await, Outcome::RateLimited);
+        assert_eq!(send("c@example.com", "ip2").await, Outcome::Queued, "a capped address is not claimed");
+        assert_eq!(send("d@example.com", "ip3").await, Outcome::RateLimited);
+
+        let queued: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM mail_outbox").fetch_one(&pool).await.unwrap();
+        assert_eq!(queued, 3);
+    }
+}
@@ -0,0 +1,357 @@
+use async_trait::async_trait;
+use futures_util::StreamExt;
+use sha2::{Digest, Sha256};
+use sqlx::SqlitePool;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio::io::AsyncWriteExt;
+
+use crate::storage::{
+    object_key, ByteRange, ByteStream, LocalStorage, ObjectMeta, StorageBackend, StorageError,
+};
+
+/// How long an unreferenced blob is kept before garbage collection may
+/// remove it, so blobs written by an import still in progress survive.
+pub const GC_GRACE_PERIOD: Duration = Duration::from_secs(60 * 60);
+
+/// Directory of immutable blobs named by the SHA-256 of their content and
+/// sharded by the first two hex digits (`ab/abcdef…`).
+pub struct BlobStore {
+    root: PathBuf,
+    files: LocalStorage,
+}
+
+impl BlobStore {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        let root = root.into();
+        Self {
+            files: LocalStorage::new(&root),
+            root,
+        }
+    }
+
+    fn key(hash: &str) -> String {
+        format!("{}/{}", &hash[..2], hash)
+    }
+
+    fn path(&self, hash: &str) -> PathBuf {
+        self.root.join(Self::key(hash))
+    }
+
+    /// Copies a stream into the store's temporary directory, hashing it on
+    /// the way. [`BlobStore::place`] then moves it into place.
+    async fn stage(&self, mut body: ByteStream) -> Result<Staged, StorageError> {
+        let tmp_dir = self.root.join("tmp");
+        tokio::fs::create_dir_all(&tmp_dir).await?;
+        let tmp = tmp_dir.join(uuid::Uuid::new_v4().to_string());
+
+        let mut file = tokio::fs::File::create(&tmp).await?;
+        let mut hasher = Sha256::new();
+        let mut size = 0u64;
+        while let Some(chunk) = body.next().await {
+            let chunk = match chunk {
+                Ok(chunk) => chunk,
+                Err(e) => {
+                    drop(file);
+                    tokio::fs::remove_file(&tmp).await.ok();
+                    return Err(e.into());
+                }
+            };
+            hasher.update(&chunk);
+            size += chunk.len() as u64;
+            file.write_all(&chunk).await?;
+        }
+        file.sync_all().await?;
+        drop(file);
+
+        Ok(Staged {
+            tmp,
+            hash: hex::encode(hasher.finalize()),
+            size,
+        })
+    }
+
+    /// Moves a staged blob to its path. Content that is already stored is
+    /// not written twice.
+    async fn place(&self, staged: &Staged) -> Result<(), StorageError> {
+        let path = self.path(&staged.hash);
+        if tokio::fs::try_exists(&path).await? {
+            tokio::fs::remove_file(&staged.tmp).await?;
+        } else {
+            tokio::fs::create_dir_all(path.parent().expect("blob path has a parent")).await?;
+            tokio::fs::rename(&staged.tmp, &path).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Content copied into the store's temporary directory.
+struct Staged {
+    tmp: PathBuf,
+    hash: String,
+    size: u64,
+}
+
+/// Serves files recorded in `download_files` with a `blob_hash` from the
+/// blob store, and everything else (including signatures) from `inner`.
+pub struct ContentAddressedStorage {
+    inner: Arc<dyn StorageBackend>,
+    blobs: Arc<BlobStore>,
+    pool: SqlitePool,
+}
+
+impl ContentAddressedStorage {
+    pub fn new(inner: Arc<dyn StorageBackend>, blobs: Arc<BlobStore>, pool: SqlitePool) -> Self {
+        Self { inner, blobs, pool }
+    }
+
+    async fn blob_for(&self, path: &str) -> Result<Option<String>, StorageError> {
+        let key = object_key(path)?;
+        sqlx::query_scalar::<_, String>(
+            "SELECT blob_hash FROM download_files WHERE file_path = ? AND blob_hash IS NOT NULL LIMIT 1",
+        )
+        .bind(key)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| StorageError::Backend(format!("Blob lookup failed: {}", e)))
+    }
+}
+
+#[async_trait]
+impl StorageBackend for ContentAddressedStorage {
+    async fn metadata(&self, path: &str) -> Result<ObjectMeta, StorageError> {
+        match self.blob_for(path).await? {
+            Some(hash) => {
+                let mut meta = self.blobs.files.metadata(&BlobStore::key(&hash)).await.map_err(|e| match e {
+                    StorageError::NotFound(_) => StorageError::NotFound(path.to_string()),
+                    e => e,
+                })?;
+                // Content never changes under a hash, so it makes the strongest validator
+                meta.etag = Some(format!("\"{}\"", hash));
+                Ok(meta)
+            }
+            None => self.inner.metadata(path).await,
+        }
+    }
+
+    async fn read(&self, path: &str, range: Option<ByteRange>) -> Result<ByteStream, StorageError> {
+        match self.blob_for(path).await? {
+            Some(hash) => self.blobs.files.read(&BlobStore::key(&hash), range).await,
+            None => self.inner.read(path, range).await,
+        }
+    }
+}
+
+/// Moves every download file not yet in the blob store into it. With
+/// `prune_dir`, the original copies are deleted from that directory.
+pub async fn import_files(
+    pool: &SqlitePool,
+    source: &dyn StorageBackend,
+    blobs: &BlobStore,
+    prune_dir: Option<&Path>,
+) -> Result<usize, StorageError> {
+    let files = sqlx::query_as::<_, (String, String)>(
+        "SELECT id, file_path FROM download_files WHERE blob_hash IS NULL",
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(db_error)?;
+
+    let mut imported = 0;
+    for (id, file_path) in files {
+        let body = match source.read(&file_path, None).await {
+            Ok(body) => body,
+            Err(StorageError::NotFound(_)) => {
+                tracing::warn!("Skipping missing file {}", file_path);
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+        let staged = blobs.stage(body).await?;
+        let Staged { hash, size, .. } = &staged;
+
+        // The blob row is written first, taking the database's write lock:
+        // garbage collection cannot remove the blob until this commits, and
+        // a removal that committed before is seen by `place`
+        let mut tx = pool.begin().await.map_err(db_error)?;
+        sqlx::query("INSERT OR IGNORE INTO blobs (hash, size_bytes) VALUES (?, ?)")
+            .bind(hash)
+            .bind(*size as i64)
+            .execute(&mut *tx)
+            .await
+            .map_err(db_error)?;
+        if let Err(e) = blobs.place(&staged).await {
+            tokio::fs::remove_file(&staged.tmp).await.ok();
+            return Err(e);
+        }
+        sqlx::query("UPDATE download_files SET blob_hash = ?, size_bytes = ? WHERE id = ?")
+            .bind(hash)
+            .bind(*size as i64)
+            .bind(&id)
+            .execute(&mut *tx)
+            .await
+            .map_err(db_error)?;
+        tx.commit().await.map_err(db_error)?;
+
+        if let Some(dir) = prune_dir {
+            let original = dir.join(object_key(&file_path)?);
+            if let Err(e) = tokio::fs::remove_file(&original).await {
+                tracing::warn!("Could not remove original {}: {}", original.display(), e);
+            }
+        }
+        tracing::info!("Imported {} as blob {}", file_path, hash);
+        imported += 1;
+    }
+    Ok(imported)
+}
+
+/// Deletes blobs no download file has referenced for at least `grace`, and
+/// stray files in the store (such as interrupted imports) older than that.
+pub async fn collect_garbage(
+    pool: &SqlitePool,
+    blobs: &BlobStore,
+    grace: Duration,
+) -> Result<usize, StorageError> {
+    let cutoff = format!("-{} seconds", grace.as_secs());
+    let unreferenced = sqlx::query_scalar::<_, String>(
+        "SELECT hash FROM blobs WHERE ref_count <= 0 AND updated_at <= datetime('now', ?)",
+    )
+    .bind(&cutoff)
+    .fetch_all(pool)
+    .await
+    .map_err(db_error)?;
+
+    let mut removed = 0;
+    for hash in unreferenced {
+        // Re-check under the delete so a blob referenced meanwhile is kept.
+        // The file is unlinked before committing, so an import waiting on
+        // the write lock finds it gone and puts it back.
+        let mut tx = pool.begin().await.map_err(db_error)?;
+        let deleted = sqlx::query("DELETE FROM blobs WHERE hash = ? AND ref_count <= 0")
+            .bind(&hash)
+            .execute(&mut *tx)
+            .await
+            .map_err(db_error)?;
+        if deleted.rows_affected() == 0 {
+            continue;
+        }
+        match tokio::fs::remove_file(blobs.path(&hash)).await {
+            Ok(()) => removed += 1,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+        tx.commit().await.map_err(db_error)?;
+    }
+
+    removed += remove_strays(pool, blobs, grace).await?;
+    Ok(removed)
+}
+
+async fn remove_strays(pool: &SqlitePool, blobs: &BlobStore, grace: Duration) -> Result<usize, StorageError> {
+    let mut removed = 0;
+    let mut shards = match tokio::fs::read_dir(&blobs.root).await {
+        Ok(shards) => shards,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e.into()),
+    };
+
+    while let Some(shard) = shards.next_entry().await? {
+        if !shard.file_type().await?.is_dir() {
+            continue;
+        }
+        let mut entries = tokio::fs::read_dir(shard.path()).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let age = entry
+                .metadata()
+                .await?
+                .modified()
+                .ok()
+                .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+                .unwrap_or_default();
+            if age < grace {
+                continue;
+            }
+
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let known = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM blobs WHERE hash = ?")
+                .bind(&name)
+                .fetch_one(pool)
+                .await
+                .map_err(db_error)?;
+            if known == 0 {
+                tokio::fs::remove_file(entry.path()).await?;
+                removed += 1;
+            }
+        }
+    }
+    Ok(removed)
+}
+
+fn db_error(e: sqlx::Error) -> StorageError {
+    StorageError::Backend(format!("Database error: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+    use futures_util::TryStreamExt;
+
+    async fn ref_count(pool: &SqlitePool, hash: &str) -> i64 {
+        sqlx::query_scalar("SELECT ref_count FROM blobs WHERE hash = ?")
+            .bind(hash)
+            .fetch_one(pool)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_import_dedupes_and_gc_removes_unreferenced() {
+        let pool = crate::db::test_pool().await;
+        sqlx::query(
+            "INSERT INTO download_files (id, file_path, display_name) VALUES ('a', 'v1/app.zip', 'A'), ('b', 'v2/app.zip', 'B')",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        let source = MemoryStorage::default();
+        source.insert("v1/app.zip", "same bytes").unwrap();
+        source.insert("v2/app.zip", "same bytes").unwrap();
+        source.insert("v2/app.zip.sig", "signature").unwrap();
+
+        let root = std::env::temp_dir().join(format!("blobs-{}", uuid::Uuid::new_v4()));
+        let blobs = Arc::new(BlobStore::new(&root));
+        assert_eq!(import_files(&pool, &source, &blobs, None).await.unwrap(), 2);
+
+        let hash = hex::encode(Sha256::digest(b"same bytes"));
+        assert_eq!(ref_count(&pool, &hash).await, 2);
+        assert!(blobs.path(&hash).exists());
+
+        let source: Arc<dyn StorageBackend> = Arc::new(source);
+        let storage = ContentAddressedStorage::new(source.clone(), blobs.clone(), pool.clone());
+        let body: Vec<_> = storage.read("v2/app.zip", None).await.unwrap().try_collect().await.unwrap();
+        assert_eq!(body.concat(), b"same bytes");
+        assert_eq!(storage.metadata("/v1/app.zip").await.unwrap().etag.unwrap(), format!("\"{}\"", hash));
+        // Files outside the blob store still come from the wrapped backend
+        assert_eq!(storage.metadata("v2/app.zip.sig").await.unwrap().size, 9);
+
+        sqlx::query("DELETE FROM download_files WHERE id = 'a'").execute(&pool).await.unwrap();
+        assert_eq!(ref_count(&pool, &hash).await, 1);
+        assert_eq!(collect_garbage(&pool, &blobs, Duration::ZERO).await.unwrap(), 0);
+
+        sqlx::query("UPDATE download_files SET blob_hash = NULL WHERE id = 'b'").execute(&pool).await.unwrap();
+        assert_eq!(ref_count(&pool, &hash).await, 0);
+        // Recently released blobs survive the grace period
+        assert_eq!(collect_garbage(&pool, &blobs, GC_GRACE_PERIOD).await.unwrap(), 0);
+        assert_eq!(collect_garbage(&pool, &blobs, Duration::ZERO).await.unwrap(), 1);
+        assert!(!blobs.path(&hash).exists());
+
+        // Importing the same content again puts the collected blob back
+        assert_eq!(import_files(&pool, source.as_ref(), &blobs, None).await.unwrap(), 1);
+        assert_eq!(ref_count(&pool, &hash).await, 1);
+        assert!(blobs.path(&hash).exists());
+
+        std::fs::remove_dir_all(&root).ok();
+    }
+}
@@ -0,0 +1,419 @@
+use actix_session::Session;
+use actix_web::http::header::{ContentDisposition, DispositionParam, DispositionType};
+use actix_web::{web, HttpRequest, HttpResponse};
+use async_zip::base::write::ZipFileWriter;
+use async_zip::{Compression, ZipEntryBuilder};
+use futures_util::{AsyncWriteExt, StreamExt};
+use serde::Deserialize;
+use sqlx::SqlitePool;
+use std::collections::HashSet;
+use std::sync::Arc;
+use tokio::io::AsyncWrite;
+use tokio_util::io::ReaderStream;
+use uuid::Uuid;
+
+use crate::analytics::{client_ip_hash, DownloadRecorder};
+use crate::auth::get_user_id;
+use crate::config::Config;
+use crate::downloads::{check_file_access, storage_error_response, DownloadToken};
+use crate::quotas::{check_quota, throttle};
+use crate::storage::{object_key, StorageBackend};
+
+/// Upper bound on the number of files a single bundle may contain.
+const MAX_BUNDLE_FILES: usize = 50;
+
+/// Size of the in-memory pipe between the ZIP writer and the response body.
+const PIPE_CAPACITY: usize = 64 * 1024;
+
+/// A file going into a bundle archive.
+struct BundleEntry {
+    name: String,
+    size: u64,
+    /// Records the file's download once its bytes have been written
+    recorder: Option<DownloadRecorder>,
+}
+
+#[derive(Deserialize)]
+pub struct BundleRequest {
+    pub file_ids: Vec<String>,
+}
+
+/// Creates a single-use token for a ZIP archive of several files. Every file is
+/// checked exactly as `generate_token` would before the token is issued.
+pub async fn generate_bundle(
+    pool: web::Data<SqlitePool>,
+    config: web::Data<Config>,
+    session: Session,
+    req: HttpRequest,
+    body: web::Json<BundleRequest>,
+) -> HttpResponse {
+    let user_id = match get_user_id(&session) {
+        Some(id) => id,
+        None => return HttpResponse::Unauthorized().body("Authentication required"),
+    };
+
+    let ip_hash = client_ip_hash(&req, &config);
+    if let Err(response) = check_quota(pool.get_ref(), &config, Some(&user_id), &ip_hash).await {
+        return response;
+    }
+
+    let mut seen = HashSet::new();
+    let file_ids: Vec<&String> = body.file_ids.iter().filter(|id| seen.insert(*id)).collect();
+
+    if file_ids.is_empty() {
+        return HttpResponse::BadRequest().body("No files requested");
+    }
+    if file_ids.len() > MAX_BUNDLE_FILES {
+        return HttpResponse::BadRequest()
+            .body(format!("A bundle may contain at most {} files", MAX_BUNDLE_FILES));
+    }
+
+    for file_id in &file_ids {
+        if let Err(response) = check_file_access(pool.get_ref(), file_id).await {
+            return response;
+        }
+    }
+
+    let token = Uuid::new_v4().to_string();
+    let bundle_id = Uuid::new_v4().to_string();
+
+    let result: Result<(), sqlx::Error> = async {
+        let mut tx = pool.begin().await?;
+
+        // Bundles expire like single-file tokens, so they cannot outlive the TTL
+        let expires_at = config.download_token_ttl_hours.map(|hours| format!("+{} hours", hours));
+        sqlx::query(
+            "INSERT INTO bundle_tokens (id, token, user_id, expires_at) VALUES (?, ?, ?, datetime('now', ?))",
+        )
+        .bind(&bundle_id)
+        .bind(&token)
+        .bind(&user_id)
+        .bind(expires_at)
+        .execute(&mut *tx)
+        .await?;
+
+        for file_id in &file_ids {
+            sqlx::query("INSERT INTO bundle_files (bundle_id, file_id) VALUES (?, ?)")
+                .bind(&bundle_id)
+                .bind(file_id)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        tx.commit().await
+    }
+    .await;
+
+    match result {
+        Ok(()) => HttpResponse::Ok().json(DownloadToken {
+            token: token.clone(),
+            download_url: format!("/downloads/bundle/{}", token),
+            signature_url: None,
+        }),
+        Err(e) => {
+            tracing::error!("Failed to create bundle token: {}", e);
+            HttpResponse::InternalServerError().body("Failed to generate token")
+        }
+    }
+}
+
+/// Streams the ZIP archive for a bundle token. The archive is produced while
+/// it is being sent, so it is never held in memory as a whole. Quotas apply
+/// as for single downloads, and each file is recorded as a download.
+pub async fn download_bundle(
+    pool: web::Data<SqlitePool>,
+    storage: web::Data<dyn StorageBackend>,
+    config: web::Data<Config>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> HttpResponse {
+    let token = path.into_inner();
+
+    let bundle = sqlx::query_as::<_, (String, String, i32)>(
+        r#"
+        SELECT id, user_id, used FROM bundle_tokens
+        WHERE token = ? AND revoked_at IS NULL
+          AND (expires_at IS NULL OR expires_at > datetime('now'))
+        "#,
+    )
+    .bind(&token)
+    .fetch_optional(pool.get_ref())
+    .await;
+
+    let (bundle_id, user_id, used) = match bundle {
+        Ok(Some(b)) => b,
+        Ok(None) => return HttpResponse::NotFound().body("Invalid or expired token"),
+        Err(e) => {
+            tracing::error!("Database error: {}", e);
+            return HttpResponse::InternalServerError().body("Database error");
+        }
+    };
+
+    if used != 0 {
+        return HttpResponse::Gone().body("Token has already been used");
+    }
+
+    let ip_hash = client_ip_hash(&req, &config);
+    if let Err(response) = check_quota(pool.get_ref(), &config, Some(&user_id), &ip_hash).await {
+        return response;
+    }
+
+    let files = sqlx::query_as::<_, (String, String)>(
+        r#"
+        SELECT df.id, df.file_path
+        FROM bundle_files bf
+        JOIN download_files df ON bf.file_id = df.id
+        WHERE bf.bundle_id = ?
+        ORDER BY df.file_path
+        "#,
+    )
+    .bind(&bundle_id)
+    .fetch_all(pool.get_ref())
+    .await;
+
+    let files = match files {
+        Ok(files) => files,
+        Err(e) => {
+            tracing::error!("Database error: {}", e);
+            return HttpResponse::InternalServerError().body("Database error");
+        }
+    };
+
+    // Resolve every file up front so missing files fail the request
+    // instead of truncating the archive mid-stream
+    let mut entries = Vec::with_capacity(files.len());
+    let mut names = HashSet::new();
+    for (file_id, file_path) in &files {
+        let name = match object_key(file_path) {
+            Ok(name) => name,
+            Err(e) => return storage_error_response(file_path, e),
+        };
+        let size = match storage.metadata(&name).await {
+            Ok(meta) => meta.size,
+            Err(e) => return storage_error_response(file_path, e),
+        };
+        if names.insert(name.clone()) {
+            let recorder = DownloadRecorder::new(
+                pool.get_ref().clone(),
+                Some(file_id.clone()),
+                file_path,
+                Some(user_id.clone()),
+                ip_hash.clone(),
+            );
+            entries.push(BundleEntry {
+                name,
+                size,
+                recorder: Some(recorder),
+            });
+        }
+    }
+
+    // Claim the token before streaming; of concurrent requests only one wins
+    match claim_bundle(pool.get_ref(), &bundle_id).await {
+        Ok(true) => {}
+        Ok(false) => return HttpResponse::Gone().body("Token has already been used"),
+        Err(e) => {
+            tracing::error!("Database error: {}", e);
+            return HttpResponse::InternalServerError().body("Database error");
+        }
+    }
+
+    let storage = storage.into_inner();
+    let (reader, writer) = tokio::io::duplex(PIPE_CAPACITY);
+    tokio::spawn(async move {
+        if let Err(e) = write_zip(writer, storage, entries).await {
+            tracing::error!("Error streaming bundle {}: {}", bundle_id, e);
+        }
+    });
+
+    HttpResponse::Ok()
+        .content_type("application/zip")
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename("bundle.zip".to_string())],
+        })
+        .streaming(throttle(ReaderStream::new(reader).boxed(), config.download_rate_limit))
+}
+
+/// Marks an unused bundle token as used, returning whether this call did.
+async fn claim_bundle(pool: &SqlitePool, bundle_id: &str) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query("UPDATE bundle_tokens SET used = 1 WHERE id = ? AND used = 0 AND revoked_at IS NULL")
+        .bind(bundle_id)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+async fn write_zip<W>(
+    writer: W,
+    storage: Arc<dyn StorageBackend>,
+    entries: Vec<BundleEntry>,
+) -> Result<(), async_zip::error::ZipError>
+where
+    W: AsyncWrite + Unpin,
+{
+    let mut zip = ZipFileWriter::with_tokio(writer);
+
+    for BundleEntry { name, size, recorder } in entries {
+        let body = storage
+            .read(&name, None)
+            .await
+            .map_err(std::io::Error::other)?;
+        let mut body = match recorder {
+            Some(recorder) => recorder.wrap(body, size).boxed(),
+            None => body,
+        };
+        let mut entry = zip
+            .write_entry_stream(ZipEntryBuilder::new(name.into(), Compression::Deflate))
+            .await?;
+        while let Some(chunk) = body.next().await {
+            entry.write_all(&chunk?).await?;
+        }
+        entry.close().await?;
+    }
+
+    zip.close().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+    use async_zip::base::read::mem::ZipFileReader;
+    use tokio::io::AsyncReadExt;
+
+    #[tokio::test]
+    async fn test_write_zip_round_trip() {
+        let storage = MemoryStorage::default();
+        storage.insert("first.txt", "hello").unwrap();
+        storage.insert("nested/second.txt", "world").unwrap();
+
+        let (mut reader, writer) = tokio::io::duplex(16);
+        let entries = ["first.txt", "nested/second.txt"]
+            .map(|name| BundleEntry {
+                name: name.to_string(),
+                size: 5,
+                recorder: None,
+            })
+            .into();
+        let producer = tokio::spawn(write_zip(writer, Arc::new(storage), entries));
+
+        let mut archive = Vec::new();
+        reader.read_to_end(&mut archive).await.unwrap();
+        producer.await.unwrap().unwrap();
+
+        let zip = ZipFileReader::new(archive).await.unwrap();
+        let names: Vec<&str> = zip
+            .file()
+            .entries()
+            .iter()
+            .map(|e| e.filename().as_str().unwrap())
+            .collect();
+        assert_eq!(names, ["first.txt", "nested/second.txt"]);
+
+        let mut contents = String::new();
+        zip.reader_with_entry(1)
+            .await
+            .unwrap()
+            .read_to_string_checked(&mut contents)
+            .await
+            .unwrap();
+        assert_eq!(contents, "world");
+    }
+
+    #[actix_web::test]
+    async fn test_bundle_downloads_count_toward_quotas() {
+        use crate::quotas::QuotaLimits;
+        use actix_web::{test, App};
+
+        let pool = crate::db::test_pool().await;
+        sqlx::query(
+            r#"
+            INSERT INTO users (id, username, password_hash) VALUES ('u', 'alice', 'x');
+            INSERT INTO download_files (id, file_path, display_name) VALUES ('f1', 'a.txt', 'A'), ('f2', 'b.txt', 'B');
+            INSERT INTO bundle_tokens (id, token, user_id) VALUES ('b1', 't1', 'u'), ('b2', 't2', 'u');
+            INSERT INTO bundle_files (bundle_id, file_id) VALUES ('b1', 'f1'), ('b1', 'f2'), ('b2', 'f1');
+            "#,
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        let storage = MemoryStorage::default();
+        storage.insert("a.txt", "hello").unwrap();
+        storage.insert("b.txt", "world").unwrap();
+        let storage: Arc<dyn StorageBackend> = Arc::new(storage);
+        let config = Config {
+            user_quota: QuotaLimits {
+                bytes_per_day: Some(10),
+                downloads_per_hour: None,
+            },
+            ..Default::default()
+        };
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool.clone()))
+                .app_data(web::Data::from(storage))
+                .app_data(web::Data::new(config))
+                .route("/downloads/bundle/{token}", web::get().to(download_bundle)),
+        )
+        .await;
+
+        let response = test::call_service(&app, test::TestRequest::get().uri("/downloads/bundle/t1").to_request()).await;
+        assert_eq!(response.status(), 200);
+        test::read_body(response).await;
+
+        // Each file's download is recorded once its bytes have been sent
+        let mut recorded = Vec::new();
+        for _ in 0..50 {
+            recorded = sqlx::query_as::<_, (String, i64)>(
+                "SELECT file_id, bytes_sent FROM download_events WHERE user_id = 'u' ORDER BY file_id",
+            )
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+            if recorded.len() == 2 {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        assert_eq!(recorded, [("f1".to_string(), 5), ("f2".to_string(), 5)]);
+
+        let response = test::call_service(&app, test::TestRequest::get().uri("/downloads/bundle/t2").to_request()).await;
+        assert_eq!(response.status(), 429);
+    }
+
+    #[actix_web::test]
+    async fn test_bundle_tokens_are_claimed_once_and_expire() {
+        use actix_web::{test, App};
+
+        let pool = crate::db::test_pool().await;
+        sqlx::query(
+            r#"
+            INSERT INTO users (id, username, password_hash) VALUES ('u', 'alice', 'x');
+            INSERT INTO bundle_tokens (id, token, user_id, expires_at) VALUES
+                ('b1', 't1', 'u', NULL),
+                ('b2', 't2', 'u', datetime('now', '-1 hour'));
+            "#,
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        assert!(claim_bundle(&pool, "b1").await.unwrap());
+        assert!(!claim_bundle(&pool, "b1").await.unwrap());
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool.clone()))
+                .app_data(web::Data::from(Arc::new(MemoryStorage::default()) as Arc<dyn StorageBackend>))
+                .app_data(web::Data::new(Config::default()))
+                .route("/downloads/bundle/{token}", web::get().to(download_bundle)),
+        )
+        .await;
+        let get = |token: &str| test::TestRequest::get().uri(&format!("/downloads/bundle/{}", token)).to_request();
+        // Already claimed, and past its expiry
+        assert_eq!(test::call_service(&app, get("t1")).await.status(), 410);
+        assert_eq!(test::call_service(&app, get("t2")).await.status(), 404);
+    }
+}
@@ -1,5 +1,14 @@
 use thiserror::Error;
 
+use crate::attachments::AttachmentLimits;
+use crate::auto_reply::AutoReplyConfig;
+use crate::mail::{parse_address_list, ContactRouting, FileFormat, Recipients, SmtpConfig, SmtpSecurity};
+use crate::notify::{Event, LoginAlertPolicy, NotifyConfig, WebhookTarget};
+use crate::outbox::RetryPolicy;
+use crate::quotas::QuotaLimits;
+use crate::spam::SpamConfig;
+use crate::storage::S3Config;
+
 #[derive(Error, Debug)]
 pub enum ConfigError {
     #[error("Missing environment variable: {0}")]
@@ -8,11 +17,82 @@ pub enum ConfigError {
     InvalidEnvVar(String),
 }
 
+const DEFAULT_DOWNLOADS_DIR: &str = "../downloads";
+const DEFAULT_ANALYTICS_RETENTION_DAYS: u32 = 90;
+const DEFAULT_PREVIEW_CACHE_DIR: &str = "../previews";
+const DEFAULT_MAIL_FILE_DIR: &str = "../mail";
+const DEFAULT_IDEMPOTENCY_WINDOW_HOURS: u32 = 24;
+
+/// Where download files are read from (`STORAGE_BACKEND`).
+#[derive(Clone, Debug, Default)]
+pub enum StorageKind {
+    #[default]
+    Local,
+    Memory,
+    S3(S3Config),
+}
+
+/// How outgoing email is delivered (`MAIL_TRANSPORT`).
+#[derive(Clone, Debug, Default)]
+pub enum MailKind {
+    #[default]
+    Resend,
+    Smtp(SmtpConfig),
+    File(FileFormat),
+    Memory,
+}
+
 #[derive(Clone, Default)]
 #[allow(dead_code)]
 pub struct Config {
     pub node_env: Option<String>,
     pub mail_api_key: Option<String>,
+    pub mail_kind: MailKind,
+    /// Directory the file mail transport writes to (`MAIL_FILE_DIR`)
+    pub mail_file_dir: Option<String>,
+    /// Sender and recipients of contact mail (`MAIL_FROM`, `CONTACT_*`)
+    pub contact: ContactRouting,
+    /// Files visitors may attach to the contact form (`CONTACT_MAX_ATTACHMENTS`, ...)
+    pub attachments: AttachmentLimits,
+    /// Acknowledgements to contact form senders (`AUTO_REPLY_ENABLED`, `AUTO_REPLY_WINDOW_HOURS`)
+    pub auto_reply: AutoReplyConfig,
+    /// Directory of email templates overriding the built-in ones (`MAIL_TEMPLATE_DIR`)
+    pub mail_template_dir: Option<String>,
+    /// Signing secret of the provider's delivery webhooks (`MAIL_WEBHOOK_SECRET`); endpoint off when unset
+    pub mail_webhook_secret: Option<String>,
+    /// Outbox delivery attempts (`MAIL_MAX_ATTEMPTS`) and first retry delay (`MAIL_RETRY_BASE_SECONDS`)
+    pub mail_retry: RetryPolicy,
+    /// Revoke unused download tokens when a release is yanked (`YANK_REVOKES_TOKENS`)
+    pub yank_revokes_tokens: bool,
+    pub storage_kind: StorageKind,
+    /// Root of the local storage backend (`DOWNLOADS_DIR`)
+    pub downloads_dir: Option<String>,
+    /// Salt for hashing client IPs (`IP_HASH_SALT`); random per process when unset
+    pub ip_hash_salt: Option<String>,
+    /// Take the client IP from `Forwarded`/`X-Forwarded-For` (`TRUST_PROXY_HEADERS`)
+    pub trust_proxy_headers: bool,
+    /// Days raw download events are kept (`ANALYTICS_RETENTION_DAYS`)
+    pub analytics_retention_days: Option<u32>,
+    /// Per-user limits (`QUOTA_USER_BYTES_PER_DAY`, `QUOTA_USER_DOWNLOADS_PER_HOUR`)
+    pub user_quota: QuotaLimits,
+    /// Per-client-IP limits (`QUOTA_IP_BYTES_PER_DAY`, `QUOTA_IP_DOWNLOADS_PER_HOUR`)
+    pub ip_quota: QuotaLimits,
+    /// Bandwidth cap for a single transfer in bytes per second (`DOWNLOAD_RATE_LIMIT`)
+    pub download_rate_limit: Option<u64>,
+    /// Directory generated previews are cached in (`PREVIEW_CACHE_DIR`)
+    pub preview_cache_dir: Option<String>,
+    /// Content-addressed blob store for download files (`BLOB_STORE_DIR`); off when unset
+    pub blob_store_dir: Option<String>,
+    /// Hours a download token stays valid (`DOWNLOAD_TOKEN_TTL_HOURS`); no expiry when unset
+    pub download_token_ttl_hours: Option<u32>,
+    /// Absolute URL of the site used in feeds (`PUBLIC_BASE_URL`); feeds are disabled when unset
+    pub public_base_url: Option<String>,
+    /// Contact form spam checks (`FORM_SECRET`, `SPAM_*`)
+    pub spam: SpamConfig,
+    /// Chat webhooks notified of events (`NOTIFY_*`)
+    pub notifications: NotifyConfig,
+    /// Hours responses to `Idempotency-Key` requests are replayed (`IDEMPOTENCY_WINDOW_HOURS`)
+    pub idempotency_window_hours: Option<u32>,
 }
 
 impl Config {
@@ -20,18 +100,229 @@ impl Config {
         // Load .env file if present
         dotenvy::dotenv().ok();
 
-        let node_env = get_env_var("NODE_ENV")?;
-        let mail_api_key = get_env_var("MAIL_API_KEY")?;
+        let node_env = get_optional_env_var("NODE_ENV")?;
+        let mail_api_key = get_optional_env_var("MAIL_API_KEY")?;
+        let mail_file_dir = get_optional_env_var("MAIL_FILE_DIR")?;
+        let mail_template_dir = get_optional_env_var("MAIL_TEMPLATE_DIR")?;
+        let mail_webhook_secret = get_optional_env_var("MAIL_WEBHOOK_SECRET")?;
+        let default_contact = ContactRouting::default();
+        let mut contact = ContactRouting {
+            from: get_optional_env_var("MAIL_FROM")?.unwrap_or(default_contact.from),
+            default: get_recipients("")?.unwrap_or(default_contact.default),
+            topics: Default::default(),
+        };
+        let default_attachments = AttachmentLimits::default();
+        let attachments = AttachmentLimits {
+            max_count: get_env_number("CONTACT_MAX_ATTACHMENTS")?.unwrap_or(default_attachments.max_count),
+            max_bytes: get_env_number("CONTACT_MAX_ATTACHMENT_BYTES")?.unwrap_or(default_attachments.max_bytes),
+            allowed_types: get_optional_env_var("CONTACT_ATTACHMENT_TYPES")?
+                .map(|list| {
+                    list.split(',')
+                        .map(|mime| mime.trim().to_lowercase())
+                        .filter(|mime| !mime.is_empty())
+                        .collect()
+                })
+                .unwrap_or(default_attachments.allowed_types),
+        };
+        let default_auto_reply = AutoReplyConfig::default();
+        let auto_reply = AutoReplyConfig {
+            enabled: get_env_flag("AUTO_REPLY_ENABLED")?,
+            window: get_env_number::<u64>("AUTO_REPLY_WINDOW_HOURS")?
+                .map(|hours| std::time::Duration::from_secs(hours * 60 * 60))
+                .unwrap_or(default_auto_reply.window),
+            max_per_ip_hour: get_env_number("AUTO_REPLY_MAX_PER_IP_HOUR")?
+                .unwrap_or(default_auto_reply.max_per_ip_hour),
+            max_per_hour: get_env_number("AUTO_REPLY_MAX_PER_HOUR")?.unwrap_or(default_auto_reply.max_per_hour),
+        };
+        for topic in get_optional_env_var("CONTACT_TOPICS")?.iter().flat_map(|list| list.split(',')) {
+            let topic = topic.trim().to_lowercase();
+            if topic.is_empty() {
+                continue;
+            }
+            let suffix = format!("_{}", topic.to_uppercase().replace('-', "_"));
+            let recipients = get_recipients(&suffix)?.ok_or_else(|| {
+                ConfigError::InvalidEnvVar(format!("CONTACT_TO{} must be set for topic {}", suffix, topic))
+            })?;
+            contact.topics.insert(topic, recipients);
+        }
+        let default_retry = RetryPolicy::default();
+        let mail_retry = RetryPolicy {
+            max_attempts: get_env_number("MAIL_MAX_ATTEMPTS")?.unwrap_or(default_retry.max_attempts),
+            base_delay: get_env_number("MAIL_RETRY_BASE_SECONDS")?
+                .map(std::time::Duration::from_secs)
+                .unwrap_or(default_retry.base_delay),
+        };
+        let yank_revokes_tokens = get_env_flag("YANK_REVOKES_TOKENS")?;
+        let downloads_dir = get_optional_env_var("DOWNLOADS_DIR")?;
+        let ip_hash_salt = get_optional_env_var("IP_HASH_SALT")?;
+        let trust_proxy_headers = get_env_flag("TRUST_PROXY_HEADERS")?;
+        let analytics_retention_days = get_env_number("ANALYTICS_RETENTION_DAYS")?;
+        let user_quota = QuotaLimits {
+            bytes_per_day: get_env_number("QUOTA_USER_BYTES_PER_DAY")?,
+            downloads_per_hour: get_env_number("QUOTA_USER_DOWNLOADS_PER_HOUR")?,
+        };
+        let ip_quota = QuotaLimits {
+            bytes_per_day: get_env_number("QUOTA_IP_BYTES_PER_DAY")?,
+            downloads_per_hour: get_env_number("QUOTA_IP_DOWNLOADS_PER_HOUR")?,
+        };
+        let download_rate_limit = get_env_number("DOWNLOAD_RATE_LIMIT")?;
+        let preview_cache_dir = get_optional_env_var("PREVIEW_CACHE_DIR")?;
+        let blob_store_dir = get_optional_env_var("BLOB_STORE_DIR")?;
+        let download_token_ttl_hours = get_env_number("DOWNLOAD_TOKEN_TTL_HOURS")?;
+        let public_base_url = get_optional_env_var("PUBLIC_BASE_URL")?;
+        let idempotency_window_hours = get_env_number("IDEMPOTENCY_WINDOW_HOURS")?;
+        let default_spam = SpamConfig::default();
+        let spam = SpamConfig {
+            secret: get_optional_env_var("FORM_SECRET")?,
+            require_challenge: get_env_flag("SPAM_REQUIRE_CHALLENGE")?,
+            min_submit_time: get_env_number("SPAM_MIN_SUBMIT_SECONDS")?
+                .map(std::time::Duration::from_secs)
+                .unwrap_or(default_spam.min_submit_time),
+            pow_difficulty: get_env_number("SPAM_POW_DIFFICULTY")?.unwrap_or(default_spam.pow_difficulty),
+            keywords: get_optional_env_var("SPAM_KEYWORDS")?
+                .map(|list| {
+                    list.split(',')
+                        .map(|keyword| keyword.trim().to_lowercase())
+                        .filter(|keyword| !keyword.is_empty())
+                        .collect()
+                })
+                .unwrap_or_default(),
+            score_threshold: get_env_number("SPAM_SCORE_THRESHOLD")?.unwrap_or(default_spam.score_threshold),
+        };
+        let default_notifications = NotifyConfig::default();
+        let mut notifications = NotifyConfig {
+            webhooks: Vec::new(),
+            retry: RetryPolicy {
+                max_attempts: get_env_number("NOTIFY_MAX_ATTEMPTS")?
+                    .unwrap_or(default_notifications.retry.max_attempts),
+                base_delay: get_env_number("NOTIFY_RETRY_BASE_SECONDS")?
+                    .map(std::time::Duration::from_secs)
+                    .unwrap_or(default_notifications.retry.base_delay),
+            },
+            failed_logins: LoginAlertPolicy {
+                threshold: get_env_number("NOTIFY_FAILED_LOGIN_THRESHOLD")?
+                    .unwrap_or(default_notifications.failed_logins.threshold),
+                window: get_env_number::<u64>("NOTIFY_FAILED_LOGIN_WINDOW_MINUTES")?
+                    .map(|minutes| std::time::Duration::from_secs(minutes * 60))
+                    .unwrap_or(default_notifications.failed_logins.window),
+            },
+        };
+        for name in get_optional_env_var("NOTIFY_WEBHOOKS")?.iter().flat_map(|list| list.split(',')) {
+            let name = name.trim().to_lowercase();
+            if name.is_empty() {
+                continue;
+            }
+            notifications.webhooks.push(get_webhook_target(&name)?);
+        }
+
+        let storage_kind = match get_optional_env_var("STORAGE_BACKEND")?.as_deref() {
+            None | Some("local") => StorageKind::Local,
+            Some("memory") => StorageKind::Memory,
+            Some("s3") => StorageKind::S3(S3Config {
+                endpoint: get_env_var("S3_ENDPOINT")?,
+                bucket: get_env_var("S3_BUCKET")?,
+                region: get_optional_env_var("S3_REGION")?.unwrap_or_else(|| "us-east-1".into()),
+                access_key_id: get_env_var("S3_ACCESS_KEY_ID")?,
+                secret_access_key: get_env_var("S3_SECRET_ACCESS_KEY")?,
+                prefix: get_optional_env_var("S3_PREFIX")?,
+            }),
+            Some(other) => {
+                return Err(ConfigError::InvalidEnvVar(format!(
+                    "STORAGE_BACKEND must be one of local, memory, s3 (got {})",
+                    other
+                )))
+            }
+        };
+
+        let mail_kind = match get_optional_env_var("MAIL_TRANSPORT")?.as_deref() {
+            None | Some("resend") => MailKind::Resend,
+            Some("smtp") => MailKind::Smtp(SmtpConfig {
+                host: get_env_var("SMTP_HOST")?,
+                port: get_env_number("SMTP_PORT")?,
+                username: get_optional_env_var("SMTP_USERNAME")?,
+                password: get_optional_env_var("SMTP_PASSWORD")?,
+                security: match get_optional_env_var("SMTP_SECURITY")?.as_deref() {
+                    None | Some("starttls") => SmtpSecurity::StartTls,
+                    Some("tls") => SmtpSecurity::Tls,
+                    Some("none") => SmtpSecurity::None,
+                    Some(other) => {
+                        return Err(ConfigError::InvalidEnvVar(format!(
+                            "SMTP_SECURITY must be one of starttls, tls, none (got {})",
+                            other
+                        )))
+                    }
+                },
+            }),
+            Some("file") => MailKind::File(FileFormat::Eml),
+            Some("maildir") => MailKind::File(FileFormat::Maildir),
+            Some("memory") => MailKind::Memory,
+            Some(other) => {
+                return Err(ConfigError::InvalidEnvVar(format!(
+                    "MAIL_TRANSPORT must be one of resend, smtp, file, maildir, memory (got {})",
+                    other
+                )))
+            }
+        };
 
         Ok(Self {
-            node_env: Some(node_env),
-            mail_api_key: Some(mail_api_key),
+            node_env,
+            mail_api_key,
+            mail_kind,
+            mail_file_dir,
+            contact,
+            attachments,
+            auto_reply,
+            mail_template_dir,
+            mail_webhook_secret,
+            mail_retry,
+            yank_revokes_tokens,
+            storage_kind,
+            downloads_dir,
+            ip_hash_salt,
+            trust_proxy_headers,
+            analytics_retention_days,
+            user_quota,
+            ip_quota,
+            download_rate_limit,
+            preview_cache_dir,
+            blob_store_dir,
+            download_token_ttl_hours,
+            public_base_url,
+            spam,
+            notifications,
+            idempotency_window_hours,
         })
     }
 
     pub fn mail_api_key(&self) -> Option<&str> {
         self.mail_api_key.as_deref()
     }
+
+    /// Whether outgoing mail can be delivered; Resend needs an API key.
+    pub fn mail_configured(&self) -> bool {
+        !matches!(self.mail_kind, MailKind::Resend) || self.mail_api_key().is_some()
+    }
+
+    pub fn mail_file_dir(&self) -> &str {
+        self.mail_file_dir.as_deref().unwrap_or(DEFAULT_MAIL_FILE_DIR)
+    }
+
+    pub fn downloads_dir(&self) -> &str {
+        self.downloads_dir.as_deref().unwrap_or(DEFAULT_DOWNLOADS_DIR)
+    }
+
+    pub fn analytics_retention_days(&self) -> u32 {
+        self.analytics_retention_days.unwrap_or(DEFAULT_ANALYTICS_RETENTION_DAYS)
+    }
+
+    pub fn preview_cache_dir(&self) -> &str {
+        self.preview_cache_dir.as_deref().unwrap_or(DEFAULT_PREVIEW_CACHE_DIR)
+    }
+
+    pub fn idempotency_window(&self) -> std::time::Duration {
+        let hours = self.idempotency_window_hours.unwrap_or(DEFAULT_IDEMPOTENCY_WINDOW_HOURS);
+        std::time::Duration::from_secs(u64::from(hours) * 60 * 60)
+    }
 }
 
 fn get_env_var(name: &str) -> Result<String, ConfigError> {
@@ -46,3 +337,88 @@ fn get_env_var(name: &str) -> Result<String, ConfigError> {
 
     Ok(value)
 }
+
+fn get_optional_env_var(name: &str) -> Result<Option<String>, ConfigError> {
+    match get_env_var(name) {
+        Ok(value) => Ok(Some(value)),
+        Err(ConfigError::MissingEnvVar(_)) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+fn get_env_number<T: std::str::FromStr>(name: &str) -> Result<Option<T>, ConfigError> {
+    match get_optional_env_var(name)? {
+        None => Ok(None),
+        Some(value) => value.parse().map(Some).map_err(|_| {
+            ConfigError::InvalidEnvVar(format!("{} must be a number", name))
+        }),
+    }
+}
+
+fn get_address_list(name: &str) -> Result<Vec<String>, ConfigError> {
+    match get_optional_env_var(name)? {
+        None => Ok(Vec::new()),
+        Some(list) => parse_address_list(&list)
+            .map_err(|e| ConfigError::InvalidEnvVar(format!("{} has an invalid address ({})", name, e))),
+    }
+}
+
+/// `CONTACT_TO{suffix}`, `CONTACT_CC{suffix}` and `CONTACT_BCC{suffix}`, if
+/// any `To` recipients are set.
+fn get_recipients(suffix: &str) -> Result<Option<Recipients>, ConfigError> {
+    let to = get_address_list(&format!("CONTACT_TO{}", suffix))?;
+    if to.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(Recipients {
+        to,
+        cc: get_address_list(&format!("CONTACT_CC{}", suffix))?,
+        bcc: get_address_list(&format!("CONTACT_BCC{}", suffix))?,
+    }))
+}
+
+/// `NOTIFY_WEBHOOK_URL_<NAME>`, `NOTIFY_WEBHOOK_FORMAT_<NAME>` (default
+/// slack) and `NOTIFY_WEBHOOK_EVENTS_<NAME>` (default all events).
+fn get_webhook_target(name: &str) -> Result<WebhookTarget, ConfigError> {
+    let suffix = name.to_uppercase().replace('-', "_");
+    let format_var = format!("NOTIFY_WEBHOOK_FORMAT_{}", suffix);
+    let events_var = format!("NOTIFY_WEBHOOK_EVENTS_{}", suffix);
+    let format = match get_optional_env_var(&format_var)? {
+        None => Default::default(),
+        Some(format) => format
+            .trim()
+            .to_lowercase()
+            .parse()
+            .map_err(|e| ConfigError::InvalidEnvVar(format!("{}: {}", format_var, e)))?,
+    };
+    let events = match get_optional_env_var(&events_var)? {
+        None => Event::ALL.to_vec(),
+        Some(list) => list
+            .split(',')
+            .map(|event| event.trim().to_lowercase())
+            .filter(|event| !event.is_empty())
+            .map(|event| event.parse())
+            .collect::<Result<_, _>>()
+            .map_err(|e| ConfigError::InvalidEnvVar(format!("{}: {}", events_var, e)))?,
+    };
+    Ok(WebhookTarget {
+        name: name.to_string(),
+        url: get_env_var(&format!("NOTIFY_WEBHOOK_URL_{}", suffix))?,
+        format,
+        events,
+    })
+}
+
+fn get_env_flag(name: &str) -> Result<bool, ConfigError> {
+    match std::env::var(name) {
+        Err(_) => Ok(false),
+        Ok(value) => match value.to_ascii_lowercase().as_str() {
+            "" | "0" | "false" | "no" => Ok(false),
+            "1" | "true" | "yes" => Ok(true),
+            _ => Err(ConfigError::InvalidEnvVar(format!(
+                "{} must be a boolean",
+                name
+            ))),
+        },
+    }
+}
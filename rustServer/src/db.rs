@@ -21,6 +21,18 @@ pub async fn init_pool(database_url: &str) -> Result<SqlitePool, sqlx::Error> {
     Ok(pool)
 }
 
+/// Fresh in-memory database with the full schema, for tests.
+#[cfg(test)]
+pub async fn test_pool() -> SqlitePool {
+    let pool = SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect("sqlite::memory:")
+        .await
+        .expect("in-memory database");
+    init_schema(&pool).await.expect("schema");
+    pool
+}
+
 async fn init_schema(pool: &SqlitePool) -> Result<(), sqlx::Error> {
     sqlx::query(
         r#"
@@ -67,5 +79,510 @@ async fn init_schema(pool: &SqlitePool) -> Result<(), sqlx::Error> {
     .execute(pool)
     .await?;
 
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS releases (
+            id TEXT PRIMARY KEY,
+            project TEXT NOT NULL,
+            version TEXT NOT NULL,
+            notes TEXT,
+            is_prerelease INTEGER NOT NULL DEFAULT 0,
+            published_at TEXT NOT NULL DEFAULT (datetime('now')),
+            yanked_at TEXT,
+            created_at TEXT NOT NULL DEFAULT (datetime('now')),
+            UNIQUE (project, version)
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS bundle_tokens (
+            id TEXT PRIMARY KEY,
+            token TEXT UNIQUE NOT NULL,
+            user_id TEXT NOT NULL,
+            used INTEGER NOT NULL DEFAULT 0,
+            revoked_at TEXT,
+            created_at TEXT NOT NULL DEFAULT (datetime('now')),
+            FOREIGN KEY (user_id) REFERENCES users(id)
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS bundle_files (
+            bundle_id TEXT NOT NULL,
+            file_id TEXT NOT NULL,
+            PRIMARY KEY (bundle_id, file_id),
+            FOREIGN KEY (bundle_id) REFERENCES bundle_tokens(id),
+            FOREIGN KEY (file_id) REFERENCES download_files(id)
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS download_events (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            file_id TEXT,
+            file_path TEXT NOT NULL,
+            user_id TEXT,
+            ip_hash TEXT,
+            bytes_sent INTEGER NOT NULL,
+            completed INTEGER NOT NULL,
+            created_at TEXT NOT NULL DEFAULT (datetime('now'))
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_download_events_created_at ON download_events (created_at)")
+        .execute(pool)
+        .await?;
+
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_download_events_user ON download_events (user_id, created_at)")
+        .execute(pool)
+        .await?;
+
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_download_events_ip ON download_events (ip_hash, created_at)")
+        .execute(pool)
+        .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS download_stats_daily (
+            file_path TEXT NOT NULL,
+            day TEXT NOT NULL,
+            downloads INTEGER NOT NULL DEFAULT 0,
+            completed INTEGER NOT NULL DEFAULT 0,
+            bytes_sent INTEGER NOT NULL DEFAULT 0,
+            PRIMARY KEY (file_path, day)
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS tags (
+            id TEXT PRIMARY KEY,
+            name TEXT UNIQUE NOT NULL,
+            created_at TEXT NOT NULL DEFAULT (datetime('now'))
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS file_tags (
+            file_id TEXT NOT NULL,
+            tag_id TEXT NOT NULL,
+            PRIMARY KEY (file_id, tag_id),
+            FOREIGN KEY (file_id) REFERENCES download_files(id),
+            FOREIGN KEY (tag_id) REFERENCES tags(id)
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS categories (
+            id TEXT PRIMARY KEY,
+            name TEXT NOT NULL,
+            slug TEXT UNIQUE NOT NULL,
+            parent_id TEXT REFERENCES categories(id),
+            created_at TEXT NOT NULL DEFAULT (datetime('now'))
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS share_links (
+            id TEXT PRIMARY KEY,
+            code TEXT UNIQUE NOT NULL,
+            file_id TEXT NOT NULL,
+            user_id TEXT NOT NULL,
+            expires_at TEXT NOT NULL,
+            password_hash TEXT,
+            max_downloads INTEGER,
+            download_count INTEGER NOT NULL DEFAULT 0,
+            revoked_at TEXT,
+            created_at TEXT NOT NULL DEFAULT (datetime('now')),
+            FOREIGN KEY (file_id) REFERENCES download_files(id),
+            FOREIGN KEY (user_id) REFERENCES users(id)
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS blobs (
+            hash TEXT PRIMARY KEY,
+            size_bytes INTEGER NOT NULL,
+            ref_count INTEGER NOT NULL DEFAULT 0,
+            created_at TEXT NOT NULL DEFAULT (datetime('now')),
+            updated_at TEXT NOT NULL DEFAULT (datetime('now'))
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS mail_outbox (
+            id TEXT PRIMARY KEY,
+            message TEXT NOT NULL,
+            status TEXT NOT NULL DEFAULT 'pending',
+            attempts INTEGER NOT NULL DEFAULT 0,
+            last_error TEXT,
+            next_attempt_at TEXT NOT NULL DEFAULT (datetime('now')),
+            sent_at TEXT,
+            created_at TEXT NOT NULL DEFAULT (datetime('now')),
+            updated_at TEXT NOT NULL DEFAULT (datetime('now'))
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_mail_outbox_due ON mail_outbox (status, next_attempt_at)")
+        .execute(pool)
+        .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS contact_quarantine (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            sender TEXT NOT NULL,
+            first_name TEXT NOT NULL,
+            last_name TEXT NOT NULL,
+            message TEXT NOT NULL,
+            reasons TEXT NOT NULL,
+            score INTEGER NOT NULL DEFAULT 0,
+            ip_hash TEXT NOT NULL,
+            created_at TEXT NOT NULL DEFAULT (datetime('now'))
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS contact_messages (
+            id TEXT PRIMARY KEY,
+            sender TEXT NOT NULL,
+            first_name TEXT NOT NULL,
+            last_name TEXT NOT NULL,
+            message TEXT NOT NULL,
+            ip_hash TEXT NOT NULL,
+            user_agent TEXT,
+            status TEXT NOT NULL DEFAULT 'new',
+            outbox_id TEXT REFERENCES mail_outbox(id) ON DELETE SET NULL,
+            read_at TEXT,
+            created_at TEXT NOT NULL DEFAULT (datetime('now')),
+            updated_at TEXT NOT NULL DEFAULT (datetime('now'))
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_contact_messages_status ON contact_messages (status, created_at)")
+        .execute(pool)
+        .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS mail_suppressions (
+            address TEXT PRIMARY KEY,
+            reason TEXT NOT NULL,
+            created_at TEXT NOT NULL DEFAULT (datetime('now'))
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS auto_reply_log (
+            address TEXT PRIMARY KEY,
+            sent_at TEXT NOT NULL DEFAULT (datetime('now'))
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    // Delivery webhook events already processed, by their svix-id
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS mail_webhook_events (
+            id TEXT PRIMARY KEY,
+            received_at TEXT NOT NULL DEFAULT (datetime('now'))
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    // One row per queued acknowledgement, for the hourly caps
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS auto_reply_sends (
+            ip_hash TEXT NOT NULL,
+            sent_at TEXT NOT NULL DEFAULT (datetime('now'))
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_auto_reply_sends_ip ON auto_reply_sends (ip_hash, sent_at)")
+        .execute(pool)
+        .await?;
+
+    // One row per wrong share link password, for throttling guesses
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS share_password_failures (
+            share_id TEXT NOT NULL,
+            ip_hash TEXT NOT NULL,
+            failed_at TEXT NOT NULL DEFAULT (datetime('now'))
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_share_password_failures_share ON share_password_failures (share_id, failed_at)")
+        .execute(pool)
+        .await?;
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_share_password_failures_ip ON share_password_failures (ip_hash, failed_at)")
+        .execute(pool)
+        .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS contact_form_nonces (
+            nonce TEXT PRIMARY KEY,
+            used_at TEXT NOT NULL DEFAULT (datetime('now'))
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    // Responses of requests sent with an Idempotency-Key; status is NULL
+    // while the first request is still being handled
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS idempotency_keys (
+            scope TEXT NOT NULL,
+            key TEXT NOT NULL,
+            fingerprint TEXT NOT NULL,
+            status INTEGER,
+            content_type TEXT,
+            body BLOB,
+            created_at TEXT NOT NULL DEFAULT (datetime('now')),
+            PRIMARY KEY (scope, key)
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_idempotency_keys_created_at ON idempotency_keys (created_at)")
+        .execute(pool)
+        .await?;
+
+    // Columns added after the initial schema
+    ensure_column(pool, "users", "is_admin", "INTEGER NOT NULL DEFAULT 0").await?;
+    ensure_column(pool, "users", "can_share", "INTEGER NOT NULL DEFAULT 0").await?;
+    ensure_column(pool, "users", "feed_token_hash", "TEXT").await?;
+    ensure_column(pool, "download_files", "release_id", "TEXT REFERENCES releases(id)").await?;
+    ensure_column(pool, "download_tokens", "revoked_at", "TEXT").await?;
+    ensure_column(pool, "download_tokens", "expires_at", "TEXT").await?;
+    ensure_column(pool, "bundle_tokens", "expires_at", "TEXT").await?;
+    ensure_column(pool, "download_files", "size_bytes", "INTEGER").await?;
+    ensure_column(pool, "download_files", "category_id", "TEXT REFERENCES categories(id)").await?;
+    ensure_column(pool, "download_files", "content_hash", "TEXT").await?;
+    ensure_column(pool, "download_files", "content_etag", "TEXT").await?;
+    ensure_column(pool, "download_files", "blob_hash", "TEXT REFERENCES blobs(hash)").await?;
+    ensure_column(pool, "contact_quarantine", "user_agent", "TEXT").await?;
+    ensure_column(pool, "contact_quarantine", "topic", "TEXT").await?;
+    ensure_column(pool, "contact_messages", "topic", "TEXT").await?;
+    ensure_column(pool, "mail_outbox", "provider_id", "TEXT").await?;
+    ensure_column(pool, "mail_outbox", "delivery_status", "TEXT").await?;
+    ensure_column(pool, "mail_outbox", "delivery_updated_at", "TEXT").await?;
+
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_mail_outbox_provider_id ON mail_outbox (provider_id)")
+        .execute(pool)
+        .await?;
+
+    init_search_index(pool).await?;
+    init_blob_refcounts(pool).await?;
+
+    Ok(())
+}
+
+/// Full-text index over file names and descriptions, kept in sync by triggers.
+/// Rows carry the file's `id` rather than sharing its `rowid`, which
+/// `download_files` does not keep stable (e.g. across `VACUUM`).
+async fn init_search_index(pool: &SqlitePool) -> Result<(), sqlx::Error> {
+    let schema = sqlx::query_scalar::<_, String>(
+        "SELECT sql FROM sqlite_master WHERE type = 'table' AND name = 'download_files_fts'",
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    match schema {
+        Some(sql) if sql.contains("file_id UNINDEXED") => return Ok(()),
+        // Replace the earlier index keyed on download_files.rowid
+        Some(_) => {
+            for trigger in ["download_files_fts_insert", "download_files_fts_delete", "download_files_fts_update"] {
+                sqlx::query(&format!("DROP TRIGGER IF EXISTS {}", trigger)).execute(pool).await?;
+            }
+            sqlx::query("DROP TABLE download_files_fts").execute(pool).await?;
+        }
+        None => {}
+    }
+
+    sqlx::query(
+        r#"
+        CREATE VIRTUAL TABLE download_files_fts USING fts5(
+            file_id UNINDEXED,
+            display_name,
+            description
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TRIGGER download_files_fts_insert AFTER INSERT ON download_files BEGIN
+            INSERT INTO download_files_fts (file_id, display_name, description)
+            VALUES (new.id, new.display_name, new.description);
+        END
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TRIGGER download_files_fts_delete AFTER DELETE ON download_files BEGIN
+            DELETE FROM download_files_fts WHERE file_id = old.id;
+        END
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TRIGGER download_files_fts_update AFTER UPDATE OF id, display_name, description ON download_files BEGIN
+            DELETE FROM download_files_fts WHERE file_id = old.id;
+            INSERT INTO download_files_fts (file_id, display_name, description)
+            VALUES (new.id, new.display_name, new.description);
+        END
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    // Index rows that existed before the search index
+    sqlx::query(
+        "INSERT INTO download_files_fts (file_id, display_name, description) SELECT id, display_name, description FROM download_files",
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Triggers keeping `blobs.ref_count` equal to the number of download files
+/// pointing at each blob. `updated_at` marks the last change, so garbage
+/// collection can tell how long a blob has been unreferenced.
+async fn init_blob_refcounts(pool: &SqlitePool) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        CREATE TRIGGER IF NOT EXISTS download_files_blob_insert
+        AFTER INSERT ON download_files WHEN new.blob_hash IS NOT NULL BEGIN
+            UPDATE blobs SET ref_count = ref_count + 1, updated_at = datetime('now') WHERE hash = new.blob_hash;
+        END
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TRIGGER IF NOT EXISTS download_files_blob_delete
+        AFTER DELETE ON download_files WHEN old.blob_hash IS NOT NULL BEGIN
+            UPDATE blobs SET ref_count = ref_count - 1, updated_at = datetime('now') WHERE hash = old.blob_hash;
+        END
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TRIGGER IF NOT EXISTS download_files_blob_update
+        AFTER UPDATE OF blob_hash ON download_files WHEN old.blob_hash IS NOT new.blob_hash BEGIN
+            UPDATE blobs SET ref_count = ref_count - 1, updated_at = datetime('now') WHERE hash = old.blob_hash;
+            UPDATE blobs SET ref_count = ref_count + 1, updated_at = datetime('now') WHERE hash = new.blob_hash;
+        END
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Adds a column to an existing table unless it is already present.
+async fn ensure_column(
+    pool: &SqlitePool,
+    table: &str,
+    column: &str,
+    definition: &str,
+) -> Result<(), sqlx::Error> {
+    let exists = sqlx::query_scalar::<_, i32>("SELECT 1 FROM pragma_table_info(?) WHERE name = ?")
+        .bind(table)
+        .bind(column)
+        .fetch_optional(pool)
+        .await?
+        .is_some();
+
+    if !exists {
+        sqlx::query(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition))
+            .execute(pool)
+            .await?;
+    }
+
     Ok(())
 }
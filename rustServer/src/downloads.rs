@@ -1,14 +1,27 @@
-use actix_files::NamedFile;
+use actix_files::HttpRange;
 use actix_session::Session;
+use actix_web::http::header::{
+    self, ContentDisposition, DispositionParam, DispositionType, HttpDate, LastModified,
+};
 use actix_web::{web, HttpRequest, HttpResponse, Result};
+use chrono::DateTime;
+use futures_util::StreamExt;
 use serde::{Deserialize, Serialize};
 use sqlx::SqlitePool;
-use std::path::{Path, PathBuf};
+use std::path::Path;
+use std::time::SystemTime;
 use uuid::Uuid;
 
+use crate::analytics::{client_ip_hash, DownloadRecorder};
 use crate::auth::get_user_id;
+use crate::config::Config;
+use crate::file_search::{query_files, ListFilesQuery, SearchError};
+use crate::previews::preview_url;
+use crate::quotas::{check_quota, throttle};
+use crate::storage::{ByteRange, ObjectMeta, StorageBackend, StorageError};
 
-const DOWNLOADS_DIR: &str = "../downloads";
+/// Extensions of detached signature files looked up beside a download, in order of preference.
+const SIGNATURE_EXTENSIONS: [&str; 2] = ["minisig", "sig"];
 
 #[derive(Serialize)]
 pub struct DownloadFile {
@@ -17,12 +30,24 @@ pub struct DownloadFile {
     pub display_name: String,
     pub description: Option<String>,
     pub is_protected: bool,
+    pub size: Option<i64>,
+    pub has_signature: bool,
+    pub signature_url: Option<String>,
+    pub preview_url: Option<String>,
 }
 
 #[derive(Serialize)]
 pub struct DownloadToken {
     pub token: String,
     pub download_url: String,
+    pub signature_url: Option<String>,
+}
+
+/// Response envelope for `GET /api/files`.
+#[derive(Serialize)]
+pub struct FileList {
+    pub items: Vec<DownloadFile>,
+    pub next_cursor: Option<String>,
 }
 
 #[derive(Deserialize)]
@@ -30,39 +55,60 @@ pub struct GenerateTokenRequest {
     pub file_id: String,
 }
 
-pub async fn list_files(pool: web::Data<SqlitePool>, session: Session) -> HttpResponse {
-    let is_authenticated = get_user_id(&session).is_some();
+/// Row shape shared by every query that builds a [`DownloadFile`].
+pub type FileRow = (String, String, String, Option<String>, i32, Option<i64>);
+
+/// SQL condition excluding files attached to a yanked release.
+pub const NOT_YANKED: &str =
+    "(release_id IS NULL OR release_id NOT IN (SELECT id FROM releases WHERE yanked_at IS NOT NULL))";
+
+impl DownloadFile {
+    pub async fn from_row(
+        storage: &dyn StorageBackend,
+        (id, file_path, display_name, description, is_protected, size): FileRow,
+    ) -> Self {
+        let is_protected = is_protected != 0;
+        let has_signature = find_signature(storage, &file_path).await.is_some();
+        // Protected signatures are only reachable through a download token
+        let signature_url = (has_signature && !is_protected)
+            .then(|| format!("/downloads/signature/{}", file_path));
+        let preview_url = preview_url(&id, &file_path);
+        DownloadFile {
+            id,
+            file_path,
+            display_name,
+            description,
+            is_protected,
+            size,
+            has_signature,
+            signature_url,
+            preview_url,
+        }
+    }
+}
 
-    let files = if is_authenticated {
-        // Show all files for authenticated users
-        sqlx::query_as::<_, (String, String, String, Option<String>, i32)>(
-            "SELECT id, file_path, display_name, description, is_protected FROM download_files",
-        )
-        .fetch_all(pool.get_ref())
-        .await
-    } else {
-        // Show only public files for unauthenticated users
-        sqlx::query_as::<_, (String, String, String, Option<String>, i32)>(
-            "SELECT id, file_path, display_name, description, is_protected FROM download_files WHERE is_protected = 0",
-        )
-        .fetch_all(pool.get_ref())
-        .await
-    };
+/// Lists visible files, optionally searched, sorted and paginated (see [`ListFilesQuery`]).
+pub async fn list_files(
+    pool: web::Data<SqlitePool>,
+    storage: web::Data<dyn StorageBackend>,
+    session: Session,
+    query: web::Query<ListFilesQuery>,
+) -> HttpResponse {
+    // Protected files are only listed for authenticated users
+    let is_authenticated = get_user_id(&session).is_some();
 
-    match files {
-        Ok(rows) => {
-            let files: Vec<DownloadFile> = rows
-                .into_iter()
-                .map(|(id, file_path, display_name, description, is_protected)| DownloadFile {
-                    id,
-                    file_path,
-                    display_name,
-                    description,
-                    is_protected: is_protected != 0,
-                })
-                .collect();
-            HttpResponse::Ok().json(files)
+    match query_files(pool.get_ref(), &query, is_authenticated).await {
+        Ok(page) => {
+            let mut items = Vec::with_capacity(page.rows.len());
+            for row in page.rows {
+                items.push(DownloadFile::from_row(storage.get_ref(), row).await);
+            }
+            HttpResponse::Ok().json(FileList {
+                items,
+                next_cursor: page.next_cursor,
+            })
         }
+        Err(SearchError::InvalidCursor) => HttpResponse::BadRequest().body("Invalid cursor"),
         Err(e) => {
             tracing::error!("Database error listing files: {}", e);
             HttpResponse::InternalServerError().body("Error listing files")
@@ -70,9 +116,48 @@ pub async fn list_files(pool: web::Data<SqlitePool>, session: Session) -> HttpRe
     }
 }
 
+/// Fills in `size_bytes` for files whose size has not been recorded yet.
+pub async fn sync_file_sizes(pool: &SqlitePool, storage: &dyn StorageBackend) {
+    let files = sqlx::query_as::<_, (String, String)>(
+        "SELECT id, file_path FROM download_files WHERE size_bytes IS NULL",
+    )
+    .fetch_all(pool)
+    .await;
+
+    let files = match files {
+        Ok(files) => files,
+        Err(e) => {
+            tracing::error!("Database error reading file sizes: {}", e);
+            return;
+        }
+    };
+
+    for (id, file_path) in files {
+        let size = match storage.metadata(&file_path).await {
+            Ok(meta) => meta.size as i64,
+            Err(e) => {
+                tracing::debug!("Cannot size {}: {}", file_path, e);
+                continue;
+            }
+        };
+
+        if let Err(e) = sqlx::query("UPDATE download_files SET size_bytes = ? WHERE id = ?")
+            .bind(size)
+            .bind(&id)
+            .execute(pool)
+            .await
+        {
+            tracing::error!("Failed to record size of {}: {}", file_path, e);
+        }
+    }
+}
+
 pub async fn generate_token(
     pool: web::Data<SqlitePool>,
+    storage: web::Data<dyn StorageBackend>,
+    config: web::Data<Config>,
     session: Session,
+    req: HttpRequest,
     body: web::Json<GenerateTokenRequest>,
 ) -> HttpResponse {
     let user_id = match get_user_id(&session) {
@@ -80,28 +165,24 @@ pub async fn generate_token(
         None => return HttpResponse::Unauthorized().body("Authentication required"),
     };
 
-    // Verify file exists and is protected
-    let file = sqlx::query_as::<_, (String, i32)>(
-        "SELECT file_path, is_protected FROM download_files WHERE id = ?",
-    )
-    .bind(&body.file_id)
-    .fetch_optional(pool.get_ref())
-    .await;
+    let ip_hash = client_ip_hash(&req, &config);
+    if let Err(response) = check_quota(pool.get_ref(), &config, Some(&user_id), &ip_hash).await {
+        return response;
+    }
 
-    let (file_path, is_protected) = match file {
-        Ok(Some(f)) => f,
-        Ok(None) => return HttpResponse::NotFound().body("File not found"),
-        Err(e) => {
-            tracing::error!("Database error: {}", e);
-            return HttpResponse::InternalServerError().body("Database error");
-        }
+    let (file_path, is_protected) = match check_file_access(pool.get_ref(), &body.file_id).await {
+        Ok(file) => file,
+        Err(response) => return response,
     };
 
     // For public files, return direct download URL
-    if is_protected == 0 {
+    if !is_protected {
         return HttpResponse::Ok().json(DownloadToken {
             token: "".to_string(),
             download_url: format!("/downloads/public/{}", file_path),
+            signature_url: find_signature(storage.get_ref(), &file_path)
+                .await
+                .map(|_| format!("/downloads/signature/{}", file_path)),
         });
     }
 
@@ -109,13 +190,15 @@ pub async fn generate_token(
     let token = Uuid::new_v4().to_string();
     let token_id = Uuid::new_v4().to_string();
 
+    let expires_at = config.download_token_ttl_hours.map(|hours| format!("+{} hours", hours));
     let result = sqlx::query(
-        "INSERT INTO download_tokens (id, token, file_id, user_id) VALUES (?, ?, ?, ?)",
+        "INSERT INTO download_tokens (id, token, file_id, user_id, expires_at) VALUES (?, ?, ?, ?, datetime('now', ?))",
     )
     .bind(&token_id)
     .bind(&token)
     .bind(&body.file_id)
     .bind(&user_id)
+    .bind(expires_at)
     .execute(pool.get_ref())
     .await;
 
@@ -123,6 +206,9 @@ pub async fn generate_token(
         Ok(_) => HttpResponse::Ok().json(DownloadToken {
             token: token.clone(),
             download_url: format!("/downloads/token/{}", token),
+            signature_url: find_signature(storage.get_ref(), &file_path)
+                .await
+                .map(|_| format!("/downloads/token/{}/signature", token)),
         }),
         Err(e) => {
             tracing::error!("Failed to create download token: {}", e);
@@ -131,27 +217,55 @@ pub async fn generate_token(
     }
 }
 
+/// Looks up a downloadable file by id, returning its path and whether it is
+/// protected, or the response to send back when it cannot be downloaded.
+pub async fn check_file_access(
+    pool: &SqlitePool,
+    file_id: &str,
+) -> std::result::Result<(String, bool), HttpResponse> {
+    // Verify file exists, is not yanked, and whether it is protected
+    let file = sqlx::query_as::<_, (String, i32)>(&format!(
+        "SELECT file_path, is_protected FROM download_files WHERE id = ? AND {}",
+        NOT_YANKED
+    ))
+    .bind(file_id)
+    .fetch_optional(pool)
+    .await;
+
+    match file {
+        Ok(Some((file_path, is_protected))) => Ok((file_path, is_protected != 0)),
+        Ok(None) => Err(HttpResponse::NotFound().body("File not found")),
+        Err(e) => {
+            tracing::error!("Database error: {}", e);
+            Err(HttpResponse::InternalServerError().body("Database error"))
+        }
+    }
+}
+
 pub async fn download_by_token(
     pool: web::Data<SqlitePool>,
+    storage: web::Data<dyn StorageBackend>,
+    config: web::Data<Config>,
     req: HttpRequest,
     path: web::Path<String>,
 ) -> Result<HttpResponse> {
     let token = path.into_inner();
 
     // Find and validate token
-    let token_data = sqlx::query_as::<_, (String, String, i32)>(
+    let token_data = sqlx::query_as::<_, (String, String, String, String, i32)>(
         r#"
-        SELECT dt.id, df.file_path, dt.used 
+        SELECT dt.id, dt.file_id, dt.user_id, df.file_path, dt.used 
         FROM download_tokens dt
         JOIN download_files df ON dt.file_id = df.id
-        WHERE dt.token = ?
+        WHERE dt.token = ? AND dt.revoked_at IS NULL
+          AND (dt.expires_at IS NULL OR dt.expires_at > datetime('now'))
         "#,
     )
     .bind(&token)
     .fetch_optional(pool.get_ref())
     .await;
 
-    let (token_id, file_path, used) = match token_data {
+    let (token_id, file_id, user_id, file_path, used) = match token_data {
         Ok(Some(data)) => data,
         Ok(None) => return Ok(HttpResponse::NotFound().body("Invalid or expired token")),
         Err(e) => {
@@ -165,6 +279,12 @@ pub async fn download_by_token(
         return Ok(HttpResponse::Gone().body("Token has already been used"));
     }
 
+    // Check quotas before the token is consumed
+    let ip_hash = client_ip_hash(&req, &config);
+    if let Err(response) = check_quota(pool.get_ref(), &config, Some(&user_id), &ip_hash).await {
+        return Ok(response);
+    }
+
     // Mark token as used
     let _ = sqlx::query("UPDATE download_tokens SET used = 1 WHERE id = ?")
         .bind(&token_id)
@@ -172,115 +292,300 @@ pub async fn download_by_token(
         .await;
 
     // Serve the file
-    serve_file(&req, &file_path).await
+    let recorder = DownloadRecorder::new(
+        pool.get_ref().clone(),
+        Some(file_id),
+        &file_path,
+        Some(user_id),
+        ip_hash,
+    );
+    serve_file(&req, storage.get_ref(), &config, &file_path, Some(recorder)).await
 }
 
-pub async fn download_public(req: HttpRequest, path: web::Path<String>) -> Result<HttpResponse> {
-    let requested_path = path.into_inner();
-    serve_file(&req, &requested_path).await
-}
+/// Serves the detached signature of the file a token grants access to.
+/// Fetching the signature does not consume the token, but like the file it
+/// is refused once the token was used, so clients fetch it first.
+pub async fn signature_by_token(
+    pool: web::Data<SqlitePool>,
+    storage: web::Data<dyn StorageBackend>,
+    config: web::Data<Config>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse> {
+    let token = path.into_inner();
 
-async fn serve_file(req: &HttpRequest, requested_path: &str) -> Result<HttpResponse> {
-    // Security: Validate and sanitize the path
-    let safe_path = match sanitize_path(requested_path) {
-        Some(p) => p,
-        None => {
-            tracing::warn!("Invalid download path requested: {}", requested_path);
-            return Ok(HttpResponse::BadRequest().body("Invalid file path"));
+    let file_path = sqlx::query_as::<_, (String, i32)>(
+        r#"
+        SELECT df.file_path, dt.used
+        FROM download_tokens dt
+        JOIN download_files df ON dt.file_id = df.id
+        WHERE dt.token = ? AND dt.revoked_at IS NULL
+          AND (dt.expires_at IS NULL OR dt.expires_at > datetime('now'))
+        "#,
+    )
+    .bind(&token)
+    .fetch_optional(pool.get_ref())
+    .await;
+
+    // Valid exactly as long as the token would still download the file
+    let file_path = match file_path {
+        Ok(Some((_, used))) if used != 0 => return Ok(HttpResponse::Gone().body("Token has already been used")),
+        Ok(Some((p, _))) => p,
+        Ok(None) => return Ok(HttpResponse::NotFound().body("Invalid or expired token")),
+        Err(e) => {
+            tracing::error!("Database error: {}", e);
+            return Ok(HttpResponse::InternalServerError().body("Database error"));
         }
     };
 
-    let file_path = Path::new(DOWNLOADS_DIR).join(&safe_path);
+    serve_signature(&req, storage.get_ref(), &config, &file_path).await
+}
 
-    // Security: Ensure the resolved path is still within downloads directory
-    let canonical_downloads = match std::fs::canonicalize(DOWNLOADS_DIR) {
-        Ok(p) => p,
-        Err(_) => {
-            tracing::error!("Downloads directory not found");
-            return Ok(HttpResponse::InternalServerError().body("Downloads directory not configured"));
-        }
-    };
+pub async fn download_public(
+    pool: web::Data<SqlitePool>,
+    storage: web::Data<dyn StorageBackend>,
+    config: web::Data<Config>,
+    session: Session,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse> {
+    let requested_path = path.into_inner();
+    let file_path = requested_path.trim_start_matches('/');
+    let user_id = get_user_id(&session);
+    let ip_hash = client_ip_hash(&req, &config);
 
-    let canonical_file = match std::fs::canonicalize(&file_path) {
-        Ok(p) => p,
-        Err(_) => {
-            tracing::debug!("File not found: {:?}", file_path);
-            return Ok(HttpResponse::NotFound().body("File not found"));
-        }
-    };
+    if let Err(response) = check_quota(pool.get_ref(), &config, user_id.as_deref(), &ip_hash).await {
+        return Ok(response);
+    }
+
+    let file_id = sqlx::query_scalar::<_, String>("SELECT id FROM download_files WHERE file_path = ?")
+        .bind(file_path)
+        .fetch_optional(pool.get_ref())
+        .await
+        .unwrap_or_else(|e| {
+            tracing::error!("Database error: {}", e);
+            None
+        });
+
+    let recorder = DownloadRecorder::new(
+        pool.get_ref().clone(),
+        file_id,
+        file_path,
+        user_id,
+        ip_hash,
+    );
+    serve_file(&req, storage.get_ref(), &config, &requested_path, Some(recorder)).await
+}
 
-    if !canonical_file.starts_with(&canonical_downloads) {
-        tracing::warn!("Path traversal attempt detected: {}", requested_path);
-        return Ok(HttpResponse::Forbidden().body("Access denied"));
+/// Serves the detached signature of a public file. Signatures have their own
+/// prefix so that no file path, such as `docs/signature`, is shadowed.
+pub async fn signature_public(
+    storage: web::Data<dyn StorageBackend>,
+    config: web::Data<Config>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse> {
+    let requested_path = path.into_inner();
+    serve_signature(&req, storage.get_ref(), &config, &requested_path).await
+}
+
+async fn serve_signature(
+    req: &HttpRequest,
+    storage: &dyn StorageBackend,
+    config: &Config,
+    file_path: &str,
+) -> Result<HttpResponse> {
+    match find_signature(storage, file_path).await {
+        Some(signature_path) => serve_file(req, storage, config, &signature_path, None).await,
+        None => Ok(HttpResponse::NotFound().body("No signature available")),
     }
+}
 
-    // Serve the file with proper headers for download
-    match NamedFile::open(&canonical_file) {
-        Ok(file) => {
-            let file = file
-                .use_last_modified(true)
-                .set_content_disposition(actix_web::http::header::ContentDisposition {
-                    disposition: actix_web::http::header::DispositionType::Attachment,
-                    parameters: vec![actix_web::http::header::DispositionParam::Filename(
-                        safe_path.file_name()
-                            .and_then(|n| n.to_str())
-                            .unwrap_or("download")
-                            .to_string(),
-                    )],
-                });
-            Ok(file.into_response(req))
+/// Returns the path of the first detached signature found beside `file_path`, if any.
+async fn find_signature(storage: &dyn StorageBackend, file_path: &str) -> Option<String> {
+    for ext in SIGNATURE_EXTENSIONS {
+        let candidate = format!("{}.{}", file_path, ext);
+        if storage.exists(&candidate).await {
+            return Some(candidate);
         }
-        Err(e) => {
-            tracing::error!("Error opening file {:?}: {}", canonical_file, e);
-            Ok(HttpResponse::InternalServerError().body("Error reading file"))
+    }
+    None
+}
+
+/// Maps a storage failure to the response sent to the client.
+pub fn storage_error_response(requested_path: &str, error: StorageError) -> HttpResponse {
+    match error {
+        StorageError::InvalidPath(_) => {
+            tracing::warn!("Invalid download path requested: {}", requested_path);
+            HttpResponse::BadRequest().body("Invalid file path")
+        }
+        StorageError::NotFound(_) => {
+            tracing::debug!("File not found: {}", requested_path);
+            HttpResponse::NotFound().body("File not found")
+        }
+        e => {
+            tracing::error!("Error reading file {}: {}", requested_path, e);
+            HttpResponse::InternalServerError().body("Error reading file")
         }
     }
 }
 
-fn sanitize_path(path: &str) -> Option<PathBuf> {
-    let path = path.trim_start_matches('/');
-    
-    // Reject empty paths
-    if path.is_empty() {
-        return None;
+/// Streams a stored file, honoring range and conditional requests and the
+/// configured bandwidth cap. When a recorder is given, the transfer is
+/// recorded once the body stops streaming.
+pub async fn serve_file(
+    req: &HttpRequest,
+    storage: &dyn StorageBackend,
+    config: &Config,
+    requested_path: &str,
+    recorder: Option<DownloadRecorder>,
+) -> Result<HttpResponse> {
+    let meta = match storage.metadata(requested_path).await {
+        Ok(meta) => meta,
+        Err(e) => return Ok(storage_error_response(requested_path, e)),
+    };
+
+    let last_modified = meta
+        .last_modified
+        .map(|m| LastModified(HttpDate::from(SystemTime::from(m))));
+
+    if is_not_modified(req, &meta) {
+        let mut response = HttpResponse::NotModified();
+        if let Some(etag) = &meta.etag {
+            response.insert_header((header::ETAG, etag.as_str()));
+        }
+        if let Some(last_modified) = last_modified {
+            response.insert_header(last_modified);
+        }
+        return Ok(response.finish());
     }
 
-    // Reject paths with null bytes
-    if path.contains('\0') {
-        return None;
+    // Only the first of several requested ranges is honored
+    let range = match req.headers().get(header::RANGE).and_then(|h| h.to_str().ok()) {
+        Some(range_header) => match HttpRange::parse(range_header, meta.size) {
+            Ok(ranges) => ranges.first().map(|r| ByteRange {
+                start: r.start,
+                length: r.length,
+            }),
+            Err(_) => {
+                return Ok(HttpResponse::RangeNotSatisfiable()
+                    .insert_header((header::CONTENT_RANGE, format!("bytes */{}", meta.size)))
+                    .finish());
+            }
+        },
+        None => None,
+    };
+
+    let body = match storage.read(requested_path, range).await {
+        Ok(body) => body,
+        Err(e) => return Ok(storage_error_response(requested_path, e)),
+    };
+
+    let file_name = Path::new(requested_path)
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or("download")
+        .to_string();
+    let content_type = meta.content_type.clone().unwrap_or_else(|| {
+        mime_guess::from_path(requested_path)
+            .first_or_octet_stream()
+            .to_string()
+    });
+
+    let mut response = match range {
+        Some(_) => HttpResponse::PartialContent(),
+        None => HttpResponse::Ok(),
+    };
+    response
+        .content_type(content_type)
+        .insert_header((header::ACCEPT_RANGES, "bytes"))
+        // Serve the file with proper headers for download
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename(file_name)],
+        });
+    if let Some(etag) = &meta.etag {
+        response.insert_header((header::ETAG, etag.as_str()));
+    }
+    if let Some(last_modified) = last_modified {
+        response.insert_header(last_modified);
+    }
+    if let Some(range) = range {
+        response.insert_header((
+            header::CONTENT_RANGE,
+            format!(
+                "bytes {}-{}/{}",
+                range.start,
+                range.start + range.length - 1,
+                meta.size
+            ),
+        ));
     }
 
-    let path_buf = PathBuf::from(path);
+    let length = range.map_or(meta.size, |r| r.length);
+    let body = throttle(body, config.download_rate_limit);
+    let body = match recorder {
+        Some(recorder) => recorder.wrap(body, length).boxed(),
+        None => body,
+    };
+    Ok(response.no_chunking(length).streaming(body))
+}
 
-    // Reject paths with parent directory references
-    for component in path_buf.components() {
-        match component {
-            std::path::Component::Normal(_) => continue,
-            std::path::Component::ParentDir => return None,
-            std::path::Component::CurDir => continue,
-            _ => return None,
-        }
+/// Evaluates `If-None-Match` and, failing that, `If-Modified-Since`.
+pub fn is_not_modified(req: &HttpRequest, meta: &ObjectMeta) -> bool {
+    let header_str = |name| req.headers().get(name).and_then(|h| h.to_str().ok());
+
+    if let Some(if_none_match) = header_str(header::IF_NONE_MATCH) {
+        return match &meta.etag {
+            Some(etag) => if_none_match
+                .split(',')
+                .map(str::trim)
+                .any(|candidate| candidate == "*" || candidate.trim_start_matches("W/") == etag),
+            None => false,
+        };
     }
 
-    Some(path_buf)
+    match (header_str(header::IF_MODIFIED_SINCE), meta.last_modified) {
+        (Some(since), Some(modified)) => DateTime::parse_from_rfc2822(since)
+            .map(|since| modified.timestamp() <= since.timestamp())
+            .unwrap_or(false),
+        _ => false,
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-
-    #[test]
-    fn test_sanitize_path_valid() {
-        assert!(sanitize_path("file.zip").is_some());
-        assert!(sanitize_path("projects/myapp.tar.gz").is_some());
-        assert!(sanitize_path("nested/deep/file.pdf").is_some());
-    }
-
-    #[test]
-    fn test_sanitize_path_invalid() {
-        assert!(sanitize_path("../etc/passwd").is_none());
-        assert!(sanitize_path("foo/../bar").is_none());
-        assert!(sanitize_path("/absolute/path").is_some()); // Leading slash is stripped
-        assert!(sanitize_path("").is_none());
+    use crate::storage::MemoryStorage;
+    use actix_web::{test, App};
+    use std::sync::Arc;
+
+    #[actix_web::test]
+    async fn test_signature_route_does_not_shadow_files() {
+        let pool = crate::db::test_pool().await;
+        let storage = MemoryStorage::default();
+        storage.insert("docs/signature", "a file called signature").unwrap();
+        storage.insert("app.zip", "app").unwrap();
+        storage.insert("app.zip.sig", "app signature").unwrap();
+        let storage: Arc<dyn StorageBackend> = Arc::new(storage);
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool))
+                .app_data(web::Data::from(storage))
+                .app_data(web::Data::new(Config::default()))
+                .route("/downloads/signature/{path:.*}", web::get().to(signature_public))
+                .route("/downloads/public/{path:.*}", web::get().to(download_public)),
+        )
+        .await;
+        let get = |uri: &str| test::TestRequest::get().uri(uri).to_request();
+
+        let response = test::call_service(&app, get("/downloads/public/docs/signature")).await;
+        assert_eq!(response.status(), 200);
+        assert_eq!(test::read_body(response).await, "a file called signature");
+        let response = test::call_service(&app, get("/downloads/signature/app.zip")).await;
+        assert_eq!(response.status(), 200);
+        assert_eq!(test::read_body(response).await, "app signature");
+        let response = test::call_service(&app, get("/downloads/signature/docs/signature")).await;
+        assert_eq!(response.status(), 404);
     }
 }
@@ -0,0 +1,389 @@
+use actix_session::Session;
+use actix_web::http::header::{self, HttpDate};
+use actix_web::{web, HttpRequest, HttpResponse};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use rand::RngCore;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use sqlx::SqlitePool;
+use std::time::SystemTime;
+
+use crate::auth::get_user_id;
+use crate::config::Config;
+use crate::downloads::{is_not_modified, NOT_YANKED};
+use crate::storage::ObjectMeta;
+
+/// Number of most recent files listed in a feed.
+const FEED_SIZE: i64 = 50;
+const FEED_TITLE: &str = "New downloads";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum FeedFormat {
+    Atom,
+    Rss,
+}
+
+impl FeedFormat {
+    fn from_extension(extension: &str) -> Option<Self> {
+        match extension {
+            "atom" => Some(FeedFormat::Atom),
+            "rss" => Some(FeedFormat::Rss),
+            _ => None,
+        }
+    }
+
+    fn content_type(self) -> &'static str {
+        match self {
+            FeedFormat::Atom => "application/atom+xml; charset=utf-8",
+            FeedFormat::Rss => "application/rss+xml; charset=utf-8",
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct FeedUrls {
+    pub atom_url: String,
+    pub rss_url: String,
+}
+
+struct FeedEntry {
+    id: String,
+    file_path: String,
+    display_name: String,
+    description: Option<String>,
+    is_protected: bool,
+    created_at: DateTime<Utc>,
+}
+
+/// Public feed of newly added files, `/feeds/downloads.atom` or `.rss`.
+pub async fn public_feed(
+    pool: web::Data<SqlitePool>,
+    config: web::Data<Config>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> HttpResponse {
+    serve_feed(pool.get_ref(), &config, &req, &path.into_inner(), false).await
+}
+
+/// A user's private feed, which also lists protected files. Feed readers
+/// cannot log in, so the URL carries a feed token instead of a session.
+pub async fn private_feed(
+    pool: web::Data<SqlitePool>,
+    config: web::Data<Config>,
+    req: HttpRequest,
+    path: web::Path<(String, String)>,
+) -> HttpResponse {
+    let (token, extension) = path.into_inner();
+
+    let user = sqlx::query_scalar::<_, String>("SELECT id FROM users WHERE feed_token_hash = ?")
+        .bind(hash_token(&token))
+        .fetch_optional(pool.get_ref())
+        .await;
+
+    match user {
+        Ok(Some(_)) => serve_feed(pool.get_ref(), &config, &req, &extension, true).await,
+        Ok(None) => HttpResponse::NotFound().body("Feed not found"),
+        Err(e) => {
+            tracing::error!("Database error: {}", e);
+            HttpResponse::InternalServerError().body("Database error")
+        }
+    }
+}
+
+/// Issues a new private feed token for the current user, invalidating any
+/// previous one, and returns the feed URLs built from it.
+pub async fn create_feed_token(
+    pool: web::Data<SqlitePool>,
+    config: web::Data<Config>,
+    session: Session,
+) -> HttpResponse {
+    let user_id = match get_user_id(&session) {
+        Some(id) => id,
+        None => return HttpResponse::Unauthorized().body("Authentication required"),
+    };
+    let Some(base) = base_url(&config) else {
+        return feeds_disabled();
+    };
+
+    let mut bytes = [0u8; 24];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    let token = URL_SAFE_NO_PAD.encode(bytes);
+
+    let result = sqlx::query("UPDATE users SET feed_token_hash = ? WHERE id = ?")
+        .bind(hash_token(&token))
+        .bind(&user_id)
+        .execute(pool.get_ref())
+        .await;
+
+    match result {
+        Ok(r) if r.rows_affected() == 0 => HttpResponse::Unauthorized().body("Authentication required"),
+        Ok(_) => {
+            let base = format!("{}/feeds/private/{}/downloads", base, token);
+            HttpResponse::Ok().json(FeedUrls {
+                atom_url: format!("{}.atom", base),
+                rss_url: format!("{}.rss", base),
+            })
+        }
+        Err(e) => {
+            tracing::error!("Failed to store feed token: {}", e);
+            HttpResponse::InternalServerError().body("Failed to create feed token")
+        }
+    }
+}
+
+async fn serve_feed(
+    pool: &SqlitePool,
+    config: &Config,
+    req: &HttpRequest,
+    extension: &str,
+    include_protected: bool,
+) -> HttpResponse {
+    let format = match FeedFormat::from_extension(extension) {
+        Some(format) => format,
+        None => return HttpResponse::NotFound().body("Feed not found"),
+    };
+    let Some(base) = base_url(config) else {
+        return feeds_disabled();
+    };
+
+    let entries = match fetch_entries(pool, include_protected).await {
+        Ok(entries) => entries,
+        Err(e) => {
+            tracing::error!("Database error building feed: {}", e);
+            return HttpResponse::InternalServerError().body("Database error");
+        }
+    };
+
+    let feed_url = format!("{}{}", base, req.path());
+    let body = match format {
+        FeedFormat::Atom => render_atom(base, &feed_url, &entries),
+        FeedFormat::Rss => render_rss(base, &feed_url, &entries),
+    };
+
+    let last_modified = entries.iter().map(|e| e.created_at).max();
+    let meta = ObjectMeta {
+        size: body.len() as u64,
+        last_modified,
+        content_type: Some(format.content_type().to_string()),
+        etag: Some(format!("\"{}\"", &hex::encode(Sha256::digest(&body))[..32])),
+    };
+    let cache_control = if include_protected { "private, no-cache" } else { "public, no-cache" };
+
+    let not_modified = is_not_modified(req, &meta);
+    let mut response = if not_modified {
+        HttpResponse::NotModified()
+    } else {
+        HttpResponse::Ok()
+    };
+    response
+        .insert_header((header::ETAG, meta.etag.clone().expect("feed etag")))
+        .insert_header((header::CACHE_CONTROL, cache_control));
+    if let Some(modified) = last_modified {
+        response.insert_header((header::LAST_MODIFIED, HttpDate::from(SystemTime::from(modified))));
+    }
+
+    if not_modified {
+        response.finish()
+    } else {
+        response.content_type(format.content_type()).body(body)
+    }
+}
+
+async fn fetch_entries(pool: &SqlitePool, include_protected: bool) -> Result<Vec<FeedEntry>, sqlx::Error> {
+    let rows = sqlx::query_as::<_, (String, String, String, Option<String>, i32, String)>(&format!(
+        r#"
+        SELECT id, file_path, display_name, description, is_protected, created_at
+        FROM download_files
+        WHERE {}
+          AND (release_id IS NULL OR release_id IN (SELECT id FROM releases WHERE published_at <= datetime('now')))
+          AND (is_protected = 0 OR ?)
+        ORDER BY created_at DESC, id
+        LIMIT ?
+        "#,
+        NOT_YANKED
+    ))
+    .bind(include_protected)
+    .bind(FEED_SIZE)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|(id, file_path, display_name, description, is_protected, created_at)| FeedEntry {
+            id,
+            file_path,
+            display_name,
+            description,
+            is_protected: is_protected != 0,
+            created_at: NaiveDateTime::parse_from_str(&created_at, "%Y-%m-%d %H:%M:%S")
+                .map(|t| t.and_utc())
+                .unwrap_or_default(),
+        })
+        .collect())
+}
+
+/// `PUBLIC_BASE_URL`, which feeds need for their absolute links. The
+/// request's `Host` header is not used, as clients choose it freely and
+/// feeds and their URLs are cached and shared.
+fn base_url(config: &Config) -> Option<&str> {
+    config.public_base_url.as_deref().map(|url| url.trim_end_matches('/'))
+}
+
+fn feeds_disabled() -> HttpResponse {
+    HttpResponse::NotFound().body("Feeds are not enabled")
+}
+
+/// Public files link straight to their download; protected ones need a
+/// logged-in user to request a token, so they link to the site.
+fn entry_link(base: &str, entry: &FeedEntry) -> String {
+    if entry.is_protected {
+        format!("{}/", base)
+    } else {
+        format!("{}/downloads/public/{}", base, entry.file_path)
+    }
+}
+
+fn render_atom(base: &str, feed_url: &str, entries: &[FeedEntry]) -> String {
+    let updated = entries.iter().map(|e| e.created_at).max().unwrap_or_default();
+    let mut xml = format!(
+        concat!(
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n",
+            "<feed xmlns=\"http://www.w3.org/2005/Atom\">\n",
+            "  <title>{}</title>\n",
+            "  <id>{}</id>\n",
+            "  <link rel=\"self\" href=\"{}\"/>\n",
+            "  <link href=\"{}/\"/>\n",
+            "  <updated>{}</updated>\n",
+        ),
+        FEED_TITLE,
+        escape(feed_url),
+        escape(feed_url),
+        escape(base),
+        updated.to_rfc3339(),
+    );
+    for entry in entries {
+        xml.push_str(&format!(
+            concat!(
+                "  <entry>\n",
+                "    <title>{}</title>\n",
+                "    <id>urn:uuid:{}</id>\n",
+                "    <link href=\"{}\"/>\n",
+                "    <updated>{}</updated>\n",
+                "    <author><name>{}</name></author>\n",
+            ),
+            escape(&entry.display_name),
+            escape(&entry.id),
+            escape(&entry_link(base, entry)),
+            entry.created_at.to_rfc3339(),
+            FEED_TITLE,
+        ));
+        if let Some(description) = &entry.description {
+            xml.push_str(&format!("    <summary>{}</summary>\n", escape(description)));
+        }
+        xml.push_str("  </entry>\n");
+    }
+    xml.push_str("</feed>\n");
+    xml
+}
+
+fn render_rss(base: &str, feed_url: &str, entries: &[FeedEntry]) -> String {
+    let mut xml = format!(
+        concat!(
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n",
+            "<rss version=\"2.0\" xmlns:atom=\"http://www.w3.org/2005/Atom\">\n",
+            "  <channel>\n",
+            "    <title>{}</title>\n",
+            "    <link>{}/</link>\n",
+            "    <description>{}</description>\n",
+            "    <atom:link href=\"{}\" rel=\"self\" type=\"application/rss+xml\"/>\n",
+        ),
+        FEED_TITLE,
+        escape(base),
+        FEED_TITLE,
+        escape(feed_url),
+    );
+    if let Some(updated) = entries.iter().map(|e| e.created_at).max() {
+        xml.push_str(&format!("    <lastBuildDate>{}</lastBuildDate>\n", updated.to_rfc2822()));
+    }
+    for entry in entries {
+        xml.push_str(&format!(
+            concat!(
+                "    <item>\n",
+                "      <title>{}</title>\n",
+                "      <link>{}</link>\n",
+                "      <guid isPermaLink=\"false\">urn:uuid:{}</guid>\n",
+                "      <pubDate>{}</pubDate>\n",
+            ),
+            escape(&entry.display_name),
+            escape(&entry_link(base, entry)),
+            escape(&entry.id),
+            entry.created_at.to_rfc2822(),
+        ));
+        if let Some(description) = &entry.description {
+            xml.push_str(&format!("      <description>{}</description>\n", escape(description)));
+        }
+        xml.push_str("    </item>\n");
+    }
+    xml.push_str("  </channel>\n</rss>\n");
+    xml
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(escape("a < b & \"c\""), "a &lt; b &amp; &quot;c&quot;");
+    }
+
+    #[tokio::test]
+    async fn test_feeds_list_public_files_newest_first() {
+        let pool = crate::db::test_pool().await;
+        sqlx::query(
+            r#"
+            INSERT INTO download_files (id, file_path, display_name, description, is_protected, created_at) VALUES
+                ('a', 'old.zip', 'Old <one>', NULL, 0, '2024-01-01 10:00:00'),
+                ('b', 'new.zip', 'New', 'Fresh & shiny', 0, '2024-02-01 10:00:00'),
+                ('c', 'secret.zip', 'Secret', NULL, 1, '2024-03-01 10:00:00')
+            "#,
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        let public = fetch_entries(&pool, false).await.unwrap();
+        assert_eq!(public.iter().map(|e| e.id.as_str()).collect::<Vec<_>>(), ["b", "a"]);
+        assert_eq!(fetch_entries(&pool, true).await.unwrap().len(), 3);
+
+        let atom = render_atom("https://example.com", "https://example.com/feeds/downloads.atom", &public);
+        assert!(atom.contains("<updated>2024-02-01T10:00:00+00:00</updated>"));
+        assert!(atom.contains("<title>Old &lt;one&gt;</title>"));
+        assert!(atom.contains("<link href=\"https://example.com/downloads/public/new.zip\"/>"));
+
+        let rss = render_rss("https://example.com", "https://example.com/feeds/downloads.rss", &public);
+        assert!(rss.contains("<pubDate>Thu, 1 Feb 2024 10:00:00 +0000</pubDate>"));
+        assert!(rss.contains("<description>Fresh &amp; shiny</description>"));
+    }
+}
@@ -0,0 +1,353 @@
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use sqlx::{QueryBuilder, Row, Sqlite, SqlitePool};
+
+use crate::downloads::{FileRow, NOT_YANKED};
+use crate::taxonomy::normalize_tag;
+
+const DEFAULT_PAGE_SIZE: u32 = 50;
+const MAX_PAGE_SIZE: u32 = 100;
+
+/// Query parameters accepted by `GET /api/files`.
+#[derive(Deserialize, Default)]
+pub struct ListFilesQuery {
+    /// Full-text search over display names and descriptions
+    pub q: Option<String>,
+    /// Comma-separated tag names; files must carry all of them
+    pub tags: Option<String>,
+    /// Category slug; files in its subcategories are included
+    pub category: Option<String>,
+    #[serde(default)]
+    pub sort: FileSort,
+    /// Defaults to ascending for `name` and descending otherwise
+    pub order: Option<SortOrder>,
+    /// Opaque cursor from a previous page's `next_cursor`
+    pub cursor: Option<String>,
+    pub limit: Option<u32>,
+}
+
+#[derive(Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FileSort {
+    #[default]
+    Name,
+    Date,
+    Size,
+    Popularity,
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    Asc,
+    Desc,
+}
+
+impl FileSort {
+    fn expression(self) -> &'static str {
+        match self {
+            FileSort::Name => "df.display_name COLLATE NOCASE",
+            FileSort::Date => "df.created_at",
+            FileSort::Size => "COALESCE(df.size_bytes, -1)",
+            FileSort::Popularity => "COALESCE(popularity.downloads, 0)",
+        }
+    }
+
+    fn default_order(self) -> SortOrder {
+        match self {
+            FileSort::Name => SortOrder::Asc,
+            _ => SortOrder::Desc,
+        }
+    }
+}
+
+/// Position after the last row of a page: its sort key and id as tie-breaker.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct Cursor {
+    key: SortKey,
+    id: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(untagged)]
+enum SortKey {
+    Int(i64),
+    Text(String),
+}
+
+impl Cursor {
+    fn encode(&self) -> String {
+        URL_SAFE_NO_PAD.encode(serde_json::to_vec(self).expect("cursor serializes"))
+    }
+
+    fn decode(cursor: &str) -> Option<Self> {
+        let bytes = URL_SAFE_NO_PAD.decode(cursor).ok()?;
+        serde_json::from_slice(&bytes).ok()
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum SearchError {
+    #[error("Invalid cursor")]
+    InvalidCursor,
+    #[error("Database error: {0}")]
+    Database(#[from] sqlx::Error),
+}
+
+/// One page of matching files plus the cursor for the next page, if any.
+pub struct FilePage {
+    pub rows: Vec<FileRow>,
+    pub next_cursor: Option<String>,
+}
+
+pub async fn query_files(
+    pool: &SqlitePool,
+    params: &ListFilesQuery,
+    include_protected: bool,
+) -> Result<FilePage, SearchError> {
+    let cursor = match params.cursor.as_deref() {
+        Some(c) => Some(Cursor::decode(c).ok_or(SearchError::InvalidCursor)?),
+        None => None,
+    };
+    let limit = params.limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+    let expr = params.sort.expression();
+    let (direction, comparison) = match params.order.unwrap_or(params.sort.default_order()) {
+        SortOrder::Asc => ("ASC", ">"),
+        SortOrder::Desc => ("DESC", "<"),
+    };
+
+    let mut query = QueryBuilder::<Sqlite>::new(format!(
+        r#"
+        SELECT df.id, df.file_path, df.display_name, df.description, df.is_protected, df.size_bytes,
+               {} AS sort_key
+        FROM download_files df
+        LEFT JOIN (
+            SELECT file_path, SUM(downloads) AS downloads FROM download_stats_daily GROUP BY file_path
+        ) popularity ON popularity.file_path = df.file_path
+        WHERE {}
+        "#,
+        expr, NOT_YANKED
+    ));
+
+    if !include_protected {
+        query.push(" AND df.is_protected = 0");
+    }
+
+    if let Some(search) = params.q.as_deref().and_then(fts_query) {
+        query
+            .push(" AND df.id IN (SELECT file_id FROM download_files_fts WHERE download_files_fts MATCH ")
+            .push_bind(search)
+            .push(")");
+    }
+
+    for tag in params.tags.iter().flat_map(|t| t.split(',')).filter_map(normalize_tag) {
+        query
+            .push(" AND df.id IN (SELECT ft.file_id FROM file_tags ft JOIN tags t ON t.id = ft.tag_id WHERE t.name = ")
+            .push_bind(tag)
+            .push(")");
+    }
+
+    if let Some(category) = &params.category {
+        query
+            .push(
+                r#" AND df.category_id IN (
+                    WITH RECURSIVE subtree(id) AS (
+                        SELECT id FROM categories WHERE slug = "#,
+            )
+            .push_bind(category.clone())
+            .push(
+                r#"
+                        UNION
+                        SELECT c.id FROM categories c JOIN subtree s ON c.parent_id = s.id
+                    )
+                    SELECT id FROM subtree
+                )"#,
+            );
+    }
+
+    if let Some(cursor) = cursor {
+        query.push(format!(" AND ({} {} ", expr, comparison));
+        push_key(&mut query, &cursor.key);
+        query.push(format!(" OR ({} = ", expr));
+        push_key(&mut query, &cursor.key);
+        query
+            .push(format!(" AND df.id {} ", comparison))
+            .push_bind(cursor.id)
+            .push("))");
+    }
+
+    query
+        .push(format!(" ORDER BY {} {}, df.id {} LIMIT ", expr, direction, direction))
+        .push_bind(limit as i64 + 1);
+
+    let mut rows = query.build().fetch_all(pool).await?;
+
+    let next_cursor = if rows.len() > limit as usize {
+        rows.truncate(limit as usize);
+        rows.last().map(|row| {
+            let key = match params.sort {
+                FileSort::Name | FileSort::Date => SortKey::Text(row.get("sort_key")),
+                FileSort::Size | FileSort::Popularity => SortKey::Int(row.get("sort_key")),
+            };
+            Cursor { key, id: row.get("id") }.encode()
+        })
+    } else {
+        None
+    };
+
+    let rows = rows
+        .into_iter()
+        .map(|row| {
+            (
+                row.get("id"),
+                row.get("file_path"),
+                row.get("display_name"),
+                row.get("description"),
+                row.get("is_protected"),
+                row.get("size_bytes"),
+            )
+        })
+        .collect();
+
+    Ok(FilePage { rows, next_cursor })
+}
+
+fn push_key(query: &mut QueryBuilder<'_, Sqlite>, key: &SortKey) {
+    match key {
+        SortKey::Int(value) => query.push_bind(*value),
+        SortKey::Text(value) => query.push_bind(value.clone()),
+    };
+}
+
+/// Turns free text into an FTS5 query matching every word as a prefix, with
+/// each word quoted so user input can never be parsed as FTS syntax.
+fn fts_query(input: &str) -> Option<String> {
+    let terms: Vec<String> = input
+        .split_whitespace()
+        .map(|term| format!("\"{}\"*", term.replace('"', "\"\"")))
+        .collect();
+
+    if terms.is_empty() {
+        None
+    } else {
+        Some(terms.join(" "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn insert_file(pool: &SqlitePool, id: &str, name: &str, description: &str, protected: bool) {
+        sqlx::query(
+            "INSERT INTO download_files (id, file_path, display_name, description, is_protected) VALUES (?, ?, ?, ?, ?)",
+        )
+        .bind(id)
+        .bind(format!("{}.zip", id))
+        .bind(name)
+        .bind(description)
+        .bind(protected as i32)
+        .execute(pool)
+        .await
+        .unwrap();
+    }
+
+    fn ids(page: &FilePage) -> Vec<&str> {
+        page.rows.iter().map(|row| row.0.as_str()).collect()
+    }
+
+    #[test]
+    fn test_fts_query_quotes_terms() {
+        assert_eq!(fts_query("rust  server").unwrap(), "\"rust\"* \"server\"*");
+        assert_eq!(fts_query("say \"hi\" OR").unwrap(), "\"say\"* \"\"\"hi\"\"\"* \"OR\"*");
+        assert!(fts_query("   ").is_none());
+    }
+
+    #[test]
+    fn test_cursor_round_trip() {
+        let cursor = Cursor {
+            key: SortKey::Int(42),
+            id: "abc".into(),
+        };
+        assert_eq!(Cursor::decode(&cursor.encode()), Some(cursor));
+        assert!(Cursor::decode("not a cursor").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_query_files_search_and_pagination() {
+        let pool = crate::db::test_pool().await;
+        insert_file(&pool, "a", "Alpha tool", "Command line helper", false).await;
+        insert_file(&pool, "b", "beta tool", "Graphical helper", false).await;
+        insert_file(&pool, "c", "Charlie", "Secret helper", true).await;
+        insert_file(&pool, "d", "Delta", "Unrelated", false).await;
+
+        let mut params = ListFilesQuery {
+            limit: Some(2),
+            ..Default::default()
+        };
+        let first = query_files(&pool, &params, false).await.unwrap();
+        assert_eq!(ids(&first), ["a", "b"]);
+
+        params.cursor = first.next_cursor;
+        let second = query_files(&pool, &params, false).await.unwrap();
+        assert_eq!(ids(&second), ["d"]);
+        assert!(second.next_cursor.is_none());
+
+        let params = ListFilesQuery {
+            q: Some("help".into()),
+            order: Some(SortOrder::Desc),
+            ..Default::default()
+        };
+        assert_eq!(ids(&query_files(&pool, &params, true).await.unwrap()), ["c", "b", "a"]);
+        assert_eq!(ids(&query_files(&pool, &params, false).await.unwrap()), ["b", "a"]);
+
+        // Rowids may be renumbered (e.g. by VACUUM) without breaking search
+        sqlx::query("UPDATE download_files SET rowid = rowid + 100").execute(&pool).await.unwrap();
+        sqlx::query("UPDATE download_files SET description = 'Unrelated' WHERE id = 'b'")
+            .execute(&pool)
+            .await
+            .unwrap();
+        assert_eq!(ids(&query_files(&pool, &params, false).await.unwrap()), ["a"]);
+
+        sqlx::query("INSERT INTO categories (id, name, slug, parent_id) VALUES ('sw', 'Software', 'software', NULL), ('cli', 'CLI', 'cli', 'sw')")
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query("UPDATE download_files SET category_id = CASE id WHEN 'a' THEN 'cli' WHEN 'b' THEN 'sw' END")
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query("INSERT INTO tags (id, name) VALUES ('t1', 'linux'), ('t2', 'stable')")
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query("INSERT INTO file_tags (file_id, tag_id) VALUES ('a', 't1'), ('a', 't2'), ('b', 't1')")
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let by_category = |slug: &str| ListFilesQuery {
+            category: Some(slug.into()),
+            ..Default::default()
+        };
+        assert_eq!(ids(&query_files(&pool, &by_category("software"), false).await.unwrap()), ["a", "b"]);
+        assert_eq!(ids(&query_files(&pool, &by_category("cli"), false).await.unwrap()), ["a"]);
+
+        let by_tags = |tags: &str| ListFilesQuery {
+            tags: Some(tags.into()),
+            ..Default::default()
+        };
+        assert_eq!(ids(&query_files(&pool, &by_tags("linux"), false).await.unwrap()), ["a", "b"]);
+        assert_eq!(ids(&query_files(&pool, &by_tags("Linux, stable"), false).await.unwrap()), ["a"]);
+
+        let params = ListFilesQuery {
+            cursor: Some("garbage".into()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            query_files(&pool, &params, false).await,
+            Err(SearchError::InvalidCursor)
+        ));
+    }
+}
@@ -1,11 +1,20 @@
-use actix_web::{web, HttpResponse, Result};
+use actix_multipart::Multipart;
+use actix_web::{web, HttpRequest, HttpResponse, Result};
 use serde::{Deserialize, Serialize};
-use std::sync::Arc;
+use sqlx::SqlitePool;
 
+use crate::analytics::client_ip_hash;
+use crate::attachments;
+use crate::auto_reply;
 use crate::config::Config;
-use crate::mail;
+use crate::inbox;
+use crate::mail::{Attachment, Templates};
+use crate::notify::{Notification, Notifications};
+use crate::outbox::Outbox;
+use crate::spam::{self, SpamFields, Verdict};
+use crate::validation::{ContactForm, FieldError, ValidationErrors};
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Default)]
 pub struct EmailRequest {
     pub sender: String,
     #[serde(rename = "firstName")]
@@ -13,6 +22,16 @@ pub struct EmailRequest {
     #[serde(rename = "lastName")]
     pub last_name: String,
     pub message: String,
+    /// Routes the message to the topic's recipients (`CONTACT_TOPICS`)
+    #[serde(default)]
+    pub topic: Option<String>,
+    /// Honeypot field, hidden from visitors
+    #[serde(default)]
+    pub website: Option<String>,
+    #[serde(rename = "formToken", default)]
+    pub form_token: Option<String>,
+    #[serde(rename = "powSolution", default)]
+    pub pow_solution: Option<String>,
 }
 
 #[derive(Serialize)]
@@ -20,31 +39,111 @@ pub struct EmailResponse {
     pub data: bool,
 }
 
+/// Stores the contact message in the admin inbox and queues it; the outbox
+/// worker delivers it, retrying while the mail provider is unavailable.
+/// Suspected spam is quarantined with the same response, so bots can't
+/// tell it was caught.
 pub async fn send_email(
-    config: web::Data<Arc<Config>>,
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    config: web::Data<Config>,
+    outbox: web::Data<Outbox>,
+    templates: web::Data<Templates>,
+    notifications: web::Data<Notifications>,
     body: web::Json<EmailRequest>,
 ) -> HttpResponse {
-    let api_key = match config.mail_api_key() {
-        Some(key) => key,
-        None => {
-            tracing::error!("Mail API key not configured");
-            return HttpResponse::ServiceUnavailable().finish();
-        }
-    };
+    submit_contact(&req, &pool, &config, &outbox, &templates, &notifications, (body.into_inner(), Vec::new())).await
+}
+
+/// [`send_email`] for `multipart/form-data` submissions carrying attachments.
+pub async fn send_email_multipart(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    config: web::Data<Config>,
+    outbox: web::Data<Outbox>,
+    templates: web::Data<Templates>,
+    notifications: web::Data<Notifications>,
+    payload: Multipart,
+) -> HttpResponse {
+    match attachments::read_contact_form(payload, &config.attachments).await {
+        Ok(submission) => submit_contact(&req, &pool, &config, &outbox, &templates, &notifications, submission).await,
+        Err(response) => response,
+    }
+}
+
+async fn submit_contact(
+    req: &HttpRequest,
+    pool: &SqlitePool,
+    config: &Config,
+    outbox: &Outbox,
+    templates: &Templates,
+    notifications: &Notifications,
+    (body, files): (EmailRequest, Vec<Attachment>),
+) -> HttpResponse {
+    // Accepting messages that can never be delivered would only hide the problem
+    if !config.mail_configured() {
+        tracing::error!("Mail API key not configured");
+        return HttpResponse::ServiceUnavailable().finish();
+    }
 
-    match mail::send_email(
+    let mut form = match ContactForm::validate(
         &body.sender,
         &body.first_name,
         &body.last_name,
         &body.message,
-        api_key,
-    )
-    .await
-    {
-        Ok(true) => HttpResponse::Ok().json(EmailResponse { data: true }),
-        Ok(false) => HttpResponse::InternalServerError().finish(),
+        body.topic.as_deref(),
+    ) {
+        Ok(form) => form,
+        Err(errors) => return errors.into_response(),
+    };
+    form.attachments = files;
+    if config.contact.recipients(form.topic.as_deref()).is_none() {
+        return ValidationErrors::single(FieldError {
+            field: "topic",
+            code: "invalid_choice",
+            message: "Unknown topic".to_string(),
+        })
+        .into_response();
+    }
+
+    let ip_hash = client_ip_hash(req, config);
+    let user_agent = inbox::user_agent(req);
+    let fields = SpamFields {
+        honeypot: body.website.as_deref(),
+        form_token: body.form_token.as_deref(),
+        pow_solution: body.pow_solution.as_deref(),
+    };
+    match spam::assess(pool, &config.spam, &form, &fields).await {
+        Ok(Verdict::Accept) => {}
+        Ok(Verdict::Quarantine { reasons, score }) => {
+            tracing::info!("Quarantined contact message ({})", reasons.join(", "));
+            return match spam::quarantine(pool, &form, &reasons, score, &ip_hash, user_agent.as_deref()).await {
+                Ok(()) => HttpResponse::Ok().json(EmailResponse { data: true }),
+                Err(e) => {
+                    tracing::error!("Error quarantining message: {:?}", e);
+                    HttpResponse::ServiceUnavailable().finish()
+                }
+            };
+        }
+        Ok(Verdict::Reject(error)) => return ValidationErrors::single(error).into_response(),
+        Err(e) => {
+            tracing::error!("Error checking message for spam: {:?}", e);
+            return HttpResponse::ServiceUnavailable().finish();
+        }
+    }
+
+    match inbox::submit(pool, outbox, templates, &config.contact, &form, &ip_hash, user_agent.as_deref()).await {
+        Ok(id) => {
+            notifications.dispatch(Notification::contact_message(&id, &form));
+            // The submission is safe at this point; a failed acknowledgement is only logged
+            if let Err(e) = auto_reply::send(pool, outbox, templates, &config.auto_reply, &config.contact, &form, &ip_hash).await
+            {
+                tracing::error!("Error queueing auto-reply: {}", e);
+            }
+            HttpResponse::Ok().json(EmailResponse { data: true })
+        }
         Err(e) => {
-            tracing::error!("Error Sending E-Mail: {:?}", e);
+            tracing::error!("Error Queueing E-Mail: {:?}", e);
             HttpResponse::ServiceUnavailable().finish()
         }
     }
@@ -55,3 +154,250 @@ pub async fn spa_fallback() -> Result<actix_files::NamedFile> {
         "../client/leptosUI/dist/index.html",
     )?)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::MailKind;
+    use crate::mail;
+    use actix_web::{test, App};
+
+    #[actix_web::test]
+    async fn test_send_email_queues_message() {
+        let pool = crate::db::test_pool().await;
+        let outbox = web::Data::new(Outbox::new(pool.clone()));
+        let mut config = Config {
+            mail_kind: MailKind::Memory,
+            ..Default::default()
+        };
+        config.spam.pow_difficulty = 0;
+        let spam_config = config.spam.clone();
+        config.contact.topics.insert(
+            "support".into(),
+            mail::Recipients {
+                to: vec!["help@example.com".into()],
+                cc: vec!["ops@example.com".into()],
+                ..Default::default()
+            },
+        );
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool.clone()))
+                .app_data(web::Data::new(config))
+                .app_data(outbox)
+                .app_data(web::Data::new(Templates::new(None)))
+                .app_data(web::Data::new(Notifications::from_config(&Default::default())))
+                .route("/email", web::post().to(send_email)),
+        )
+        .await;
+
+        let request = test::TestRequest::post()
+            .uri("/email")
+            .set_json(serde_json::json!({
+                "sender": "visitor@example.com",
+                "firstName": "Ada",
+                "lastName": "Lovelace",
+                "formToken": spam::test_token(&spam_config),
+                "message": "Hello!",
+            }))
+            .to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), 200);
+
+        let payload: String = sqlx::query_scalar("SELECT message FROM mail_outbox WHERE status = 'pending'")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        let queued: mail::EmailMessage = serde_json::from_str(&payload).unwrap();
+        assert_eq!(queued.subject, "Logan0Dev - Mail from: Ada Lovelace<visitor@example.com>");
+        assert!(queued.text.starts_with("Hello!\n"));
+        assert!(queued.html.unwrap().contains("Hello!"));
+        assert_eq!(queued.to, ["LoganTCarpenter@gmail.com"]);
+        assert_eq!(queued.reply_to.as_deref(), Some("visitor@example.com"));
+        let (status, linked): (String, bool) =
+            sqlx::query_as("SELECT status, outbox_id IS NOT NULL FROM contact_messages WHERE sender = 'visitor@example.com'")
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+        assert_eq!((status.as_str(), linked), ("new", true));
+
+        let request = test::TestRequest::post()
+            .uri("/email")
+            .set_json(serde_json::json!({
+                "sender": "not an address",
+                "firstName": "Ada",
+                "lastName": "Lovelace",
+                "formToken": spam::test_token(&spam_config),
+                "message": "Hello!",
+            }))
+            .to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), 422);
+        let body: serde_json::Value = test::read_body_json(response).await;
+        assert_eq!(body["errors"][0]["field"], "sender");
+
+        let request = test::TestRequest::post()
+            .uri("/email")
+            .set_json(serde_json::json!({
+                "sender": "visitor@example.com",
+                "firstName": "Ada",
+                "lastName": "Lovelace",
+                "formToken": spam::test_token(&spam_config),
+                "message": "Help!",
+                "topic": "Support",
+            }))
+            .to_request();
+        assert_eq!(test::call_service(&app, request).await.status(), 200);
+        let payload: String = sqlx::query_scalar("SELECT message FROM mail_outbox ORDER BY created_at DESC, rowid DESC")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        let routed: mail::EmailMessage = serde_json::from_str(&payload).unwrap();
+        assert_eq!((routed.to, routed.cc), (vec!["help@example.com".to_string()], vec!["ops@example.com".to_string()]));
+
+        let request = test::TestRequest::post()
+            .uri("/email")
+            .set_json(serde_json::json!({
+                "sender": "visitor@example.com",
+                "firstName": "Ada",
+                "lastName": "Lovelace",
+                "formToken": spam::test_token(&spam_config),
+                "message": "Hi",
+                "topic": "sales",
+            }))
+            .to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), 422);
+        let body: serde_json::Value = test::read_body_json(response).await;
+        assert_eq!(body["errors"][0]["field"], "topic");
+
+        // Honeypot hits look accepted but are held back
+        let request = test::TestRequest::post()
+            .uri("/email")
+            .set_json(serde_json::json!({
+                "sender": "bot@example.com",
+                "firstName": "Spam",
+                "lastName": "Bot",
+                "formToken": spam::test_token(&spam_config),
+                "message": "Cheap pills",
+                "website": "http://spam.example",
+            }))
+            .to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), 200);
+        let queued: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM mail_outbox").fetch_one(&pool).await.unwrap();
+        assert_eq!(queued, 2);
+        let reasons: String = sqlx::query_scalar("SELECT reasons FROM contact_quarantine")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(reasons, "honeypot");
+    }
+
+    #[actix_web::test]
+    async fn test_send_email_needs_mail_configured() {
+        let pool = crate::db::test_pool().await;
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool.clone()))
+                .app_data(web::Data::new(Config::default()))
+                .app_data(web::Data::new(Outbox::new(pool.clone())))
+                .app_data(web::Data::new(Templates::new(None)))
+                .app_data(web::Data::new(Notifications::from_config(&Default::default())))
+                .route("/email", web::post().to(send_email)),
+        )
+        .await;
+
+        let request = test::TestRequest::post()
+            .uri("/email")
+            .set_json(serde_json::json!({
+                "sender": "visitor@example.com",
+                "firstName": "Ada",
+                "lastName": "Lovelace",
+                "message": "Hello!",
+            }))
+            .to_request();
+        assert_eq!(test::call_service(&app, request).await.status(), 503);
+        let stored: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM contact_messages").fetch_one(&pool).await.unwrap();
+        assert_eq!(stored, 0);
+    }
+
+    fn multipart_body(parts: &[(&str, Option<&str>, &[u8])]) -> Vec<u8> {
+        let mut body = Vec::new();
+        for (name, filename, content) in parts {
+            body.extend_from_slice(b"--XBOUNDARY\r\n");
+            let disposition = match filename {
+                Some(filename) => format!("form-data; name=\"{}\"; filename=\"{}\"", name, filename),
+                None => format!("form-data; name=\"{}\"", name),
+            };
+            body.extend_from_slice(format!("Content-Disposition: {}\r\n\r\n", disposition).as_bytes());
+            body.extend_from_slice(content);
+            body.extend_from_slice(b"\r\n");
+        }
+        body.extend_from_slice(b"--XBOUNDARY--\r\n");
+        body
+    }
+
+    #[actix_web::test]
+    async fn test_send_email_accepts_attachments() {
+        let pool = crate::db::test_pool().await;
+        let mut config = Config {
+            mail_kind: MailKind::Memory,
+            ..Default::default()
+        };
+        config.spam.pow_difficulty = 0;
+        let spam_config = config.spam.clone();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool.clone()))
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(Outbox::new(pool.clone())))
+                .app_data(web::Data::new(Templates::new(None)))
+                .app_data(web::Data::new(Notifications::from_config(&Default::default())))
+                .route("/email", web::post().to(send_email_multipart)),
+        )
+        .await;
+        let fields: [(&str, Option<&str>, &[u8]); 4] = [
+            ("sender", None, b"visitor@example.com"),
+            ("firstName", None, b"Ada"),
+            ("lastName", None, b"Lovelace"),
+            ("message", None, b"My CV is attached"),
+        ];
+        let post = |extra: (&'static str, Option<&'static str>, &'static [u8])| {
+            let token = spam::test_token(&spam_config);
+            let mut parts = fields.to_vec();
+            parts.push(("formToken", None, token.as_bytes()));
+            parts.push(extra);
+            test::TestRequest::post()
+                .uri("/email")
+                .insert_header(("Content-Type", "multipart/form-data; boundary=XBOUNDARY"))
+                .set_payload(multipart_body(&parts))
+                .to_request()
+        };
+
+        let response = test::call_service(&app, post(("attachments", Some("../cv.pdf"), b"%PDF-1.7 cv"))).await;
+        assert_eq!(response.status(), 200);
+        let payload: String = sqlx::query_scalar("SELECT message FROM mail_outbox").fetch_one(&pool).await.unwrap();
+        let queued: mail::EmailMessage = serde_json::from_str(&payload).unwrap();
+        assert!(queued.text.starts_with("My CV is attached"));
+        assert_eq!(
+            queued.attachments,
+            [Attachment {
+                filename: "cv.pdf".into(),
+                content_type: "application/pdf".into(),
+                content: b"%PDF-1.7 cv".to_vec(),
+            }]
+        );
+
+        for (file, code) in [
+            (("attachments", Some("cv.pdf"), &b"MZ\x90\x00"[..]), "unsupported_type"),
+            (("attachments", Some("run.exe"), &b"MZ"[..]), "unsupported_type"),
+            (("attachments", Some("big.txt"), &[b'a'; 6 * 1024 * 1024][..]), "too_large"),
+        ] {
+            let response = test::call_service(&app, post(file)).await;
+            assert_eq!(response.status(), 422);
+            let body: serde_json::Value = test::read_body_json(response).await;
+            assert_eq!(body["errors"][0]["code"], code);
+        }
+    }
+}
@@ -0,0 +1,440 @@
+use actix_session::SessionExt;
+use actix_web::body::{self, BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::{header, StatusCode};
+use actix_web::middleware::Next;
+use actix_web::{web, Error, HttpMessage, HttpResponse};
+use futures_util::StreamExt;
+use sha2::{Digest, Sha256};
+use sqlx::SqlitePool;
+use std::time::Duration;
+
+use crate::analytics::client_ip_hash;
+use crate::auth::get_user_id;
+use crate::config::Config;
+
+const IDEMPOTENCY_KEY: &str = "Idempotency-Key";
+const MAX_KEY_LEN: usize = 255;
+/// Largest non-multipart body buffered to fingerprint it, the same as
+/// actix's default `JsonConfig` limit the wrapped JSON routes use.
+const MAX_JSON_BYTES: usize = 2 * 1024 * 1024;
+/// A first request still unfinished after this long is taken to have been
+/// abandoned, so its key can be used again.
+const IN_PROGRESS_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// What an earlier request with the same key means for this one.
+enum Claim {
+    /// This is the first request; it runs and its response is stored
+    First,
+    InProgress,
+    /// The key was used for a different request
+    Mismatch,
+    Replay {
+        status: u16,
+        content_type: Option<String>,
+        body: Vec<u8>,
+    },
+}
+
+/// Route middleware honoring the `Idempotency-Key` header: the first
+/// response to a key is stored for the configured window and sent again for
+/// repeated requests, so a double-clicked form or a retried request takes
+/// effect once. Keys are scoped to the path and the session user, or the
+/// client IP for anonymous requests. Requests without the header pass
+/// through unchanged.
+pub async fn idempotency(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let key = match req.headers().get(IDEMPOTENCY_KEY).map(|value| value.to_str()) {
+        None => return next.call(req).await.map(ServiceResponse::map_into_boxed_body),
+        Some(Ok(key)) if is_valid_key(key) => key.to_string(),
+        Some(_) => return Ok(req.into_response(HttpResponse::BadRequest().body("Invalid Idempotency-Key"))),
+    };
+    let (Some(pool), Some(config)) = (
+        req.app_data::<web::Data<SqlitePool>>().cloned(),
+        req.app_data::<web::Data<Config>>().cloned(),
+    ) else {
+        tracing::error!("Idempotency keys need the database and configuration");
+        return Ok(req.into_response(HttpResponse::InternalServerError().finish()));
+    };
+
+    // The body is read up front to fingerprint it, then handed on as it was
+    let max_body_bytes = max_body_bytes(&req, &config);
+    let mut payload = req.take_payload();
+    let mut body = web::BytesMut::new();
+    while let Some(chunk) = payload.next().await {
+        let chunk = chunk?;
+        if body.len() + chunk.len() > max_body_bytes {
+            return Ok(req.into_response(HttpResponse::PayloadTooLarge().finish()));
+        }
+        body.extend_from_slice(&chunk);
+    }
+    let body = body.freeze();
+    req.set_payload(body.clone().into());
+
+    let principal = match get_user_id(&req.get_session()) {
+        Some(user_id) => format!("user:{}", user_id),
+        None => format!("ip:{}", client_ip_hash(req.request(), &config)),
+    };
+    let scope = format!("{} {} {}", req.method(), req.path(), principal);
+    let fingerprint = fingerprint(&req, &body);
+
+    match claim(&pool, &scope, &key, &fingerprint, config.idempotency_window()).await {
+        Ok(Claim::First) => {}
+        Ok(Claim::InProgress) => {
+            return Ok(req.into_response(
+                HttpResponse::Conflict().body("A request with this Idempotency-Key is still being processed"),
+            ))
+        }
+        Ok(Claim::Mismatch) => {
+            return Ok(req.into_response(
+                HttpResponse::Conflict().body("Idempotency-Key was already used for a different request"),
+            ))
+        }
+        Ok(Claim::Replay {
+            status,
+            content_type,
+            body,
+        }) => {
+            let mut response = HttpResponse::build(StatusCode::from_u16(status).unwrap_or(StatusCode::OK));
+            if let Some(content_type) = content_type {
+                response.insert_header((header::CONTENT_TYPE, content_type));
+            }
+            response.insert_header(("Idempotent-Replayed", "true"));
+            return Ok(req.into_response(response.body(body)));
+        }
+        Err(e) => {
+            tracing::error!("Database error claiming idempotency key: {}", e);
+            return Ok(req.into_response(HttpResponse::InternalServerError().body("Database error")));
+        }
+    }
+
+    let response = match next.call(req).await {
+        Ok(response) => response,
+        Err(e) => {
+            release(&pool, &scope, &key).await;
+            return Err(e);
+        }
+    };
+    // Failures that may go away are not stored, so the request can be retried
+    let status = response.status();
+    if status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS {
+        release(&pool, &scope, &key).await;
+        return Ok(response.map_into_boxed_body());
+    }
+
+    let (request, response) = response.into_parts();
+    let (response, body) = response.into_parts();
+    let body = body::to_bytes(body).await.map_err(|e| {
+        let e: Box<dyn std::error::Error> = e.into();
+        actix_web::error::ErrorInternalServerError(e.to_string())
+    })?;
+    let content_type = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+
+    let result = sqlx::query("UPDATE idempotency_keys SET status = ?, content_type = ?, body = ? WHERE scope = ? AND key = ?")
+        .bind(status.as_u16())
+        .bind(content_type)
+        .bind(body.as_ref())
+        .bind(&scope)
+        .bind(&key)
+        .execute(pool.get_ref())
+        .await;
+    if let Err(e) = result {
+        tracing::error!("Database error storing idempotent response: {}", e);
+        release(&pool, &scope, &key).await;
+    }
+
+    Ok(ServiceResponse::new(request, response.set_body(body).map_into_boxed_body()))
+}
+
+/// Largest body the wrapped route itself would accept: the contact form's
+/// attachments for multipart submissions, the JSON limit otherwise.
+fn max_body_bytes(req: &ServiceRequest, config: &Config) -> usize {
+    match req.get_header::<header::ContentType>() {
+        Some(mime) if mime.type_() == "multipart" => config.attachments.max_form_bytes(),
+        _ => MAX_JSON_BYTES,
+    }
+}
+
+fn is_valid_key(key: &str) -> bool {
+    !key.is_empty() && key.len() <= MAX_KEY_LEN && key.bytes().all(|b| b.is_ascii_graphic())
+}
+
+/// Hash of what makes two requests the same: the query, the content type
+/// and the body. Multipart bodies are compared without their boundary,
+/// which browsers pick anew for every submission.
+fn fingerprint(req: &ServiceRequest, body: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(req.query_string());
+    hasher.update([0]);
+
+    let content_type = req.get_header::<header::ContentType>();
+    hasher.update(content_type.as_ref().map(|mime| mime.essence_str()).unwrap_or_default());
+    hasher.update([0]);
+    let boundary = content_type
+        .as_ref()
+        .filter(|mime| mime.type_() == "multipart")
+        .and_then(|mime| mime.get_param("boundary"))
+        .map(|boundary| boundary.as_str().as_bytes())
+        .filter(|boundary| !boundary.is_empty());
+
+    match boundary {
+        Some(boundary) => {
+            let mut rest = body;
+            while let Some(at) = rest.windows(boundary.len()).position(|window| window == boundary) {
+                hasher.update(&rest[..at]);
+                rest = &rest[at + boundary.len()..];
+            }
+            hasher.update(rest);
+        }
+        None => hasher.update(body),
+    }
+    hex::encode(hasher.finalize())
+}
+
+/// Records the first request for a key, or reports what happened to it.
+async fn claim(
+    pool: &SqlitePool,
+    scope: &str,
+    key: &str,
+    fingerprint: &str,
+    window: Duration,
+) -> Result<Claim, sqlx::Error> {
+    sqlx::query(
+        r#"
+        DELETE FROM idempotency_keys
+        WHERE created_at <= datetime('now', ?)
+           OR (status IS NULL AND created_at <= datetime('now', ?))
+        "#,
+    )
+    .bind(format!("-{} seconds", window.as_secs()))
+    .bind(format!("-{} seconds", IN_PROGRESS_TIMEOUT.as_secs()))
+    .execute(pool)
+    .await?;
+
+    let inserted = sqlx::query(
+        "INSERT INTO idempotency_keys (scope, key, fingerprint) VALUES (?, ?, ?) ON CONFLICT (scope, key) DO NOTHING",
+    )
+    .bind(scope)
+    .bind(key)
+    .bind(fingerprint)
+    .execute(pool)
+    .await?;
+    if inserted.rows_affected() == 1 {
+        return Ok(Claim::First);
+    }
+
+    let row = sqlx::query_as::<_, (String, Option<u16>, Option<String>, Option<Vec<u8>>)>(
+        "SELECT fingerprint, status, content_type, body FROM idempotency_keys WHERE scope = ? AND key = ?",
+    )
+    .bind(scope)
+    .bind(key)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(match row {
+        Some((stored, _, _, _)) if stored != fingerprint => Claim::Mismatch,
+        Some((_, Some(status), content_type, body)) => Claim::Replay {
+            status,
+            content_type,
+            body: body.unwrap_or_default(),
+        },
+        // Released by a failing first request in the meantime
+        Some((_, None, _, _)) | None => Claim::InProgress,
+    })
+}
+
+/// Forgets a key whose first request failed, so it can be retried.
+async fn release(pool: &SqlitePool, scope: &str, key: &str) {
+    let result = sqlx::query("DELETE FROM idempotency_keys WHERE scope = ? AND key = ?")
+        .bind(scope)
+        .bind(key)
+        .execute(pool)
+        .await;
+    if let Err(e) = result {
+        tracing::error!("Database error releasing idempotency key: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{middleware, test, App};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    async fn create(calls: web::Data<AtomicUsize>, body: web::Bytes) -> HttpResponse {
+        let call = calls.fetch_add(1, Ordering::SeqCst) + 1;
+        if body.as_ref() == b"fail" {
+            return HttpResponse::ServiceUnavailable().finish();
+        }
+        HttpResponse::Created().json(serde_json::json!({ "call": call }))
+    }
+
+    #[actix_web::test]
+    async fn test_replays_first_response_for_a_key() {
+        let pool = crate::db::test_pool().await;
+        let calls = web::Data::from(Arc::new(AtomicUsize::new(0)));
+        let pool = web::Data::new(pool);
+        let app = test::init_service(
+            App::new()
+                .app_data(pool.clone())
+                .app_data(web::Data::new(Config::default()))
+                .app_data(calls.clone())
+                .route("/create", web::post().to(create).wrap(middleware::from_fn(idempotency))),
+        )
+        .await;
+        let post = |key: Option<&str>, content_type: &str, body: &str| {
+            let mut request = test::TestRequest::post()
+                .uri("/create")
+                .insert_header((header::CONTENT_TYPE, content_type.to_string()))
+                .set_payload(body.to_string());
+            if let Some(key) = key {
+                request = request.insert_header((IDEMPOTENCY_KEY, key.to_string()));
+            }
+            request.to_request()
+        };
+
+        let first = test::call_service(&app, post(Some("k1"), "application/json", r#"{"a":1}"#)).await;
+        assert_eq!(first.status(), 201);
+        let first: serde_json::Value = test::read_body_json(first).await;
+
+        let again = test::call_service(&app, post(Some("k1"), "application/json", r#"{"a":1}"#)).await;
+        assert_eq!(again.status(), 201);
+        assert_eq!(again.headers().get("Idempotent-Replayed").unwrap(), "true");
+        let again: serde_json::Value = test::read_body_json(again).await;
+        assert_eq!(again, first);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        let reused = test::call_service(&app, post(Some("k1"), "application/json", r#"{"a":2}"#)).await;
+        assert_eq!(reused.status(), 409);
+        let invalid = test::call_service(&app, post(Some("bad key"), "application/json", "{}")).await;
+        assert_eq!(invalid.status(), 400);
+        let unkeyed = test::call_service(&app, post(None, "application/json", r#"{"a":1}"#)).await;
+        assert_eq!(unkeyed.status(), 201);
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        // Multipart resubmissions differ only in their boundary
+        let form = |boundary: &str| {
+            format!("--{b}\r\nContent-Disposition: form-data; name=\"message\"\r\n\r\nHello\r\n--{b}--\r\n", b = boundary)
+        };
+        let first = test::call_service(&app, post(Some("k2"), "multipart/form-data; boundary=aaa", &form("aaa"))).await;
+        assert_eq!(first.status(), 201);
+        let again = test::call_service(&app, post(Some("k2"), "multipart/form-data; boundary=bbb", &form("bbb"))).await;
+        assert_eq!(again.status(), 201);
+        assert!(again.headers().contains_key("Idempotent-Replayed"));
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+        // Server errors are not stored, so the request can be retried
+        let failed = test::call_service(&app, post(Some("k3"), "text/plain", "fail")).await;
+        assert_eq!(failed.status(), 503);
+        let retried = test::call_service(&app, post(Some("k3"), "text/plain", "fail")).await;
+        assert_eq!(retried.status(), 503);
+        assert_eq!(calls.load(Ordering::SeqCst), 5);
+
+        // Once the window has passed the key starts over
+        sqlx::query("UPDATE idempotency_keys SET created_at = datetime('now', '-25 hours')")
+            .execute(pool.get_ref())
+            .await
+            .unwrap();
+        let expired = test::call_service(&app, post(Some("k1"), "application/json", r#"{"a":1}"#)).await;
+        assert_eq!(expired.status(), 201);
+        assert!(!expired.headers().contains_key("Idempotent-Replayed"));
+        assert_eq!(calls.load(Ordering::SeqCst), 6);
+    }
+
+    async fn wait_for_gate(gate: web::Data<tokio::sync::Notify>) -> HttpResponse {
+        gate.notified().await;
+        HttpResponse::Created().finish()
+    }
+
+    #[actix_web::test]
+    async fn test_concurrent_duplicate_is_rejected() {
+        let pool = web::Data::new(crate::db::test_pool().await);
+        let gate = web::Data::new(tokio::sync::Notify::new());
+        let app = test::init_service(
+            App::new()
+                .app_data(pool.clone())
+                .app_data(web::Data::new(Config::default()))
+                .app_data(gate.clone())
+                .route("/slow", web::post().to(wait_for_gate).wrap(middleware::from_fn(idempotency))),
+        )
+        .await;
+        let post = || test::TestRequest::post().uri("/slow").insert_header((IDEMPOTENCY_KEY, "k")).to_request();
+
+        let first = test::call_service(&app, post());
+        let duplicate = async {
+            // Sent once the first request holds the key
+            while sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM idempotency_keys")
+                .fetch_one(pool.get_ref())
+                .await
+                .unwrap()
+                == 0
+            {
+                tokio::task::yield_now().await;
+            }
+            let response = test::call_service(&app, post()).await;
+            gate.notify_one();
+            response
+        };
+        let (first, duplicate) = futures_util::join!(first, duplicate);
+        assert_eq!(first.status(), 201);
+        assert_eq!(duplicate.status(), 409);
+
+        gate.notify_one();
+        let replayed = test::call_service(&app, post()).await;
+        assert_eq!(replayed.status(), 201);
+        assert!(replayed.headers().contains_key("Idempotent-Replayed"));
+    }
+
+    #[actix_web::test]
+    async fn test_contact_form_is_sent_once() {
+        let pool = crate::db::test_pool().await;
+        let mut config = Config {
+            mail_kind: crate::config::MailKind::Memory,
+            ..Default::default()
+        };
+        config.spam.pow_difficulty = 0;
+        let form_token = crate::spam::test_token(&config.spam);
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool.clone()))
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(crate::outbox::Outbox::new(pool.clone())))
+                .app_data(web::Data::new(crate::mail::Templates::new(None)))
+                .app_data(web::Data::new(crate::notify::Notifications::from_config(&Default::default())))
+                .route(
+                    "/email",
+                    web::post().to(crate::handlers::send_email).wrap(middleware::from_fn(idempotency)),
+                ),
+        )
+        .await;
+        let post = || {
+            test::TestRequest::post()
+                .uri("/email")
+                .insert_header((IDEMPOTENCY_KEY, "contact-1"))
+                .set_json(serde_json::json!({
+                    "sender": "visitor@example.com",
+                    "firstName": "Ada",
+                    "lastName": "Lovelace",
+                    "message": "Hello!",
+                    "formToken": form_token,
+                }))
+                .to_request()
+        };
+
+        assert_eq!(test::call_service(&app, post()).await.status(), 200);
+        let again = test::call_service(&app, post()).await;
+        assert_eq!(again.status(), 200);
+        assert!(again.headers().contains_key("Idempotent-Replayed"));
+        let queued: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM mail_outbox").fetch_one(&pool).await.unwrap();
+        assert_eq!(queued, 1);
+        let messages: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM contact_messages").fetch_one(&pool).await.unwrap();
+        assert_eq!(messages, 1);
+    }
+}
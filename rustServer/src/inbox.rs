@@ -0,0 +1,423 @@
+use actix_session::Session;
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use uuid::Uuid;
+
+use crate::auth::require_admin;
+use crate::mail::{self, ContactRouting, MailError, Templates};
+use crate::outbox::Outbox;
+use crate::validation::ContactForm;
+
+/// Longest user agent kept with a message; anything beyond is cut off.
+const MAX_USER_AGENT_LEN: usize = 512;
+
+/// A stored contact form submission. `status` is `new`, `handled` or
+/// `archived`; `delivery` is the outbox status of the mail sent for it,
+/// or the provider's `delivered`, `bounced` or `complained` once reported.
+#[derive(Serialize)]
+pub struct ContactMessage {
+    pub id: String,
+    pub sender: String,
+    pub first_name: String,
+    pub last_name: String,
+    pub message: String,
+    pub topic: Option<String>,
+    pub ip_hash: String,
+    pub user_agent: Option<String>,
+    pub status: String,
+    pub delivery: Option<String>,
+    pub read_at: Option<String>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+#[derive(Deserialize)]
+pub struct ListMessagesQuery {
+    /// Only messages with this status; all but archived ones when unset
+    pub status: Option<String>,
+}
+
+type MessageRow = (
+    String,
+    String,
+    String,
+    String,
+    String,
+    Option<String>,
+    String,
+    Option<String>,
+    String,
+    Option<String>,
+    Option<String>,
+    String,
+    String,
+);
+
+const SELECT_MESSAGES: &str = r#"
+    SELECT m.id, m.sender, m.first_name, m.last_name, m.message, m.topic, m.ip_hash, m.user_agent,
+           m.status, COALESCE(o.delivery_status, o.status), m.read_at, m.created_at, m.updated_at
+    FROM contact_messages m
+    LEFT JOIN mail_outbox o ON o.id = m.outbox_id
+"#;
+
+impl From<MessageRow> for ContactMessage {
+    fn from(row: MessageRow) -> Self {
+        let (
+            id,
+            sender,
+            first_name,
+            last_name,
+            message,
+            topic,
+            ip_hash,
+            user_agent,
+            status,
+            delivery,
+            read_at,
+            created_at,
+            updated_at,
+        ) = row;
+        Self {
+            id,
+            sender,
+            first_name,
+            last_name,
+            message,
+            topic,
+            ip_hash,
+            user_agent,
+            status,
+            delivery,
+            read_at,
+            created_at,
+            updated_at,
+        }
+    }
+}
+
+/// The request's `User-Agent`, truncated to a sensible length.
+pub fn user_agent(req: &HttpRequest) -> Option<String> {
+    req.headers()
+        .get(actix_web::http::header::USER_AGENT)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.chars().take(MAX_USER_AGENT_LEN).collect())
+}
+
+/// Stores a submission before any delivery is attempted, returning its ID.
+async fn record(
+    pool: &SqlitePool,
+    form: &ContactForm,
+    ip_hash: &str,
+    user_agent: Option<&str>,
+) -> Result<String, sqlx::Error> {
+    let id = Uuid::new_v4().to_string();
+    sqlx::query(
+        r#"
+        INSERT INTO contact_messages (id, sender, first_name, last_name, message, topic, ip_hash, user_agent)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+        "#,
+    )
+    .bind(&id)
+    .bind(&form.sender)
+    .bind(&form.first_name)
+    .bind(&form.last_name)
+    .bind(&form.message)
+    .bind(&form.topic)
+    .bind(ip_hash)
+    .bind(user_agent)
+    .execute(pool)
+    .await?;
+    Ok(id)
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum SubmitError {
+    #[error("Database error: {0}")]
+    Database(#[from] sqlx::Error),
+    #[error(transparent)]
+    Mail(#[from] MailError),
+}
+
+/// Stores a submission and queues the mail to the site owner. The mail is
+/// rendered first, so nothing is stored if the templates fail. Once stored,
+/// the message is kept even if it cannot be queued.
+pub async fn submit(
+    pool: &SqlitePool,
+    outbox: &Outbox,
+    templates: &Templates,
+    routing: &ContactRouting,
+    form: &ContactForm,
+    ip_hash: &str,
+    user_agent: Option<&str>,
+) -> Result<String, SubmitError> {
+    let message = mail::contact_message(templates, routing, form)?;
+    let id = record(pool, form, ip_hash, user_agent).await?;
+
+    let queued = match outbox.enqueue(&message).await {
+        Ok(outbox_id) => {
+            sqlx::query("UPDATE contact_messages SET outbox_id = ? WHERE id = ?")
+                .bind(outbox_id)
+                .bind(&id)
+                .execute(pool)
+                .await
+        }
+        Err(e) => Err(e),
+    };
+    if let Err(e) = queued {
+        tracing::error!("Stored contact message {} but could not queue it: {}", id, e);
+    }
+    Ok(id)
+}
+
+/// Lists contact messages, newest first.
+pub async fn list_messages(
+    pool: web::Data<SqlitePool>,
+    session: Session,
+    query: web::Query<ListMessagesQuery>,
+) -> HttpResponse {
+    if let Err(response) = require_admin(pool.get_ref(), &session).await {
+        return response;
+    }
+
+    let rows = match query.status.as_deref() {
+        Some(status @ ("new" | "handled" | "archived")) => {
+            sqlx::query_as::<_, MessageRow>(&format!(
+                "{} WHERE m.status = ? ORDER BY m.created_at DESC, m.id",
+                SELECT_MESSAGES
+            ))
+            .bind(status)
+            .fetch_all(pool.get_ref())
+            .await
+        }
+        Some(_) => return HttpResponse::BadRequest().body("status must be one of new, handled, archived"),
+        None => {
+            sqlx::query_as::<_, MessageRow>(&format!(
+                "{} WHERE m.status != 'archived' ORDER BY m.created_at DESC, m.id",
+                SELECT_MESSAGES
+            ))
+            .fetch_all(pool.get_ref())
+            .await
+        }
+    };
+
+    match rows {
+        Ok(rows) => HttpResponse::Ok().json(rows.into_iter().map(ContactMessage::from).collect::<Vec<_>>()),
+        Err(e) => {
+            tracing::error!("Database error listing messages: {}", e);
+            HttpResponse::InternalServerError().body("Database error")
+        }
+    }
+}
+
+/// Returns one message, marking it read.
+pub async fn get_message(
+    pool: web::Data<SqlitePool>,
+    session: Session,
+    path: web::Path<String>,
+) -> HttpResponse {
+    if let Err(response) = require_admin(pool.get_ref(), &session).await {
+        return response;
+    }
+    let id = path.into_inner();
+
+    if let Err(e) = sqlx::query("UPDATE contact_messages SET read_at = datetime('now') WHERE id = ? AND read_at IS NULL")
+        .bind(&id)
+        .execute(pool.get_ref())
+        .await
+    {
+        tracing::error!("Database error marking message read: {}", e);
+        return HttpResponse::InternalServerError().body("Database error");
+    }
+
+    let row = sqlx::query_as::<_, MessageRow>(&format!("{} WHERE m.id = ?", SELECT_MESSAGES))
+        .bind(&id)
+        .fetch_optional(pool.get_ref())
+        .await;
+
+    match row {
+        Ok(Some(row)) => HttpResponse::Ok().json(ContactMessage::from(row)),
+        Ok(None) => HttpResponse::NotFound().body("Message not found"),
+        Err(e) => {
+            tracing::error!("Database error reading message: {}", e);
+            HttpResponse::InternalServerError().body("Database error")
+        }
+    }
+}
+
+/// Marks a message as handled.
+pub async fn mark_handled(
+    pool: web::Data<SqlitePool>,
+    session: Session,
+    path: web::Path<String>,
+) -> HttpResponse {
+    set_status(pool, session, path, "handled").await
+}
+
+/// Moves a message out of the default inbox listing.
+pub async fn archive_message(
+    pool: web::Data<SqlitePool>,
+    session: Session,
+    path: web::Path<String>,
+) -> HttpResponse {
+    set_status(pool, session, path, "archived").await
+}
+
+async fn set_status(
+    pool: web::Data<SqlitePool>,
+    session: Session,
+    path: web::Path<String>,
+    status: &str,
+) -> HttpResponse {
+    if let Err(response) = require_admin(pool.get_ref(), &session).await {
+        return response;
+    }
+
+    match sqlx::query("UPDATE contact_messages SET status = ?, updated_at = datetime('now') WHERE id = ?")
+        .bind(status)
+        .bind(path.into_inner())
+        .execute(pool.get_ref())
+        .await
+    {
+        Ok(r) if r.rows_affected() == 0 => HttpResponse::NotFound().body("Message not found"),
+        Ok(_) => HttpResponse::NoContent().finish(),
+        Err(e) => {
+            tracing::error!("Database error updating message: {}", e);
+            HttpResponse::InternalServerError().body("Database error")
+        }
+    }
+}
+
+/// Permanently deletes a message.
+pub async fn delete_message(
+    pool: web::Data<SqlitePool>,
+    session: Session,
+    path: web::Path<String>,
+) -> HttpResponse {
+    if let Err(response) = require_admin(pool.get_ref(), &session).await {
+        return response;
+    }
+
+    match sqlx::query("DELETE FROM contact_messages WHERE id = ?")
+        .bind(path.into_inner())
+        .execute(pool.get_ref())
+        .await
+    {
+        Ok(r) if r.rows_affected() == 0 => HttpResponse::NotFound().body("Message not found"),
+        Ok(_) => HttpResponse::NoContent().finish(),
+        Err(e) => {
+            tracing::error!("Database error deleting message: {}", e);
+            HttpResponse::InternalServerError().body("Database error")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_session::{storage::CookieSessionStore, SessionMiddleware};
+    use actix_web::{cookie::Key, test, App};
+
+    async fn login_admin(session: Session) -> HttpResponse {
+        session.insert("user_id", "admin").unwrap();
+        HttpResponse::Ok().finish()
+    }
+
+    #[tokio::test]
+    async fn test_submit_reports_template_errors() {
+        let pool = crate::db::test_pool().await;
+        let dir = std::env::temp_dir().join(format!("templates-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("contact.txt"), "{% if %}").unwrap();
+        let form = ContactForm {
+            sender: "visitor@example.com".into(),
+            first_name: "Ada".into(),
+            last_name: "Lovelace".into(),
+            message: "Hello!".into(),
+            topic: None,
+            attachments: Vec::new(),
+        };
+
+        let result = submit(
+            &pool,
+            &Outbox::new(pool.clone()),
+            &Templates::new(Some(dir.clone())),
+            &ContactRouting::default(),
+            &form,
+            "hash",
+            None,
+        )
+        .await;
+        assert!(matches!(result, Err(SubmitError::Mail(MailError::Template(_)))));
+        let stored: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM contact_messages").fetch_one(&pool).await.unwrap();
+        assert_eq!(stored, 0);
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[actix_web::test]
+    async fn test_admin_inbox_workflow() {
+        let pool = crate::db::test_pool().await;
+        sqlx::query("INSERT INTO users (id, username, password_hash, is_admin) VALUES ('admin', 'admin', 'x', 1)")
+            .execute(&pool)
+            .await
+            .unwrap();
+        let form = ContactForm {
+            sender: "visitor@example.com".into(),
+            first_name: "Ada".into(),
+            last_name: "Lovelace".into(),
+            message: "Hello!".into(),
+            topic: None,
+            attachments: Vec::new(),
+        };
+        let id = record(&pool, &form, "hash", Some("test-agent")).await.unwrap();
+
+        let app = test::init_service(
+            App::new()
+                .wrap(SessionMiddleware::new(CookieSessionStore::default(), Key::generate()))
+                .app_data(web::Data::new(pool.clone()))
+                .route("/login", web::post().to(login_admin))
+                .route("/messages", web::get().to(list_messages))
+                .route("/messages/{id}", web::get().to(get_message))
+                .route("/messages/{id}", web::delete().to(delete_message))
+                .route("/messages/{id}/handled", web::post().to(mark_handled))
+                .route("/messages/{id}/archive", web::post().to(archive_message)),
+        )
+        .await;
+
+        let response = test::call_service(&app, test::TestRequest::get().uri("/messages").to_request()).await;
+        assert_eq!(response.status(), 401);
+
+        let response = test::call_service(&app, test::TestRequest::post().uri("/login").to_request()).await;
+        let cookie = response.response().cookies().next().unwrap().into_owned();
+        let get = |uri: String| test::TestRequest::get().uri(&uri).cookie(cookie.clone()).to_request();
+        let post = |uri: String| test::TestRequest::post().uri(&uri).cookie(cookie.clone()).to_request();
+
+        let listed: Vec<serde_json::Value> = test::call_and_read_body_json(&app, get("/messages".into())).await;
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0]["status"], "new");
+        assert_eq!(listed[0]["user_agent"], "test-agent");
+        assert!(listed[0]["read_at"].is_null());
+
+        let read: serde_json::Value = test::call_and_read_body_json(&app, get(format!("/messages/{}", id))).await;
+        assert!(read["read_at"].is_string());
+
+        let response = test::call_service(&app, post(format!("/messages/{}/handled", id))).await;
+        assert_eq!(response.status(), 204);
+        let handled: Vec<serde_json::Value> =
+            test::call_and_read_body_json(&app, get("/messages?status=handled".into())).await;
+        assert_eq!(handled.len(), 1);
+
+        test::call_service(&app, post(format!("/messages/{}/archive", id))).await;
+        let listed: Vec<serde_json::Value> = test::call_and_read_body_json(&app, get("/messages".into())).await;
+        assert!(listed.is_empty());
+
+        let delete = test::TestRequest::delete()
+            .uri(&format!("/messages/{}", id))
+            .cookie(cookie.clone())
+            .to_request();
+        assert_eq!(test::call_service(&app, delete).await.status(), 204);
+        let response = test::call_service(&app, get(format!("/messages/{}", id))).await;
+        assert_eq!(response.status(), 404);
+    }
+}
@@ -0,0 +1,117 @@
+use async_trait::async_trait;
+use chrono::Utc;
+use std::path::PathBuf;
+use uuid::Uuid;
+
+use super::{to_rfc5322, EmailMessage, MailError, MailTransport};
+
+/// Layout of the directory messages are written to.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FileFormat {
+    /// One `.eml` file per message directly in the directory
+    #[default]
+    Eml,
+    /// A Maildir (`tmp/`, `new/`, `cur/`) that mail clients can open
+    Maildir,
+}
+
+/// Writes messages to disk instead of sending them, for development setups
+/// without a mail provider.
+pub struct FileTransport {
+    dir: PathBuf,
+    format: FileFormat,
+}
+
+impl FileTransport {
+    pub fn new(dir: impl Into<PathBuf>, format: FileFormat) -> Self {
+        Self {
+            dir: dir.into(),
+            format,
+        }
+    }
+}
+
+#[async_trait]
+impl MailTransport for FileTransport {
+    async fn send(&self, message: &EmailMessage) -> Result<Option<String>, MailError> {
+        let raw = to_rfc5322(message)?.formatted();
+        let now = Utc::now();
+
+        match self.format {
+            FileFormat::Eml => {
+                tokio::fs::create_dir_all(&self.dir).await?;
+                let name = format!("{}-{}.eml", now.format("%Y%m%dT%H%M%S"), Uuid::new_v4());
+                tokio::fs::write(self.dir.join(name), raw).await?;
+            }
+            FileFormat::Maildir => {
+                for sub in ["tmp", "new", "cur"] {
+                    tokio::fs::create_dir_all(self.dir.join(sub)).await?;
+                }
+                // Delivered to tmp/ first so readers of new/ never see a partial message
+                let name = format!("{}.{}.server", now.timestamp(), Uuid::new_v4().simple());
+                let tmp = self.dir.join("tmp").join(&name);
+                tokio::fs::write(&tmp, raw).await?;
+                tokio::fs::rename(&tmp, self.dir.join("new").join(&name)).await?;
+            }
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mail::Attachment;
+
+    #[tokio::test]
+    async fn test_file_transport_writes_eml_and_maildir() {
+        let root = std::env::temp_dir().join(format!("mail-{}", Uuid::new_v4()));
+        let message = EmailMessage {
+            from: "Site <noreply@example.com>".into(),
+            to: vec!["owner@example.com".into()],
+            cc: vec!["copy@example.com".into()],
+            reply_to: Some("visitor@example.com".into()),
+            subject: "Hello".into(),
+            text: "Body text".into(),
+            ..Default::default()
+        };
+
+        FileTransport::new(root.join("eml"), FileFormat::Eml).send(&message).await.unwrap();
+        FileTransport::new(root.join("maildir"), FileFormat::Maildir).send(&message).await.unwrap();
+
+        for dir in [root.join("eml"), root.join("maildir/new")] {
+            let entry = std::fs::read_dir(&dir).unwrap().next().unwrap().unwrap();
+            let raw = std::fs::read_to_string(entry.path()).unwrap();
+            assert!(raw.contains("Subject: Hello\r\n"));
+            assert!(raw.contains("To: owner@example.com\r\n"));
+            assert!(raw.contains("Cc: copy@example.com\r\n"));
+            assert!(raw.contains("Reply-To: visitor@example.com\r\n"));
+            assert!(raw.ends_with("Body text"));
+        }
+        assert_eq!(std::fs::read_dir(root.join("maildir/tmp")).unwrap().count(), 0);
+
+        std::fs::remove_dir_all(&root).ok();
+    }
+
+    #[test]
+    fn test_attachments_become_mime_parts() {
+        let message = EmailMessage {
+            from: "Site <noreply@example.com>".into(),
+            to: vec!["owner@example.com".into()],
+            subject: "Hello".into(),
+            text: "Body text".into(),
+            html: Some("<p>Body text</p>".into()),
+            attachments: vec![Attachment {
+                filename: "notes.txt".into(),
+                content_type: "text/plain".into(),
+                content: b"Notes".to_vec(),
+            }],
+            ..Default::default()
+        };
+
+        let raw = String::from_utf8(to_rfc5322(&message).unwrap().formatted()).unwrap();
+        assert!(raw.contains("Content-Type: multipart/mixed"));
+        assert!(raw.contains("Content-Type: multipart/alternative"));
+        assert!(raw.contains("Content-Disposition: attachment; filename=\"notes.txt\""));
+    }
+}
@@ -0,0 +1,29 @@
+use async_trait::async_trait;
+use std::sync::Mutex;
+
+use super::{EmailMessage, MailError, MailTransport};
+
+/// Keeps sent messages in memory instead of delivering them. Intended for tests.
+#[derive(Default)]
+pub struct MemoryTransport {
+    sent: Mutex<Vec<EmailMessage>>,
+}
+
+impl MemoryTransport {
+    /// Messages sent so far, oldest first.
+    #[allow(dead_code)]
+    pub fn sent(&self) -> Vec<EmailMessage> {
+        self.sent.lock().expect("memory transport lock poisoned").clone()
+    }
+}
+
+#[async_trait]
+impl MailTransport for MemoryTransport {
+    async fn send(&self, message: &EmailMessage) -> Result<Option<String>, MailError> {
+        self.sent
+            .lock()
+            .expect("memory transport lock poisoned")
+            .push(message.clone());
+        Ok(None)
+    }
+}
@@ -0,0 +1,188 @@
+mod file;
+mod memory;
+mod resend;
+mod routing;
+mod smtp;
+mod templates;
+mod webhooks;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use thiserror::Error;
+
+use crate::config::{Config, MailKind};
+use crate::validation::ContactForm;
+
+pub use file::{FileFormat, FileTransport};
+pub use memory::MemoryTransport;
+pub use resend::ResendTransport;
+pub use routing::{parse_address_list, ContactRouting, Recipients};
+pub use smtp::{SmtpConfig, SmtpSecurity, SmtpTransport};
+pub use templates::{list_templates, preview_template, Templates};
+pub use webhooks::receive_webhook;
+
+#[derive(Error, Debug)]
+pub enum MailError {
+    #[error("Failed to send email: {0}")]
+    SendError(String),
+    #[error("Request error: {0}")]
+    RequestError(#[from] reqwest::Error),
+    #[error("Mail transport not configured: {0}")]
+    NotConfigured(String),
+    #[error("Invalid message: {0}")]
+    InvalidMessage(String),
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Template error: {0}")]
+    Template(String),
+}
+
+/// An email with a plain-text body and optional HTML alternative,
+/// independent of the transport delivering it.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EmailMessage {
+    pub from: String,
+    pub to: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub cc: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub bcc: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reply_to: Option<String>,
+    pub subject: String,
+    pub text: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub html: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub attachments: Vec<Attachment>,
+}
+
+/// A file attached to an email. The content is stored base64-encoded when
+/// the message is serialized, as in the outbox.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Attachment {
+    pub filename: String,
+    pub content_type: String,
+    #[serde(with = "base64_content")]
+    pub content: Vec<u8>,
+}
+
+mod base64_content {
+    use base64::{engine::general_purpose::STANDARD, Engine};
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(content: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&STANDARD.encode(content))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let encoded = String::deserialize(deserializer)?;
+        STANDARD.decode(encoded).map_err(serde::de::Error::custom)
+    }
+}
+
+/// Delivers email. Implementations are shared between requests, so any
+/// connection or client state is reused rather than rebuilt per message.
+#[async_trait]
+pub trait MailTransport: Send + Sync {
+    /// Sends a message, returning the provider's ID for it when there is
+    /// one; delivery webhooks refer to messages by that ID.
+    async fn send(&self, message: &EmailMessage) -> Result<Option<String>, MailError>;
+}
+
+/// Builds the transport selected by `MAIL_TRANSPORT`.
+pub fn from_config(config: &Config) -> Result<Arc<dyn MailTransport>, MailError> {
+    Ok(match &config.mail_kind {
+        MailKind::Resend => Arc::new(ResendTransport::new(config.mail_api_key.clone())),
+        MailKind::Smtp(smtp) => Arc::new(SmtpTransport::new(smtp)?),
+        MailKind::File(format) => Arc::new(FileTransport::new(config.mail_file_dir(), *format)),
+        MailKind::Memory => Arc::new(MemoryTransport::default()),
+    })
+}
+
+/// The message sent to the site owner for a contact form submission,
+/// rendered from the `contact` templates and routed by its topic. Replies
+/// go to the visitor. Topics no longer configured use the default recipients.
+pub fn contact_message(
+    templates: &Templates,
+    routing: &ContactRouting,
+    form: &ContactForm,
+) -> Result<EmailMessage, MailError> {
+    let rendered = templates.render(
+        "contact",
+        serde_json::json!({
+            "sender": form.sender,
+            "first_name": form.first_name,
+            "last_name": form.last_name,
+            "message": form.message,
+            "topic": form.topic,
+        }),
+    )?;
+    let recipients = routing
+        .recipients(form.topic.as_deref())
+        .unwrap_or(&routing.default);
+    Ok(EmailMessage {
+        from: routing.from.clone(),
+        to: recipients.to.clone(),
+        cc: recipients.cc.clone(),
+        bcc: recipients.bcc.clone(),
+        reply_to: Some(form.sender.clone()),
+        subject: rendered.subject,
+        text: rendered.text,
+        html: Some(rendered.html),
+        attachments: form.attachments.clone(),
+    })
+}
+
+/// Renders a message as RFC 5322 for transports that deal in raw mail.
+fn to_rfc5322(message: &EmailMessage) -> Result<lettre::Message, MailError> {
+    use lettre::message::{header::ContentType, MultiPart, SinglePart};
+
+    let parse = |address: &str| {
+        address
+            .parse::<lettre::message::Mailbox>()
+            .map_err(|e| MailError::InvalidMessage(format!("{}: {}", address, e)))
+    };
+
+    let mut builder = lettre::Message::builder()
+        .from(parse(&message.from)?)
+        .subject(message.subject.clone());
+    for to in &message.to {
+        builder = builder.to(parse(to)?);
+    }
+    for cc in &message.cc {
+        builder = builder.cc(parse(cc)?);
+    }
+    for bcc in &message.bcc {
+        builder = builder.bcc(parse(bcc)?);
+    }
+    if let Some(reply_to) = &message.reply_to {
+        builder = builder.reply_to(parse(reply_to)?);
+    }
+
+    let result = match (&message.html, message.attachments.is_empty()) {
+        (None, true) => builder
+            .header(lettre::message::header::ContentType::TEXT_PLAIN)
+            .body(message.text.clone()),
+        (Some(html), true) => builder.multipart(MultiPart::alternative_plain_html(message.text.clone(), html.clone())),
+        (html, false) => {
+            let mut mixed = match html {
+                Some(html) => {
+                    MultiPart::mixed().multipart(MultiPart::alternative_plain_html(message.text.clone(), html.clone()))
+                }
+                None => MultiPart::mixed().singlepart(SinglePart::plain(message.text.clone())),
+            };
+            for attachment in &message.attachments {
+                let content_type = ContentType::parse(&attachment.content_type)
+                    .map_err(|e| MailError::InvalidMessage(format!("{}: {}", attachment.content_type, e)))?;
+                mixed = mixed.singlepart(
+                    lettre::message::Attachment::new(attachment.filename.clone())
+                        .body(attachment.content.clone(), content_type),
+                );
+            }
+            builder.multipart(mixed)
+        }
+    };
+    result.map_err(|e| MailError::InvalidMessage(e.to_string()))
+}
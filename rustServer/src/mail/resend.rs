@@ -0,0 +1,170 @@
+use async_trait::async_trait;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+
+use super::{EmailMessage, MailError, MailTransport};
+
+const RESEND_ENDPOINT: &str = "https://api.resend.com/emails";
+
+#[derive(Serialize)]
+struct ResendEmail<'a> {
+    from: &'a str,
+    to: &'a [String],
+    #[serde(skip_serializing_if = "<[String]>::is_empty")]
+    cc: &'a [String],
+    #[serde(skip_serializing_if = "<[String]>::is_empty")]
+    bcc: &'a [String],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reply_to: Option<&'a str>,
+    subject: &'a str,
+    text: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    html: Option<&'a str>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    attachments: Vec<ResendAttachment<'a>>,
+}
+
+/// Resend takes attachment content as a base64 string.
+#[derive(Serialize)]
+struct ResendAttachment<'a> {
+    filename: &'a str,
+    content: String,
+    content_type: &'a str,
+}
+
+#[derive(Deserialize)]
+struct ResendResponse {
+    id: String,
+}
+
+/// Sends through the Resend HTTP API.
+pub struct ResendTransport {
+    api_key: Option<String>,
+    endpoint: String,
+    client: reqwest::Client,
+}
+
+impl ResendTransport {
+    /// Without an API key every send fails with [`MailError::NotConfigured`].
+    pub fn new(api_key: Option<String>) -> Self {
+        Self::with_endpoint(api_key, RESEND_ENDPOINT)
+    }
+
+    pub fn with_endpoint(api_key: Option<String>, endpoint: impl Into<String>) -> Self {
+        Self {
+            api_key,
+            endpoint: endpoint.into(),
+            client: reqwest::Client::new(),
+        }
+    }
+}
+
+#[async_trait]
+impl MailTransport for ResendTransport {
+    async fn send(&self, message: &EmailMessage) -> Result<Option<String>, MailError> {
+        let api_key = self
+            .api_key
+            .as_deref()
+            .ok_or_else(|| MailError::NotConfigured("MAIL_API_KEY not set".into()))?;
+
+        let email = ResendEmail {
+            from: &message.from,
+            to: &message.to,
+            cc: &message.cc,
+            bcc: &message.bcc,
+            reply_to: message.reply_to.as_deref(),
+            subject: &message.subject,
+            text: &message.text,
+            html: message.html.as_deref(),
+            attachments: message
+                .attachments
+                .iter()
+                .map(|attachment| ResendAttachment {
+                    filename: &attachment.filename,
+                    content: base64::engine::general_purpose::STANDARD.encode(&attachment.content),
+                    content_type: &attachment.content_type,
+                })
+                .collect(),
+        };
+
+        let response = self
+            .client
+            .post(&self.endpoint)
+            .header("Authorization", format!("Bearer {}", api_key))
+            .json(&email)
+            .send()
+            .await?;
+
+        if response.status().is_success() {
+            Ok(response.json::<ResendResponse>().await.ok().map(|r| r.id))
+        } else {
+            let error_text = response.text().await.unwrap_or_default();
+            tracing::error!("Failed to Send Email Message: {}", error_text);
+            Err(MailError::SendError(error_text))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mail::Attachment;
+    use wiremock::matchers::{body_json, header, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[tokio::test]
+    async fn test_resend_posts_message() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/emails"))
+            .and(header("Authorization", "Bearer key"))
+            .and(body_json(serde_json::json!({
+                "from": "a@example.com",
+                "to": ["b@example.com"],
+                "bcc": ["c@example.com"],
+                "reply_to": "visitor@example.com",
+                "subject": "Hi",
+                "text": "Hello",
+                "html": "<p>Hello</p>",
+                "attachments": [{ "filename": "cv.txt", "content": "Q1Y=", "content_type": "text/plain" }],
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({ "id": "msg-1" })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(422).set_body_string("bad"))
+            .mount(&server)
+            .await;
+
+        let message = EmailMessage {
+            from: "a@example.com".into(),
+            to: vec!["b@example.com".into()],
+            bcc: vec!["c@example.com".into()],
+            reply_to: Some("visitor@example.com".into()),
+            subject: "Hi".into(),
+            text: "Hello".into(),
+            html: Some("<p>Hello</p>".into()),
+            attachments: vec![Attachment {
+                filename: "cv.txt".into(),
+                content_type: "text/plain".into(),
+                content: b"CV".to_vec(),
+            }],
+            ..Default::default()
+        };
+        let endpoint = format!("{}/emails", server.uri());
+        let id = ResendTransport::with_endpoint(Some("key".into()), &endpoint)
+            .send(&message)
+            .await
+            .unwrap();
+        assert_eq!(id.as_deref(), Some("msg-1"));
+
+        let rejected = ResendTransport::with_endpoint(Some("other".into()), &endpoint)
+            .send(&message)
+            .await;
+        assert!(matches!(rejected, Err(MailError::SendError(body)) if body == "bad"));
+
+        let unconfigured = ResendTransport::with_endpoint(None, &endpoint).send(&message).await;
+        assert!(matches!(unconfigured, Err(MailError::NotConfigured(_))));
+    }
+}
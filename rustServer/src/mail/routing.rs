@@ -0,0 +1,94 @@
+use std::collections::BTreeMap;
+
+/// Where a contact message goes.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Recipients {
+    pub to: Vec<String>,
+    pub cc: Vec<String>,
+    pub bcc: Vec<String>,
+}
+
+/// Sender and recipients of contact mail (`MAIL_FROM`, `CONTACT_TO`,
+/// `CONTACT_CC`, `CONTACT_BCC`), with per-topic overrides
+/// (`CONTACT_TOPICS`, `CONTACT_TO_<TOPIC>`, ...).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ContactRouting {
+    pub from: String,
+    pub default: Recipients,
+    /// Recipients for submissions with a `topic`, keyed by lowercase topic name
+    pub topics: BTreeMap<String, Recipients>,
+}
+
+impl Default for ContactRouting {
+    fn default() -> Self {
+        Self {
+            from: "Logan Carpenter <noreply@logancarpenter.space>".to_string(),
+            default: Recipients {
+                to: vec!["LoganTCarpenter@gmail.com".to_string()],
+                ..Default::default()
+            },
+            topics: BTreeMap::new(),
+        }
+    }
+}
+
+impl ContactRouting {
+    /// Recipients for a submission, or `None` if its topic is not configured.
+    pub fn recipients(&self, topic: Option<&str>) -> Option<&Recipients> {
+        match topic {
+            None => Some(&self.default),
+            Some(topic) => self.topics.get(topic),
+        }
+    }
+
+    /// Whether `address` is the sender or one of the configured recipients,
+    /// i.e. the site's own rather than a visitor's.
+    pub fn is_own_address(&self, address: &str) -> bool {
+        let address = crate::suppressions::normalize(address);
+        std::iter::once(&self.from)
+            .chain(std::iter::once(&self.default).chain(self.topics.values()).flat_map(|recipients| {
+                recipients.to.iter().chain(&recipients.cc).chain(&recipients.bcc)
+            }))
+            .any(|own| crate::suppressions::normalize(own) == address)
+    }
+}
+
+/// Splits a comma-separated address list, checking each address.
+pub fn parse_address_list(list: &str) -> Result<Vec<String>, String> {
+    list.split(',')
+        .map(str::trim)
+        .filter(|address| !address.is_empty())
+        .map(|address| {
+            address
+                .parse::<lettre::message::Mailbox>()
+                .map(|_| address.to_string())
+                .map_err(|e| format!("{}: {}", address, e))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_topic_routing_and_address_lists() {
+        let support = Recipients {
+            to: parse_address_list("Help <help@example.com>, ops@example.com").unwrap(),
+            ..Default::default()
+        };
+        let routing = ContactRouting {
+            topics: BTreeMap::from([("support".to_string(), support.clone())]),
+            ..Default::default()
+        };
+
+        assert_eq!(routing.recipients(None), Some(&routing.default));
+        assert_eq!(routing.recipients(Some("support")), Some(&support));
+        assert_eq!(routing.recipients(Some("sales")), None);
+        assert_eq!(support.to, ["Help <help@example.com>", "ops@example.com"]);
+        assert!(parse_address_list("ok@example.com, not an address").is_err());
+        assert!(routing.is_own_address("OPS@example.com"));
+        assert!(routing.is_own_address("noreply@logancarpenter.space"));
+        assert!(!routing.is_own_address("visitor@example.com"));
+    }
+}
@@ -0,0 +1,66 @@
+use async_trait::async_trait;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Tokio1Executor};
+
+use super::{to_rfc5322, EmailMessage, MailError, MailTransport};
+
+/// How the SMTP connection is secured.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SmtpSecurity {
+    /// Upgrade a plain connection with STARTTLS (port 587 by default)
+    #[default]
+    StartTls,
+    /// TLS from the first byte (port 465 by default)
+    Tls,
+    /// No encryption; only for local relays (port 25 by default)
+    None,
+}
+
+#[derive(Clone, Debug)]
+pub struct SmtpConfig {
+    pub host: String,
+    /// Defaults to the standard port for `security`
+    pub port: Option<u16>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub security: SmtpSecurity,
+}
+
+/// Sends through an SMTP relay over a pool of reused connections.
+pub struct SmtpTransport {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+}
+
+impl SmtpTransport {
+    pub fn new(config: &SmtpConfig) -> Result<Self, MailError> {
+        let invalid = |e: lettre::transport::smtp::Error| MailError::NotConfigured(format!("SMTP: {}", e));
+
+        let mut builder = match config.security {
+            SmtpSecurity::StartTls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&config.host).map_err(invalid)?,
+            SmtpSecurity::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(&config.host).map_err(invalid)?,
+            SmtpSecurity::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&config.host),
+        };
+        if let Some(port) = config.port {
+            builder = builder.port(port);
+        }
+        if let (Some(username), Some(password)) = (&config.username, &config.password) {
+            builder = builder.credentials(Credentials::new(username.clone(), password.clone()));
+        }
+
+        Ok(Self {
+            transport: builder.build(),
+        })
+    }
+}
+
+#[async_trait]
+impl MailTransport for SmtpTransport {
+    async fn send(&self, message: &EmailMessage) -> Result<Option<String>, MailError> {
+        let email = to_rfc5322(message)?;
+        self.transport
+            .send(email)
+            .await
+            .map_err(|e| MailError::SendError(e.to_string()))?;
+        Ok(None)
+    }
+}
@@ -0,0 +1,195 @@
+use actix_session::Session;
+use actix_web::{web, HttpResponse};
+use minijinja::Environment;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use std::path::PathBuf;
+
+use super::MailError;
+use crate::auth::require_admin;
+
+/// Name shown in subjects, layouts and footers.
+const SITE_NAME: &str = "Logan0Dev";
+
+/// Templates compiled into the binary; a file of the same name in the
+/// template directory takes precedence.
+const EMBEDDED: &[(&str, &str)] = &[
+    ("layout.html", include_str!("templates/layout.html")),
+    ("layout.txt", include_str!("templates/layout.txt")),
+    ("contact.subject.txt", include_str!("templates/contact.subject.txt")),
+    ("contact.html", include_str!("templates/contact.html")),
+    ("contact.txt", include_str!("templates/contact.txt")),
+    ("auto_reply.subject.txt", include_str!("templates/auto_reply.subject.txt")),
+    ("auto_reply.html", include_str!("templates/auto_reply.html")),
+    ("auto_reply.txt", include_str!("templates/auto_reply.txt")),
+];
+
+/// Emails that can be previewed with [`sample_data`].
+const PREVIEWS: &[&str] = &["contact", "auto_reply"];
+
+/// A rendered email: `{name}.subject.txt`, `{name}.html` and `{name}.txt`.
+#[derive(Debug, Serialize)]
+pub struct RenderedEmail {
+    pub subject: String,
+    pub html: String,
+    pub text: String,
+}
+
+/// Email templates rendered with MiniJinja. `.html` templates are
+/// auto-escaped and may extend `layout.html`; `.txt` ones `layout.txt`.
+pub struct Templates {
+    env: Environment<'static>,
+}
+
+#[derive(Deserialize)]
+pub struct PreviewQuery {
+    /// `html` or `text` to get that part alone; all parts as JSON by default
+    pub format: Option<String>,
+}
+
+impl Templates {
+    /// Templates from `dir` (`MAIL_TEMPLATE_DIR`), falling back to the embedded ones.
+    pub fn new(dir: Option<PathBuf>) -> Self {
+        let mut env = Environment::new();
+        env.add_global("site_name", SITE_NAME);
+        env.set_loader(move |name| {
+            if let Some(dir) = &dir {
+                // Names come from this module, but never leave the directory regardless
+                if !name.contains("..") {
+                    match std::fs::read_to_string(dir.join(name)) {
+                        Ok(source) => return Ok(Some(source)),
+                        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                        Err(e) => {
+                            return Err(minijinja::Error::new(
+                                minijinja::ErrorKind::InvalidOperation,
+                                format!("could not read template {}: {}", name, e),
+                            ))
+                        }
+                    }
+                }
+            }
+            Ok(EMBEDDED
+                .iter()
+                .find(|(embedded, _)| *embedded == name)
+                .map(|(_, source)| source.to_string()))
+        });
+        Self { env }
+    }
+
+    /// Renders the subject, HTML and text parts of the email called `name`.
+    pub fn render(&self, name: &str, context: impl Serialize) -> Result<RenderedEmail, MailError> {
+        let context = minijinja::Value::from_serialize(context);
+        let render = |suffix: &str| {
+            self.env
+                .get_template(&format!("{}.{}", name, suffix))
+                .and_then(|template| template.render(&context))
+                .map_err(|e| MailError::Template(format!("{}.{}: {:#}", name, suffix, e)))
+        };
+
+        Ok(RenderedEmail {
+            // A subject is a single header line whatever the template produces
+            subject: crate::validation::single_line(&render("subject.txt")?),
+            html: render("html")?,
+            text: render("txt")?,
+        })
+    }
+}
+
+/// Example variables for previewing the email called `name`.
+fn sample_data(name: &str) -> Option<serde_json::Value> {
+    match name {
+        // Acknowledgements go to unverified addresses, so they contain nothing the visitor wrote
+        "auto_reply" => Some(serde_json::json!({})),
+        "contact" => Some(serde_json::json!({
+            "sender": "ada@example.com",
+            "first_name": "Ada",
+            "last_name": "Lovelace",
+            "message": "Hello!\nI'd love to hear more about your projects.",
+            "topic": null,
+        })),
+        _ => None,
+    }
+}
+
+/// Lists the emails that can be previewed.
+pub async fn list_templates(pool: web::Data<SqlitePool>, session: Session) -> HttpResponse {
+    if let Err(response) = require_admin(pool.get_ref(), &session).await {
+        return response;
+    }
+    HttpResponse::Ok().json(PREVIEWS)
+}
+
+/// Renders an email with sample data.
+pub async fn preview_template(
+    pool: web::Data<SqlitePool>,
+    templates: web::Data<Templates>,
+    session: Session,
+    path: web::Path<String>,
+    query: web::Query<PreviewQuery>,
+) -> HttpResponse {
+    if let Err(response) = require_admin(pool.get_ref(), &session).await {
+        return response;
+    }
+
+    let name = path.into_inner();
+    let sample = match sample_data(&name) {
+        Some(sample) => sample,
+        None => return HttpResponse::NotFound().body("Template not found"),
+    };
+
+    let rendered = match templates.render(&name, sample) {
+        Ok(rendered) => rendered,
+        Err(e) => {
+            tracing::error!("Failed to render template preview: {}", e);
+            return HttpResponse::InternalServerError().body(e.to_string());
+        }
+    };
+
+    match query.format.as_deref() {
+        None | Some("json") => HttpResponse::Ok().json(rendered),
+        Some("html") => HttpResponse::Ok().content_type("text/html; charset=utf-8").body(rendered.html),
+        Some("text") => HttpResponse::Ok().content_type("text/plain; charset=utf-8").body(rendered.text),
+        Some(_) => HttpResponse::BadRequest().body("format must be one of json, html, text"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_embedded_templates_render_with_layout() {
+        let rendered = Templates::new(None)
+            .render(
+                "contact",
+                serde_json::json!({
+                    "sender": "ada@example.com",
+                    "first_name": "Ada",
+                    "last_name": "<Lovelace>",
+                    "message": "Hi & bye",
+                }),
+            )
+            .unwrap();
+
+        assert_eq!(rendered.subject, "Logan0Dev - Mail from: Ada <Lovelace><ada@example.com>");
+        assert!(rendered.html.starts_with("<!DOCTYPE html>"));
+        assert!(rendered.html.contains("Ada &lt;Lovelace&gt;"));
+        assert!(rendered.html.contains("Hi &amp; bye"));
+        assert!(rendered.text.starts_with("Hi & bye\n"));
+        assert!(rendered.text.contains("Ada <Lovelace> <ada@example.com>"));
+    }
+
+    #[test]
+    fn test_template_dir_overrides_embedded() {
+        let dir = std::env::temp_dir().join(format!("templates-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("contact.txt"), "{% extends \"layout.txt\" %}{% block content %}Custom {{ first_name }}{% endblock %}").unwrap();
+
+        let rendered = Templates::new(Some(dir.clone())).render("contact", sample_data("contact")).unwrap();
+        assert!(rendered.text.starts_with("Custom Ada\n"));
+        assert!(rendered.text.ends_with("Sent by Logan0Dev."));
+        assert!(rendered.html.contains("Ada Lovelace"));
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
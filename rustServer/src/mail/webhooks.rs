@@ -0,0 +1,237 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use sha2::Sha256;
+use sqlx::SqlitePool;
+
+use crate::config::Config;
+use crate::suppressions;
+
+/// How long processed event IDs are remembered to skip redeliveries.
+const EVENT_ID_RETENTION_DAYS: u32 = 7;
+
+/// Largest accepted difference between a webhook's timestamp and now, which
+/// keeps captured requests from being replayed later.
+const TIMESTAMP_TOLERANCE_SECS: i64 = 5 * 60;
+
+/// A delivery event as sent by Resend.
+#[derive(Deserialize)]
+struct DeliveryEvent {
+    #[serde(rename = "type")]
+    kind: String,
+    data: DeliveryEventData,
+}
+
+#[derive(Deserialize)]
+struct DeliveryEventData {
+    email_id: String,
+    #[serde(default)]
+    to: Vec<String>,
+}
+
+/// Checks a webhook signed the Svix way Resend uses: `svix-signature` holds
+/// space-separated `v1,<base64 HMAC-SHA256>` entries over
+/// `{svix-id}.{svix-timestamp}.{body}`, keyed with the base64 part of the
+/// `whsec_` secret.
+fn verify_signature(secret: &str, req: &HttpRequest, body: &[u8], now: i64) -> bool {
+    let header = |name: &str| req.headers().get(name).and_then(|value| value.to_str().ok());
+    let (Some(id), Some(timestamp), Some(signatures)) =
+        (header("svix-id"), header("svix-timestamp"), header("svix-signature"))
+    else {
+        return false;
+    };
+    match timestamp.parse::<i64>() {
+        Ok(timestamp) if (now - timestamp).abs() <= TIMESTAMP_TOLERANCE_SECS => {}
+        _ => return false,
+    }
+
+    let key = base64::engine::general_purpose::STANDARD
+        .decode(secret.strip_prefix("whsec_").unwrap_or(secret))
+        .unwrap_or_else(|_| secret.as_bytes().to_vec());
+    let mut mac = Hmac::<Sha256>::new_from_slice(&key).expect("HMAC accepts any key length");
+    mac.update(format!("{}.{}.", id, timestamp).as_bytes());
+    mac.update(body);
+
+    // Several signatures are sent while the secret is being rotated
+    signatures
+        .split_whitespace()
+        .filter_map(|entry| entry.strip_prefix("v1,"))
+        .filter_map(|signature| base64::engine::general_purpose::STANDARD.decode(signature).ok())
+        .any(|signature| mac.clone().verify_slice(&signature).is_ok())
+}
+
+/// Receives delivery events from the mail provider, recording each sent
+/// message's delivery status and suppressing visitor addresses that bounced
+/// or complained. The site's own addresses are never suppressed, and an
+/// event redelivered with the same `svix-id` is only acknowledged.
+pub async fn receive_webhook(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    config: web::Data<Config>,
+    body: web::Bytes,
+) -> HttpResponse {
+    let secret = match config.mail_webhook_secret.as_deref() {
+        Some(secret) => secret,
+        None => return HttpResponse::NotFound().finish(),
+    };
+    if !verify_signature(secret, &req, &body, chrono::Utc::now().timestamp()) {
+        return HttpResponse::Unauthorized().body("Invalid signature");
+    }
+
+    let event_id = req
+        .headers()
+        .get("svix-id")
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default()
+        .to_string();
+    match sqlx::query_scalar::<_, bool>("SELECT EXISTS (SELECT 1 FROM mail_webhook_events WHERE id = ?)")
+        .bind(&event_id)
+        .fetch_one(pool.get_ref())
+        .await
+    {
+        Ok(true) => return HttpResponse::NoContent().finish(),
+        Ok(false) => {}
+        Err(e) => {
+            tracing::error!("Database error checking delivery event: {}", e);
+            return HttpResponse::InternalServerError().body("Database error");
+        }
+    }
+
+    let event: DeliveryEvent = match serde_json::from_slice(&body) {
+        Ok(event) => event,
+        Err(e) => return HttpResponse::BadRequest().body(format!("Invalid event: {}", e)),
+    };
+    let status = match event.kind.as_str() {
+        "email.delivered" => "delivered",
+        "email.bounced" => "bounced",
+        "email.complained" => "complained",
+        // Other events are acknowledged so the provider doesn't retry them
+        _ => return record_event(pool.get_ref(), &event_id).await,
+    };
+
+    let result = sqlx::query(
+        r#"
+        UPDATE mail_outbox
+        SET delivery_status = ?, delivery_updated_at = datetime('now')
+        WHERE provider_id = ?
+        "#,
+    )
+    .bind(status)
+    .bind(&event.data.email_id)
+    .execute(pool.get_ref())
+    .await;
+    if let Err(e) = result {
+        tracing::error!("Database error recording delivery event: {}", e);
+        return HttpResponse::InternalServerError().body("Database error");
+    }
+
+    if status != "delivered" {
+        tracing::warn!("Mail {} {}: {}", event.data.email_id, status, event.data.to.join(", "));
+        for address in event.data.to.iter().filter(|address| !config.contact.is_own_address(address)) {
+            if let Err(e) = suppressions::suppress(pool.get_ref(), address, status).await {
+                tracing::error!("Database error suppressing address: {}", e);
+                return HttpResponse::InternalServerError().body("Database error");
+            }
+        }
+    }
+
+    record_event(pool.get_ref(), &event_id).await
+}
+
+/// Remembers a processed event so redeliveries are skipped.
+async fn record_event(pool: &SqlitePool, event_id: &str) -> HttpResponse {
+    let result = sqlx::query("DELETE FROM mail_webhook_events WHERE received_at <= datetime('now', ?)")
+        .bind(format!("-{} days", EVENT_ID_RETENTION_DAYS))
+        .execute(pool)
+        .await;
+    let result = match result {
+        Ok(_) => sqlx::query("INSERT OR IGNORE INTO mail_webhook_events (id) VALUES (?)")
+            .bind(event_id)
+            .execute(pool)
+            .await,
+        Err(e) => Err(e),
+    };
+    match result {
+        Ok(_) => HttpResponse::NoContent().finish(),
+        Err(e) => {
+            tracing::error!("Database error recording delivery event: {}", e);
+            HttpResponse::InternalServerError().body("Database error")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{test, App};
+
+    const SECRET: &str = "whsec_c2VjcmV0LWtleQ==";
+
+    fn sign(timestamp: i64, body: &str) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(b"secret-key").unwrap();
+        mac.update(format!("msg_1.{}.{}", timestamp, body).as_bytes());
+        format!(
+            "v1,{}",
+            base64::engine::general_purpose::STANDARD.encode(mac.finalize().into_bytes())
+        )
+    }
+
+    #[actix_web::test]
+    async fn test_webhook_records_status_and_suppresses() {
+        let pool = crate::db::test_pool().await;
+        sqlx::query("INSERT INTO mail_outbox (id, message, status, provider_id) VALUES ('m1', '{}', 'sent', 're_1')")
+            .execute(&pool)
+            .await
+            .unwrap();
+        let config = Config {
+            mail_webhook_secret: Some(SECRET.into()),
+            ..Default::default()
+        };
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool.clone()))
+                .app_data(web::Data::new(config))
+                .route("/webhooks/mail", web::post().to(receive_webhook)),
+        )
+        .await;
+
+        let body = r#"{"type":"email.bounced","data":{"email_id":"re_1","to":["Gone@example.com","LoganTCarpenter@gmail.com"]}}"#;
+        let now = chrono::Utc::now().timestamp();
+        let request = |timestamp: i64, signature: String| {
+            test::TestRequest::post()
+                .uri("/webhooks/mail")
+                .insert_header(("svix-id", "msg_1"))
+                .insert_header(("svix-timestamp", timestamp.to_string()))
+                .insert_header(("svix-signature", signature))
+                .set_payload(body)
+                .to_request()
+        };
+
+        let forged = test::call_service(&app, request(now, sign(now, "{}"))).await;
+        assert_eq!(forged.status(), 401);
+        let stale = test::call_service(&app, request(now - 3600, sign(now - 3600, body))).await;
+        assert_eq!(stale.status(), 401);
+
+        let rotated = format!("v1,b2xk {}", sign(now, body));
+        let response = test::call_service(&app, request(now, rotated)).await;
+        assert_eq!(response.status(), 204);
+
+        let status: String = sqlx::query_scalar("SELECT delivery_status FROM mail_outbox WHERE id = 'm1'")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(status, "bounced");
+        assert!(suppressions::is_suppressed(&pool, "gone@example.com").await.unwrap());
+        assert!(
+            !suppressions::is_suppressed(&pool, "LoganTCarpenter@gmail.com").await.unwrap(),
+            "the site owner is never suppressed"
+        );
+
+        // A redelivered event is acknowledged without being processed again
+        sqlx::query("DELETE FROM mail_suppressions").execute(&pool).await.unwrap();
+        let response = test::call_service(&app, request(now, sign(now, body))).await;
+        assert_eq!(response.status(), 204);
+        assert!(!suppressions::is_suppressed(&pool, "gone@example.com").await.unwrap());
+    }
+}
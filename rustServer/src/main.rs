@@ -1,14 +1,35 @@
+mod analytics;
+mod attachments;
 mod auth;
+mod auto_reply;
+mod blobs;
+mod bundles;
 mod config;
 mod db;
 mod downloads;
+mod feeds;
+mod file_search;
 mod handlers;
+mod idempotency;
+mod inbox;
 mod mail;
+mod notify;
+mod outbox;
+mod previews;
+mod quotas;
+mod releases;
+mod shares;
+mod spam;
+mod storage;
+mod suppressions;
+mod taxonomy;
+mod tokens;
+mod validation;
 
 use actix_cors::Cors;
 use actix_files::Files;
 use actix_session::{storage::CookieSessionStore, SessionMiddleware};
-use actix_web::{cookie::Key, middleware, web, App, HttpServer};
+use actix_web::{cookie::Key, guard, http::header, middleware, web, App, HttpServer};
 use std::sync::Arc;
 use tracing::info;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
@@ -26,11 +47,34 @@ async fn main() -> std::io::Result<()> {
         .init();
 
     // Load configuration
+    // A misconfigured backend (e.g. S3 without credentials) must not start
+    // with defaults that silently serve or send from somewhere else
     let config = match Config::from_env() {
         Ok(cfg) => Arc::new(cfg),
         Err(e) => {
-            tracing::warn!("Api Functionality Limited: {}", e);
-            Arc::new(Config::default())
+            tracing::error!("Invalid configuration: {}", e);
+            return Err(std::io::Error::other("Configuration failed"));
+        }
+    };
+    if !config.mail_configured() {
+        tracing::warn!("Api Functionality Limited: MAIL_API_KEY not set, email disabled");
+    }
+
+    // Initialize mail delivery
+    let mail = match mail::from_config(&config) {
+        Ok(mail) => mail,
+        Err(e) => {
+            tracing::error!("Failed to initialize mail transport: {}", e);
+            return Err(std::io::Error::other("Mail initialization failed"));
+        }
+    };
+
+    // Initialize download storage
+    let storage = match storage::from_config(&config) {
+        Ok(storage) => storage,
+        Err(e) => {
+            tracing::error!("Failed to initialize storage backend: {}", e);
+            return Err(std::io::Error::other("Storage initialization failed"));
         }
     };
 
@@ -40,10 +84,33 @@ async fn main() -> std::io::Result<()> {
         Ok(pool) => pool,
         Err(e) => {
             tracing::error!("Failed to initialize database: {}", e);
-            return Err(std::io::Error::new(std::io::ErrorKind::Other, "Database initialization failed"));
+            return Err(std::io::Error::other("Database initialization failed"));
         }
     };
 
+    // Serve deduplicated files from the blob store when one is configured
+    let blob_store = config.blob_store_dir.as_deref().map(|dir| Arc::new(blobs::BlobStore::new(dir)));
+    if std::env::args().nth(1).as_deref() == Some("blobs") {
+        let blob_store = blob_store.ok_or_else(|| std::io::Error::other("BLOB_STORE_DIR is not set"))?;
+        return run_blobs_command(&config, &db_pool, storage.as_ref(), &blob_store).await;
+    }
+    let storage: Arc<dyn storage::StorageBackend> = match blob_store {
+        Some(blob_store) => Arc::new(blobs::ContentAddressedStorage::new(storage, blob_store, db_pool.clone())),
+        None => storage,
+    };
+
+    // Deliver queued mail in the background
+    let mail_outbox = outbox::Outbox::new(db_pool.clone());
+    mail_outbox.spawn_worker(mail.clone(), config.mail_retry);
+
+    analytics::spawn_retention_task(db_pool.clone(), config.analytics_retention_days());
+
+    // Record sizes of files added since the last start, for sorting by size
+    let (sync_pool, sync_storage) = (db_pool.clone(), storage.clone());
+    tokio::spawn(async move {
+        downloads::sync_file_sizes(&sync_pool, sync_storage.as_ref()).await;
+    });
+
     let port = std::env::var("PORT")
         .ok()
         .and_then(|p| p.parse().ok())
@@ -53,6 +120,13 @@ async fn main() -> std::io::Result<()> {
 
     let config_data = web::Data::from(config);
     let db_data = web::Data::new(db_pool);
+    let storage_data: web::Data<dyn storage::StorageBackend> = web::Data::from(storage);
+    let mail_data: web::Data<dyn mail::MailTransport> = web::Data::from(mail);
+    let outbox_data = web::Data::new(mail_outbox);
+    let templates_data = web::Data::new(mail::Templates::new(
+        config_data.mail_template_dir.as_ref().map(std::path::PathBuf::from),
+    ));
+    let notifications_data = web::Data::new(notify::Notifications::from_config(&config_data.notifications));
 
     // Session secret key - in production, load from env
     let secret_key = Key::from(
@@ -62,6 +136,8 @@ async fn main() -> std::io::Result<()> {
     );
 
     HttpServer::new(move || {
+        // Replays the first response to requests repeating an Idempotency-Key
+        let idempotent = || middleware::from_fn(idempotency::idempotency);
         let cors = Cors::default()
             .allow_any_origin()
             .allow_any_method()
@@ -72,6 +148,11 @@ async fn main() -> std::io::Result<()> {
         App::new()
             .app_data(config_data.clone())
             .app_data(db_data.clone())
+            .app_data(storage_data.clone())
+            .app_data(mail_data.clone())
+            .app_data(outbox_data.clone())
+            .app_data(templates_data.clone())
+            .app_data(notifications_data.clone())
             .wrap(middleware::Logger::default())
             .wrap(
                 SessionMiddleware::builder(CookieSessionStore::default(), secret_key.clone())
@@ -85,10 +166,75 @@ async fn main() -> std::io::Result<()> {
             .route("/api/auth/me", web::get().to(auth::me))
             // Download routes
             .route("/api/files", web::get().to(downloads::list_files))
-            .route("/api/files/token", web::post().to(downloads::generate_token))
+            .route("/api/files/token", web::post().to(downloads::generate_token).wrap(idempotent()))
+            .route("/api/files/{file_id}/preview", web::get().to(previews::get_preview))
+            .route("/api/files/bundle", web::post().to(bundles::generate_bundle).wrap(idempotent()))
+            .route("/downloads/bundle/{token}", web::get().to(bundles::download_bundle))
             .route("/downloads/token/{token}", web::get().to(downloads::download_by_token))
+            .route("/downloads/token/{token}/signature", web::get().to(downloads::signature_by_token))
+            .route("/downloads/signature/{path:.*}", web::get().to(downloads::signature_public))
             .route("/downloads/public/{path:.*}", web::get().to(downloads::download_public))
-            .route("/email", web::post().to(handlers::send_email))
+            // Feed routes
+            .route("/feeds/downloads.{format}", web::get().to(feeds::public_feed))
+            .route("/feeds/private/{token}/downloads.{format}", web::get().to(feeds::private_feed))
+            .route("/api/feeds/token", web::post().to(feeds::create_feed_token))
+            // Token routes
+            .route("/api/tokens", web::get().to(tokens::list_tokens))
+            .route("/api/tokens/{id}", web::delete().to(tokens::revoke_token))
+            .route("/api/admin/files/{file_id}/tokens/revoke", web::post().to(tokens::revoke_file_tokens))
+            .route("/api/admin/users/{user_id}/tokens/revoke", web::post().to(tokens::revoke_user_tokens))
+            // Share routes
+            .route("/api/shares", web::post().to(shares::create_share).wrap(idempotent()))
+            .route("/api/shares", web::get().to(shares::list_shares))
+            .route("/api/shares/{id}", web::delete().to(shares::revoke_share))
+            .route("/s/{code}", web::get().to(shares::open_share))
+            .route("/s/{code}", web::post().to(shares::open_share_with_password))
+            // Tag and category routes
+            .route("/api/tags", web::get().to(taxonomy::list_tags))
+            .route("/api/categories", web::get().to(taxonomy::list_categories))
+            .route("/api/admin/tags", web::post().to(taxonomy::create_tag).wrap(idempotent()))
+            .route("/api/admin/tags/{id}", web::delete().to(taxonomy::delete_tag))
+            .route("/api/admin/categories", web::post().to(taxonomy::create_category).wrap(idempotent()))
+            .route("/api/admin/categories/{id}", web::put().to(taxonomy::update_category))
+            .route("/api/admin/categories/{id}", web::delete().to(taxonomy::delete_category))
+            .route("/api/admin/files/{file_id}/tags", web::put().to(taxonomy::set_file_tags))
+            .route("/api/admin/files/{file_id}/category", web::put().to(taxonomy::set_file_category))
+            // Admin routes
+            .route("/api/admin/stats/files", web::get().to(analytics::file_stats))
+            .route("/api/admin/stats/files/{file_id}/daily", web::get().to(analytics::file_daily_stats))
+            .route("/api/admin/mail/dead", web::get().to(outbox::list_dead_letters))
+            .route("/api/admin/mail/{id}/retry", web::post().to(outbox::retry_dead_letter))
+            .route("/api/admin/mail/suppressions", web::get().to(suppressions::list_suppressions))
+            .route("/api/admin/mail/suppressions", web::post().to(suppressions::add_suppression))
+            .route("/api/admin/mail/suppressions/{address}", web::delete().to(suppressions::remove_suppression))
+            .route("/api/admin/mail/templates", web::get().to(mail::list_templates))
+            .route("/api/admin/mail/templates/{name}/preview", web::get().to(mail::preview_template))
+            .route("/api/admin/messages", web::get().to(inbox::list_messages))
+            .route("/api/admin/messages/{id}", web::get().to(inbox::get_message))
+            .route("/api/admin/messages/{id}", web::delete().to(inbox::delete_message))
+            .route("/api/admin/messages/{id}/handled", web::post().to(inbox::mark_handled))
+            .route("/api/admin/messages/{id}/archive", web::post().to(inbox::archive_message))
+            .route("/api/admin/spam", web::get().to(spam::list_quarantine))
+            .route("/api/admin/spam/{id}/release", web::post().to(spam::release_quarantined))
+            .route("/api/admin/spam/{id}", web::delete().to(spam::delete_quarantined))
+            // Release routes
+            .route("/api/releases", web::post().to(releases::create_release).wrap(idempotent()))
+            .route("/api/releases/{project}", web::get().to(releases::list_releases))
+            .route("/api/releases/{project}/latest", web::get().to(releases::latest_release))
+            .route("/api/releases/{project}/{version}/yank", web::post().to(releases::yank_release))
+            .route("/api/contact/challenge", web::get().to(spam::challenge))
+            .route(
+                "/email",
+                web::post()
+                    .guard(guard::fn_guard(|ctx| {
+                        ctx.header::<header::ContentType>()
+                            .is_some_and(|content_type| content_type.essence_str() == "multipart/form-data")
+                    }))
+                    .to(handlers::send_email_multipart)
+                    .wrap(idempotent()),
+            )
+            .route("/email", web::post().to(handlers::send_email).wrap(idempotent()))
+            .route("/webhooks/mail", web::post().to(mail::receive_webhook))
             // Serve static files from client build directory
             .service(Files::new("/static", "../client/leptosUI/dist"))
             // SPA fallback - serve index.html for all other routes
@@ -98,3 +244,33 @@ async fn main() -> std::io::Result<()> {
     .run()
     .await
 }
+
+/// `server blobs import [--prune]` copies download files into the blob store,
+/// deleting the local originals with `--prune`; `server blobs gc` removes
+/// blobs that are no longer referenced.
+async fn run_blobs_command(
+    config: &Config,
+    pool: &sqlx::SqlitePool,
+    source: &dyn storage::StorageBackend,
+    blob_store: &blobs::BlobStore,
+) -> std::io::Result<()> {
+    let args: Vec<String> = std::env::args().skip(2).collect();
+    let result = match args.first().map(String::as_str) {
+        Some("import") => {
+            let prune = args.iter().any(|a| a == "--prune");
+            let prune_dir = match (&config.storage_kind, prune) {
+                (config::StorageKind::Local, true) => Some(std::path::Path::new(config.downloads_dir())),
+                (_, true) => return Err(std::io::Error::other("--prune requires local storage")),
+                _ => None,
+            };
+            blobs::import_files(pool, source, blob_store, prune_dir)
+                .await
+                .map(|n| info!("Imported {} files into the blob store", n))
+        }
+        Some("gc") => blobs::collect_garbage(pool, blob_store, blobs::GC_GRACE_PERIOD)
+            .await
+            .map(|n| info!("Removed {} unreferenced blobs", n)),
+        _ => return Err(std::io::Error::other("Usage: server blobs <import [--prune] | gc>")),
+    };
+    result.map_err(std::io::Error::other)
+}
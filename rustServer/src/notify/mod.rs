@@ -0,0 +1,450 @@
+mod webhook;
+
+use async_trait::async_trait;
+use std::collections::{BTreeSet, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+use crate::outbox::RetryPolicy;
+use crate::validation::ContactForm;
+
+pub use webhook::{WebhookFormat, WebhookNotifier};
+
+/// Longest excerpt of a contact message included in a notification.
+const MAX_EXCERPT_CHARS: usize = 300;
+/// Most usernames listed in a failed-login alert.
+const MAX_LISTED_USERNAMES: usize = 10;
+/// Most failed logins remembered at once; older ones are dropped first.
+const MAX_TRACKED_FAILURES: usize = 1000;
+/// Usernames are remembered only up to this many characters.
+const MAX_TRACKED_USERNAME_CHARS: usize = 32;
+
+#[derive(Error, Debug)]
+pub enum NotifyError {
+    #[error("Request error: {0}")]
+    RequestError(#[from] reqwest::Error),
+    /// The receiver asked us to slow down, optionally saying for how long
+    #[error("Rate limited")]
+    RateLimited(Option<Duration>),
+    #[error("Webhook returned {0}")]
+    Status(reqwest::StatusCode),
+}
+
+impl NotifyError {
+    /// Whether sending the same notification again may succeed.
+    fn is_transient(&self) -> bool {
+        match self {
+            NotifyError::RequestError(_) | NotifyError::RateLimited(_) => true,
+            NotifyError::Status(status) => status.is_server_error(),
+        }
+    }
+}
+
+/// Something worth telling the site owner about.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Event {
+    ContactMessage,
+    Registration,
+    FailedLogins,
+}
+
+impl Event {
+    pub const ALL: [Event; 3] = [Event::ContactMessage, Event::Registration, Event::FailedLogins];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Event::ContactMessage => "contact_message",
+            Event::Registration => "registration",
+            Event::FailedLogins => "failed_logins",
+        }
+    }
+}
+
+impl std::str::FromStr for Event {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Event::ALL
+            .into_iter()
+            .find(|event| event.as_str() == s)
+            .ok_or_else(|| format!("unknown event {}", s))
+    }
+}
+
+/// A short message for a chat channel.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Notification {
+    pub event: Event,
+    pub title: String,
+    pub body: String,
+}
+
+impl Notification {
+    pub fn contact_message(id: &str, form: &ContactForm) -> Self {
+        let mut excerpt: String = form.message.chars().take(MAX_EXCERPT_CHARS).collect();
+        if excerpt.len() < form.message.len() {
+            excerpt.push('…');
+        }
+        let topic = form.topic.as_deref().map(|topic| format!(" about {}", topic)).unwrap_or_default();
+        Self {
+            event: Event::ContactMessage,
+            title: format!("New contact message{}", topic),
+            body: format!(
+                "From {} {} <{}>\n{}\nMessage {}",
+                form.first_name, form.last_name, form.sender, excerpt, id
+            ),
+        }
+    }
+
+    pub fn registration(username: &str) -> Self {
+        Self {
+            event: Event::Registration,
+            title: "New registration".to_string(),
+            body: format!("User {} signed up", username),
+        }
+    }
+
+    fn failed_logins(count: usize, window: Duration, usernames: &BTreeSet<String>) -> Self {
+        let mut listed: Vec<&str> = usernames.iter().map(String::as_str).take(MAX_LISTED_USERNAMES).collect();
+        if usernames.len() > MAX_LISTED_USERNAMES {
+            listed.push("…");
+        }
+        Self {
+            event: Event::FailedLogins,
+            title: "Burst of failed logins".to_string(),
+            body: format!(
+                "{} failed logins within {} minutes for: {}",
+                count,
+                window.as_secs() / 60,
+                listed.join(", ")
+            ),
+        }
+    }
+}
+
+/// Delivers notifications to one destination, such as a chat webhook.
+#[async_trait]
+pub trait Notifier: Send + Sync {
+    async fn notify(&self, notification: &Notification) -> Result<(), NotifyError>;
+}
+
+/// A chat webhook and the events it receives (`NOTIFY_WEBHOOKS`,
+/// `NOTIFY_WEBHOOK_*_<NAME>`).
+#[derive(Clone, Debug)]
+pub struct WebhookTarget {
+    pub name: String,
+    pub url: String,
+    pub format: WebhookFormat,
+    pub events: Vec<Event>,
+}
+
+/// When failed logins are reported (`NOTIFY_FAILED_LOGIN_THRESHOLD`,
+/// `NOTIFY_FAILED_LOGIN_WINDOW_MINUTES`).
+#[derive(Clone, Copy, Debug)]
+pub struct LoginAlertPolicy {
+    /// Failures within the window that make a burst
+    pub threshold: usize,
+    /// At most one alert is sent per window
+    pub window: Duration,
+}
+
+impl Default for LoginAlertPolicy {
+    fn default() -> Self {
+        Self {
+            threshold: 10,
+            window: Duration::from_secs(10 * 60),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct NotifyConfig {
+    pub webhooks: Vec<WebhookTarget>,
+    /// Delivery attempts per notification (`NOTIFY_MAX_ATTEMPTS`, `NOTIFY_RETRY_BASE_SECONDS`)
+    pub retry: RetryPolicy,
+    pub failed_logins: LoginAlertPolicy,
+}
+
+impl Default for NotifyConfig {
+    fn default() -> Self {
+        Self {
+            webhooks: Vec::new(),
+            retry: RetryPolicy {
+                max_attempts: 4,
+                base_delay: Duration::from_secs(2),
+            },
+            failed_logins: LoginAlertPolicy::default(),
+        }
+    }
+}
+
+struct Route {
+    notifier: Arc<dyn Notifier>,
+    events: Vec<Event>,
+}
+
+#[derive(Default)]
+struct LoginFailures {
+    recent: VecDeque<(Instant, String)>,
+    last_alert: Option<Instant>,
+}
+
+/// Sends each notification to the notifiers routed its event, retrying
+/// failed deliveries in the background.
+pub struct Notifications {
+    routes: Arc<Vec<Route>>,
+    retry: RetryPolicy,
+    login_policy: LoginAlertPolicy,
+    login_failures: Mutex<LoginFailures>,
+}
+
+impl Notifications {
+    pub fn new(retry: RetryPolicy, login_policy: LoginAlertPolicy) -> Self {
+        Self {
+            routes: Arc::new(Vec::new()),
+            retry,
+            login_policy,
+            login_failures: Mutex::default(),
+        }
+    }
+
+    /// Sends `events` to `notifier` as well.
+    pub fn route(mut self, notifier: Arc<dyn Notifier>, events: Vec<Event>) -> Self {
+        Arc::get_mut(&mut self.routes)
+            .expect("routes are set up before notifications are sent")
+            .push(Route { notifier, events });
+        self
+    }
+
+    /// Routes the configured webhooks.
+    pub fn from_config(config: &NotifyConfig) -> Self {
+        config.webhooks.iter().fold(
+            Self::new(config.retry, config.failed_logins),
+            |notifications, target| {
+                let events: Vec<&str> = target.events.iter().map(|event| event.as_str()).collect();
+                tracing::info!("Notifying webhook {} of {}", target.name, events.join(", "));
+                let notifier = WebhookNotifier::new(&target.url, target.format);
+                notifications.route(Arc::new(notifier), target.events.clone())
+            },
+        )
+    }
+
+    /// Sends a notification without waiting for it to be delivered.
+    pub fn dispatch(&self, notification: Notification) {
+        if !self.routes.iter().any(|route| route.events.contains(&notification.event)) {
+            return;
+        }
+        let (routes, retry) = (self.routes.clone(), self.retry);
+        tokio::spawn(async move {
+            deliver(&routes, &retry, &notification).await;
+        });
+    }
+
+    /// Records a failed login, alerting once failures reach the burst threshold.
+    pub fn login_failed(&self, username: &str) {
+        if let Some(notification) = self.record_login_failure(username, Instant::now()) {
+            self.dispatch(notification);
+        }
+    }
+
+    fn record_login_failure(&self, username: &str, now: Instant) -> Option<Notification> {
+        let policy = &self.login_policy;
+        let mut failures = self.login_failures.lock().unwrap_or_else(|e| e.into_inner());
+        while failures
+            .recent
+            .front()
+            .is_some_and(|(at, _)| now.duration_since(*at) >= policy.window)
+        {
+            failures.recent.pop_front();
+        }
+        if failures.recent.len() >= MAX_TRACKED_FAILURES {
+            failures.recent.pop_front();
+        }
+        let username = username.chars().take(MAX_TRACKED_USERNAME_CHARS).collect();
+        failures.recent.push_back((now, username));
+
+        let alerted_recently = failures
+            .last_alert
+            .is_some_and(|at| now.duration_since(at) < policy.window);
+        if failures.recent.len() < policy.threshold || alerted_recently {
+            return None;
+        }
+        failures.last_alert = Some(now);
+        let usernames = failures.recent.iter().map(|(_, username)| username.clone()).collect();
+        Some(Notification::failed_logins(failures.recent.len(), policy.window, &usernames))
+    }
+}
+
+/// Delivers a notification to every route taking its event, returning how
+/// many deliveries failed for good.
+async fn deliver(routes: &[Route], retry: &RetryPolicy, notification: &Notification) -> usize {
+    let deliveries = routes
+        .iter()
+        .filter(|route| route.events.contains(&notification.event))
+        .map(|route| deliver_to(route.notifier.as_ref(), retry, notification));
+    let results = futures_util::future::join_all(deliveries).await;
+    results.into_iter().filter(|delivered| !delivered).count()
+}
+
+async fn deliver_to(notifier: &dyn Notifier, retry: &RetryPolicy, notification: &Notification) -> bool {
+    let mut attempts = 0;
+    loop {
+        attempts += 1;
+        let error = match notifier.notify(notification).await {
+            Ok(()) => return true,
+            Err(e) => e,
+        };
+        if !error.is_transient() || attempts >= retry.max_attempts {
+            tracing::error!(
+                "Giving up on {} notification after {} attempts: {}",
+                notification.event.as_str(),
+                attempts,
+                error
+            );
+            return false;
+        }
+        let delay = match error {
+            NotifyError::RateLimited(Some(wait)) => wait.min(retry.delay(retry.max_attempts)),
+            _ => retry.delay(attempts),
+        };
+        tracing::warn!(
+            "{} notification failed (attempt {}), retrying in {:?}: {}",
+            notification.event.as_str(),
+            attempts,
+            delay,
+            error
+        );
+        tokio::time::sleep(delay).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn notifications(retry: RetryPolicy) -> Notifications {
+        Notifications::new(retry, LoginAlertPolicy { threshold: 3, window: Duration::from_secs(120) })
+    }
+
+    fn quick_retry() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(10),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_routes_and_retries_webhook_deliveries() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/slack"))
+            .respond_with(ResponseTemplate::new(503))
+            .up_to_n_times(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/slack"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/discord"))
+            .respond_with(ResponseTemplate::new(204))
+            .mount(&server)
+            .await;
+
+        let webhook = |route: &str, format| {
+            Arc::new(WebhookNotifier::new(&format!("{}/{}", server.uri(), route), format)) as Arc<dyn Notifier>
+        };
+        let notifications = notifications(quick_retry())
+            .route(webhook("slack", WebhookFormat::Slack), Event::ALL.to_vec())
+            .route(webhook("discord", WebhookFormat::Discord), vec![Event::Registration]);
+
+        let form = ContactForm {
+            sender: "ada@example.com".into(),
+            first_name: "Ada".into(),
+            last_name: "Lovelace".into(),
+            message: "Hello!".into(),
+            topic: Some("support".into()),
+            attachments: Vec::new(),
+        };
+        let failed = deliver(&notifications.routes, &notifications.retry, &Notification::contact_message("m1", &form)).await;
+        assert_eq!(failed, 0);
+
+        let requests = server.received_requests().await.unwrap();
+        assert_eq!(requests.len(), 2, "the first failure is retried, Discord is not routed");
+        let body: serde_json::Value = requests[1].body_json().unwrap();
+        assert_eq!(
+            body["text"],
+            "*New contact message about support*\nFrom Ada Lovelace &lt;ada@example.com&gt;\nHello!\nMessage m1"
+        );
+
+        let failed = deliver(&notifications.routes, &notifications.retry, &Notification::registration("grace")).await;
+        assert_eq!(failed, 0);
+        let requests = server.received_requests().await.unwrap();
+        let discord: serde_json::Value = requests.last().unwrap().body_json().unwrap();
+        assert_eq!(discord["content"], "**New registration**\nUser grace signed up");
+    }
+
+    #[tokio::test]
+    async fn test_gives_up_on_rejected_and_persistently_failing_webhooks() {
+        let server = MockServer::start().await;
+        Mock::given(path("/gone"))
+            .respond_with(ResponseTemplate::new(404))
+            .mount(&server)
+            .await;
+        Mock::given(path("/down"))
+            .respond_with(ResponseTemplate::new(500))
+            .mount(&server)
+            .await;
+
+        let notifications = notifications(quick_retry())
+            .route(
+                Arc::new(WebhookNotifier::new(&format!("{}/gone", server.uri()), WebhookFormat::Slack)),
+                Event::ALL.to_vec(),
+            )
+            .route(
+                Arc::new(WebhookNotifier::new(&format!("{}/down", server.uri()), WebhookFormat::Matrix)),
+                Event::ALL.to_vec(),
+            );
+        let failed = deliver(&notifications.routes, &notifications.retry, &Notification::registration("grace")).await;
+        assert_eq!(failed, 2);
+
+        let requests = server.received_requests().await.unwrap();
+        let count = |route: &str| requests.iter().filter(|r| r.url.path() == route).count();
+        assert_eq!(count("/gone"), 1, "client errors are not retried");
+        assert_eq!(count("/down"), 3);
+    }
+
+    #[test]
+    fn test_failed_login_bursts_alert_once_per_window() {
+        let notifications = notifications(quick_retry());
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+
+        assert!(notifications.record_login_failure("admin", at(0)).is_none());
+        assert!(notifications.record_login_failure("root", at(1)).is_none());
+        let alert = notifications.record_login_failure("admin", at(2)).unwrap();
+        assert_eq!(alert.event, Event::FailedLogins);
+        assert_eq!(alert.body, "3 failed logins within 2 minutes for: admin, root");
+        assert!(notifications.record_login_failure("admin", at(3)).is_none());
+
+        // Failures spread out over more than the window are not a burst
+        assert!(notifications.record_login_failure("admin", at(200)).is_none());
+        assert!(notifications.record_login_failure("admin", at(330)).is_none());
+        assert!(notifications.record_login_failure("admin", at(331)).is_none());
+        assert!(notifications.record_login_failure("admin", at(332)).is_some());
+
+        // A flood of long usernames stays bounded
+        let long = "x".repeat(10_000);
+        for i in 0..MAX_TRACKED_FAILURES + 10 {
+            notifications.record_login_failure(&long, at(500 + i as u64 / 100));
+        }
+        let failures = notifications.login_failures.lock().unwrap();
+        assert_eq!(failures.recent.len(), MAX_TRACKED_FAILURES);
+        assert!(failures.recent.iter().all(|(_, name)| name.chars().count() == MAX_TRACKED_USERNAME_CHARS));
+    }
+}
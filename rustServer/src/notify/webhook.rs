@@ -0,0 +1,153 @@
+use async_trait::async_trait;
+use std::time::Duration;
+
+use super::{Notification, Notifier, NotifyError};
+
+/// Discord rejects messages longer than this.
+const MAX_DISCORD_CONTENT: usize = 2000;
+
+/// The payload an incoming webhook expects (`NOTIFY_WEBHOOK_FORMAT_<NAME>`).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum WebhookFormat {
+    /// `{"text": ...}` with Slack's mrkdwn
+    #[default]
+    Slack,
+    /// `{"content": ...}` with Discord's markdown
+    Discord,
+    /// `{"text": ..., "html": ...}` as taken by Matrix hookshot webhooks
+    Matrix,
+}
+
+impl std::str::FromStr for WebhookFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "slack" => Ok(WebhookFormat::Slack),
+            "discord" => Ok(WebhookFormat::Discord),
+            "matrix" => Ok(WebhookFormat::Matrix),
+            other => Err(format!("unknown webhook format {}", other)),
+        }
+    }
+}
+
+impl WebhookFormat {
+    fn payload(self, notification: &Notification) -> serde_json::Value {
+        match self {
+            // Escaping keeps visitor text from forming `<!channel>` pings or links
+            WebhookFormat::Slack => serde_json::json!({
+                "text": format!(
+                    "*{}*\n{}",
+                    escape_slack(&notification.title),
+                    escape_slack(&notification.body)
+                ),
+            }),
+            WebhookFormat::Discord => {
+                let content: String = format!("**{}**\n{}", notification.title, notification.body)
+                    .chars()
+                    .take(MAX_DISCORD_CONTENT)
+                    .collect();
+                // No `@everyone`, role or user pings whatever the text says
+                serde_json::json!({ "content": content, "allowed_mentions": { "parse": [] } })
+            }
+            WebhookFormat::Matrix => serde_json::json!({
+                "text": format!("{}\n{}", notification.title, notification.body),
+                "html": format!(
+                    "<strong>{}</strong><br>{}",
+                    escape_html(&notification.title),
+                    escape_html(&notification.body).replace('\n', "<br>")
+                ),
+            }),
+        }
+    }
+}
+
+/// Slack treats only these three characters as control characters.
+fn escape_slack(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Posts notifications to a chat service's incoming webhook.
+pub struct WebhookNotifier {
+    url: String,
+    format: WebhookFormat,
+    client: reqwest::Client,
+}
+
+impl WebhookNotifier {
+    pub fn new(url: &str, format: WebhookFormat) -> Self {
+        Self {
+            url: url.to_string(),
+            format,
+            client: reqwest::Client::new(),
+        }
+    }
+}
+
+#[async_trait]
+impl Notifier for WebhookNotifier {
+    async fn notify(&self, notification: &Notification) -> Result<(), NotifyError> {
+        let response = self
+            .client
+            .post(&self.url)
+            .timeout(Duration::from_secs(10))
+            .json(&self.format.payload(notification))
+            .send()
+            .await?;
+
+        let status = response.status();
+        if status == reqwest::StatusCode::TOO_MANY_REQUESTS {
+            let retry_after = response
+                .headers()
+                .get(reqwest::header::RETRY_AFTER)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.parse::<f64>().ok())
+                .and_then(|secs| Duration::try_from_secs_f64(secs).ok());
+            return Err(NotifyError::RateLimited(retry_after));
+        }
+        if !status.is_success() {
+            return Err(NotifyError::Status(status));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notify::Event;
+
+    #[test]
+    fn test_payloads_cannot_ping() {
+        let notification = Notification {
+            event: Event::ContactMessage,
+            title: "New contact message".into(),
+            body: "<!channel> @everyone <@U123>".into(),
+        };
+        let slack = WebhookFormat::Slack.payload(&notification);
+        assert_eq!(slack["text"], "*New contact message*\n&lt;!channel&gt; @everyone &lt;@U123&gt;");
+        let discord = WebhookFormat::Discord.payload(&notification);
+        assert_eq!(discord["allowed_mentions"], serde_json::json!({ "parse": [] }));
+    }
+
+    #[test]
+    fn test_matrix_payload_escapes_html() {
+        let notification = Notification {
+            event: Event::Registration,
+            title: "New registration".into(),
+            body: "User <b>x</b> signed up\nfrom a & b".into(),
+        };
+        let payload = WebhookFormat::Matrix.payload(&notification);
+        assert_eq!(
+            payload["html"],
+            "<strong>New registration</strong><br>User &lt;b&gt;x&lt;/b&gt; signed up<br>from a &amp; b"
+        );
+    }
+}
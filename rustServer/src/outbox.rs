@@ -0,0 +1,405 @@
+use actix_session::Session;
+use actix_web::{web, HttpResponse};
+use serde::Serialize;
+use sqlx::SqlitePool;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Notify;
+use uuid::Uuid;
+
+use crate::auth::require_admin;
+use crate::mail::{EmailMessage, MailTransport};
+use crate::suppressions;
+
+/// How often the worker looks for due messages when nothing wakes it.
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+/// Longest wait between two delivery attempts.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60 * 60);
+
+/// When to give up on a message and how long to wait between attempts.
+#[derive(Clone, Copy, Debug)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    /// Wait after the first failure; doubled after each further failure
+    pub base_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 8,
+            base_delay: Duration::from_secs(30),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Delay before the next attempt after `attempts` failed ones.
+    pub fn delay(&self, attempts: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempts.saturating_sub(1));
+        self.base_delay.saturating_mul(factor).min(MAX_RETRY_DELAY)
+    }
+}
+
+/// Durable queue of outgoing mail. Messages are stored before delivery is
+/// attempted, so a failing provider delays mail instead of losing it.
+#[derive(Clone)]
+pub struct Outbox {
+    pool: SqlitePool,
+    wake: Arc<Notify>,
+}
+
+#[derive(Serialize)]
+pub struct DeadLetter {
+    pub id: String,
+    pub to: Vec<String>,
+    pub subject: String,
+    pub attempts: i64,
+    pub last_error: Option<String>,
+    pub created_at: String,
+    pub failed_at: String,
+}
+
+impl Outbox {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self {
+            pool,
+            wake: Arc::new(Notify::new()),
+        }
+    }
+
+    /// Queues a message for delivery and wakes the worker.
+    pub async fn enqueue(&self, message: &EmailMessage) -> Result<String, sqlx::Error> {
+        let id = Uuid::new_v4().to_string();
+        let payload = serde_json::to_string(message).expect("email message serializes");
+        sqlx::query("INSERT INTO mail_outbox (id, message) VALUES (?, ?)")
+            .bind(&id)
+            .bind(payload)
+            .execute(&self.pool)
+            .await?;
+        self.wake.notify_one();
+        Ok(id)
+    }
+
+    /// Delivers queued mail in the background until the process exits.
+    pub fn spawn_worker(&self, transport: Arc<dyn MailTransport>, policy: RetryPolicy) {
+        let outbox = self.clone();
+        tokio::spawn(async move {
+            // Messages left mid-delivery by a previous process are retried
+            if let Err(e) = sqlx::query("UPDATE mail_outbox SET status = 'pending' WHERE status = 'sending'")
+                .execute(&outbox.pool)
+                .await
+            {
+                tracing::error!("Failed to recover outbox: {}", e);
+            }
+
+            loop {
+                while let Ok(true) = outbox.deliver_next(transport.as_ref(), &policy).await {}
+                tokio::select! {
+                    _ = outbox.wake.notified() => {}
+                    _ = tokio::time::sleep(POLL_INTERVAL) => {}
+                }
+            }
+        });
+    }
+
+    /// Attempts the oldest due message, returning whether there was one.
+    async fn deliver_next(&self, transport: &dyn MailTransport, policy: &RetryPolicy) -> Result<bool, sqlx::Error> {
+        let claimed = sqlx::query_as::<_, (String, String, i64)>(
+            r#"
+            UPDATE mail_outbox SET status = 'sending', updated_at = datetime('now')
+            WHERE id = (
+                SELECT id FROM mail_outbox
+                WHERE status = 'pending' AND next_attempt_at <= datetime('now')
+                ORDER BY next_attempt_at, created_at
+                LIMIT 1
+            )
+            RETURNING id, message, attempts
+            "#,
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .inspect_err(|e| tracing::error!("Failed to read outbox: {}", e))?;
+
+        let (id, payload, attempts) = match claimed {
+            Some(claimed) => claimed,
+            None => return Ok(false),
+        };
+
+        let result = match serde_json::from_str::<EmailMessage>(&payload) {
+            Ok(message) => match without_suppressed(&self.pool, message).await {
+                Ok(Some(message)) => transport.send(&message).await.map_err(|e| e.to_string()),
+                Err(e) => Err(format!("Suppression check failed: {}", e)),
+                Ok(None) => {
+                    tracing::info!("Not sending mail {}: all recipients are suppressed", id);
+                    sqlx::query(
+                        "UPDATE mail_outbox SET status = 'suppressed', updated_at = datetime('now') WHERE id = ?",
+                    )
+                    .bind(&id)
+                    .execute(&self.pool)
+                    .await?;
+                    return Ok(true);
+                }
+            },
+            Err(e) => Err(format!("Unreadable message: {}", e)),
+        };
+
+        let attempts = attempts as u32 + 1;
+        let update = match result {
+            Ok(provider_id) => sqlx::query(
+                r#"
+                UPDATE mail_outbox
+                SET status = 'sent', attempts = ?, provider_id = ?, sent_at = datetime('now'), updated_at = datetime('now')
+                WHERE id = ?
+                "#,
+            )
+            .bind(attempts)
+            .bind(provider_id)
+            .bind(&id),
+            Err(error) if attempts >= policy.max_attempts => {
+                tracing::error!("Giving up on mail {} after {} attempts: {}", id, attempts, error);
+                sqlx::query(
+                    "UPDATE mail_outbox SET status = 'dead', attempts = ?, last_error = ?, updated_at = datetime('now') WHERE id = ?",
+                )
+                .bind(attempts)
+                .bind(error)
+                .bind(&id)
+            }
+            Err(error) => {
+                let delay = policy.delay(attempts);
+                tracing::warn!("Mail {} failed (attempt {}), retrying in {:?}: {}", id, attempts, delay, error);
+                sqlx::query(
+                    r#"
+                    UPDATE mail_outbox
+                    SET status = 'pending', attempts = ?, last_error = ?,
+                        next_attempt_at = datetime('now', ?), updated_at = datetime('now')
+                    WHERE id = ?
+                    "#,
+                )
+                .bind(attempts)
+                .bind(error)
+                .bind(format!("+{} seconds", delay.as_secs()))
+                .bind(&id)
+            }
+        };
+        update.execute(&self.pool).await?;
+        Ok(true)
+    }
+}
+
+/// The message without recipients on the suppression list, or `None` if
+/// no `To` recipient is left.
+async fn without_suppressed(pool: &SqlitePool, mut message: EmailMessage) -> Result<Option<EmailMessage>, sqlx::Error> {
+    for list in [&mut message.to, &mut message.cc, &mut message.bcc] {
+        let mut kept = Vec::with_capacity(list.len());
+        for address in list.drain(..) {
+            if !suppressions::is_suppressed(pool, &address).await? {
+                kept.push(address);
+            }
+        }
+        *list = kept;
+    }
+    Ok((!message.to.is_empty()).then_some(message))
+}
+
+/// Messages that exhausted their delivery attempts, most recent failure first.
+pub async fn list_dead_letters(
+    pool: web::Data<SqlitePool>,
+    session: Session,
+) -> HttpResponse {
+    if let Err(response) = require_admin(pool.get_ref(), &session).await {
+        return response;
+    }
+
+    let rows = sqlx::query_as::<_, (String, String, i64, Option<String>, String, String)>(
+        r#"
+        SELECT id, message, attempts, last_error, created_at, updated_at
+        FROM mail_outbox
+        WHERE status = 'dead'
+        ORDER BY updated_at DESC
+        "#,
+    )
+    .fetch_all(pool.get_ref())
+    .await;
+
+    match rows {
+        Ok(rows) => {
+            let letters: Vec<DeadLetter> = rows
+                .into_iter()
+                .map(|(id, payload, attempts, last_error, created_at, failed_at)| {
+                    let message = serde_json::from_str::<EmailMessage>(&payload).ok();
+                    DeadLetter {
+                        id,
+                        to: message.as_ref().map(|m| m.to.clone()).unwrap_or_default(),
+                        subject: message.map(|m| m.subject).unwrap_or_default(),
+                        attempts,
+                        last_error,
+                        created_at,
+                        failed_at,
+                    }
+                })
+                .collect();
+            HttpResponse::Ok().json(letters)
+        }
+        Err(e) => {
+            tracing::error!("Database error listing dead letters: {}", e);
+            HttpResponse::InternalServerError().body("Database error")
+        }
+    }
+}
+
+/// Puts a dead-lettered message back in the queue with a fresh set of attempts.
+pub async fn retry_dead_letter(
+    pool: web::Data<SqlitePool>,
+    outbox: web::Data<Outbox>,
+    session: Session,
+    path: web::Path<String>,
+) -> HttpResponse {
+    if let Err(response) = require_admin(pool.get_ref(), &session).await {
+        return response;
+    }
+
+    let result = sqlx::query(
+        r#"
+        UPDATE mail_outbox
+        SET status = 'pending', attempts = 0, next_attempt_at = datetime('now'), updated_at = datetime('now')
+        WHERE id = ? AND status = 'dead'
+        "#,
+    )
+    .bind(path.into_inner())
+    .execute(pool.get_ref())
+    .await;
+
+    match result {
+        Ok(r) if r.rows_affected() == 0 => HttpResponse::NotFound().body("Dead letter not found"),
+        Ok(_) => {
+            outbox.wake.notify_one();
+            HttpResponse::Accepted().finish()
+        }
+        Err(e) => {
+            tracing::error!("Failed to requeue message: {}", e);
+            HttpResponse::InternalServerError().body("Database error")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mail::{MailError, MemoryTransport};
+    use async_trait::async_trait;
+
+    struct FailingTransport;
+
+    #[async_trait]
+    impl MailTransport for FailingTransport {
+        async fn send(&self, _message: &EmailMessage) -> Result<Option<String>, MailError> {
+            Err(MailError::SendError("provider down".into()))
+        }
+    }
+
+    fn message() -> EmailMessage {
+        EmailMessage {
+            from: "a@example.com".into(),
+            to: vec!["b@example.com".into()],
+            subject: "Hi".into(),
+            text: "Hello".into(),
+            ..Default::default()
+        }
+    }
+
+    async fn status(pool: &SqlitePool, id: &str) -> (String, i64) {
+        sqlx::query_as("SELECT status, attempts FROM mail_outbox WHERE id = ?")
+            .bind(id)
+            .fetch_one(pool)
+            .await
+            .unwrap()
+    }
+
+    async fn make_due(pool: &SqlitePool) {
+        sqlx::query("UPDATE mail_outbox SET next_attempt_at = datetime('now')")
+            .execute(pool)
+            .await
+            .unwrap();
+    }
+
+    #[test]
+    fn test_retry_delay_doubles_up_to_cap() {
+        let policy = RetryPolicy::default();
+        assert_eq!(policy.delay(1), Duration::from_secs(30));
+        assert_eq!(policy.delay(3), Duration::from_secs(120));
+        assert_eq!(policy.delay(40), MAX_RETRY_DELAY);
+    }
+
+    #[tokio::test]
+    async fn test_failed_mail_backs_off_then_dead_letters() {
+        let pool = crate::db::test_pool().await;
+        let outbox = Outbox::new(pool.clone());
+        let policy = RetryPolicy {
+            max_attempts: 2,
+            base_delay: Duration::from_secs(60),
+        };
+        let id = outbox.enqueue(&message()).await.unwrap();
+
+        assert!(outbox.deliver_next(&FailingTransport, &policy).await.unwrap());
+        assert_eq!(status(&pool, &id).await, ("pending".into(), 1));
+        // Not due again until the backoff has passed
+        assert!(!outbox.deliver_next(&FailingTransport, &policy).await.unwrap());
+
+        make_due(&pool).await;
+        assert!(outbox.deliver_next(&FailingTransport, &policy).await.unwrap());
+        assert_eq!(status(&pool, &id).await, ("dead".into(), 2));
+
+        sqlx::query("UPDATE mail_outbox SET status = 'pending', attempts = 0 WHERE id = ?")
+            .bind(&id)
+            .execute(&pool)
+            .await
+            .unwrap();
+        let transport = MemoryTransport::default();
+        assert!(outbox.deliver_next(&transport, &policy).await.unwrap());
+        assert_eq!(status(&pool, &id).await, ("sent".into(), 1));
+        assert_eq!(transport.sent(), vec![message()]);
+    }
+
+    #[tokio::test]
+    async fn test_suppressed_recipients_are_skipped() {
+        let pool = crate::db::test_pool().await;
+        let outbox = Outbox::new(pool.clone());
+        let transport = MemoryTransport::default();
+        suppressions::suppress(&pool, "b@example.com", "bounced").await.unwrap();
+
+        let id = outbox.enqueue(&message()).await.unwrap();
+        assert!(outbox.deliver_next(&transport, &RetryPolicy::default()).await.unwrap());
+        assert_eq!(status(&pool, &id).await, ("suppressed".into(), 0));
+
+        let mut partly = message();
+        partly.to.push("Other <other@example.com>".into());
+        outbox.enqueue(&partly).await.unwrap();
+        assert!(outbox.deliver_next(&transport, &RetryPolicy::default()).await.unwrap());
+        assert_eq!(transport.sent()[0].to, ["Other <other@example.com>"]);
+    }
+
+    #[tokio::test]
+    async fn test_suppression_check_failure_is_retried() {
+        let pool = crate::db::test_pool().await;
+        let outbox = Outbox::new(pool.clone());
+        let transport = MemoryTransport::default();
+        let id = outbox.enqueue(&message()).await.unwrap();
+
+        sqlx::query("ALTER TABLE mail_suppressions RENAME TO mail_suppressions_gone")
+            .execute(&pool)
+            .await
+            .unwrap();
+        assert!(outbox.deliver_next(&transport, &RetryPolicy::default()).await.unwrap());
+        assert_eq!(status(&pool, &id).await, ("pending".into(), 1));
+        assert!(transport.sent().is_empty());
+
+        sqlx::query("ALTER TABLE mail_suppressions_gone RENAME TO mail_suppressions")
+            .execute(&pool)
+            .await
+            .unwrap();
+        make_due(&pool).await;
+        assert!(outbox.deliver_next(&transport, &RetryPolicy::default()).await.unwrap());
+        assert_eq!(status(&pool, &id).await, ("sent".into(), 2));
+    }
+}
@@ -0,0 +1,355 @@
+use actix_session::Session;
+use actix_web::http::header;
+use actix_web::{web, HttpRequest, HttpResponse};
+use futures_util::StreamExt;
+use image::ImageFormat;
+use sha2::{Digest, Sha256};
+use sqlx::SqlitePool;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+
+use crate::auth::get_user_id;
+use crate::config::Config;
+use crate::downloads::{check_file_access, storage_error_response};
+use crate::storage::{ObjectMeta, StorageBackend, StorageError};
+
+/// Thumbnails fit within a square of this many pixels.
+const THUMBNAIL_SIZE: u32 = 256;
+/// Characters of text kept in a snippet preview.
+const SNIPPET_CHARS: usize = 1000;
+/// Files larger than this are never loaded to build a preview.
+const MAX_SOURCE_BYTES: u64 = 32 * 1024 * 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum SourceKind {
+    Image,
+    Pdf,
+    Text,
+}
+
+impl SourceKind {
+    fn for_path(file_path: &str) -> Option<Self> {
+        let mime = mime_guess::from_path(file_path).first()?;
+        match (mime.type_().as_str(), mime.subtype().as_str()) {
+            ("image", "png" | "jpeg" | "gif" | "webp") => Some(SourceKind::Image),
+            ("application", "pdf") => Some(SourceKind::Pdf),
+            ("text", _) | ("application", "json") => Some(SourceKind::Text),
+            _ => None,
+        }
+    }
+}
+
+/// A generated preview: a PNG thumbnail or a plain-text snippet.
+#[derive(Debug)]
+enum Preview {
+    Thumbnail(Vec<u8>),
+    Snippet(String),
+}
+
+impl Preview {
+    fn extension(kind: SourceKind) -> &'static str {
+        match kind {
+            SourceKind::Image => "png",
+            SourceKind::Pdf | SourceKind::Text => "txt",
+        }
+    }
+
+    fn into_bytes(self) -> Vec<u8> {
+        match self {
+            Preview::Thumbnail(png) => png,
+            Preview::Snippet(text) => text.into_bytes(),
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+enum PreviewError {
+    #[error("Storage error: {0}")]
+    Storage(#[from] StorageError),
+    #[error("File too large to preview")]
+    TooLarge,
+    #[error("Image error: {0}")]
+    Image(#[from] image::ImageError),
+    #[error("PDF error: {0}")]
+    Pdf(#[from] lopdf::Error),
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// URL of a file's preview, for the kinds of files previews can be built from.
+pub fn preview_url(file_id: &str, file_path: &str) -> Option<String> {
+    SourceKind::for_path(file_path).map(|_| format!("/api/files/{}/preview", file_id))
+}
+
+/// Serves a file's preview, generating and caching it on first request.
+/// Protected files need a logged-in user, as they do for downloading.
+pub async fn get_preview(
+    pool: web::Data<SqlitePool>,
+    storage: web::Data<dyn StorageBackend>,
+    config: web::Data<Config>,
+    session: Session,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> HttpResponse {
+    let file_id = path.into_inner();
+    let (file_path, is_protected) = match check_file_access(pool.get_ref(), &file_id).await {
+        Ok(file) => file,
+        Err(response) => return response,
+    };
+
+    if is_protected && get_user_id(&session).is_none() {
+        return HttpResponse::Unauthorized().body("Authentication required");
+    }
+
+    let kind = match SourceKind::for_path(&file_path) {
+        Some(kind) => kind,
+        None => return HttpResponse::NotFound().body("No preview available"),
+    };
+
+    let meta = match storage.metadata(&file_path).await {
+        Ok(meta) => meta,
+        Err(e) => return storage_error_response(&file_path, e),
+    };
+    if meta.size > MAX_SOURCE_BYTES {
+        return HttpResponse::NotFound().body("No preview available");
+    }
+
+    let result = load_preview(
+        pool.get_ref(),
+        storage.get_ref(),
+        Path::new(config.preview_cache_dir()),
+        &file_id,
+        &file_path,
+        &meta,
+        kind,
+    )
+    .await;
+
+    let (hash, body) = match result {
+        Ok(preview) => preview,
+        Err(PreviewError::Storage(e)) => return storage_error_response(&file_path, e),
+        Err(PreviewError::TooLarge) => return HttpResponse::NotFound().body("No preview available"),
+        Err(e @ (PreviewError::Image(_) | PreviewError::Pdf(_))) => {
+            tracing::debug!("Cannot preview {}: {}", file_path, e);
+            return HttpResponse::NotFound().body("No preview available");
+        }
+        Err(e) => {
+            tracing::error!("Failed to build preview of {}: {}", file_path, e);
+            return HttpResponse::InternalServerError().body("Failed to build preview");
+        }
+    };
+
+    let etag = format!("\"{}\"", hash);
+    let cache_control = if is_protected {
+        "private, max-age=3600"
+    } else {
+        "public, max-age=86400"
+    };
+
+    let matches = req
+        .headers()
+        .get(header::IF_NONE_MATCH)
+        .and_then(|h| h.to_str().ok())
+        .is_some_and(|value| value.split(',').any(|candidate| candidate.trim() == etag));
+    if matches {
+        return HttpResponse::NotModified()
+            .insert_header((header::ETAG, etag))
+            .finish();
+    }
+
+    let content_type = match kind {
+        SourceKind::Image => "image/png",
+        SourceKind::Pdf | SourceKind::Text => "text/plain; charset=utf-8",
+    };
+
+    HttpResponse::Ok()
+        .content_type(content_type)
+        .insert_header((header::ETAG, etag))
+        .insert_header((header::CACHE_CONTROL, cache_control))
+        .body(body)
+}
+
+/// Returns the content hash of the file and its preview, reading the file
+/// only when the hash or the cached preview is missing.
+async fn load_preview(
+    pool: &SqlitePool,
+    storage: &dyn StorageBackend,
+    cache_dir: &Path,
+    file_id: &str,
+    file_path: &str,
+    meta: &ObjectMeta,
+    kind: SourceKind,
+) -> Result<(String, Vec<u8>), PreviewError> {
+    let mut data = None;
+    let hash = match stored_hash(pool, file_id, meta).await {
+        Some(hash) => hash,
+        None => {
+            let bytes = read_all(storage, file_path).await?;
+            let hash = hex::encode(Sha256::digest(&bytes));
+            store_hash(pool, file_id, meta, &hash).await;
+            data = Some(bytes);
+            hash
+        }
+    };
+
+    let cache_path = cache_path(cache_dir, &hash, kind);
+    if let Ok(cached) = tokio::fs::read(&cache_path).await {
+        return Ok((hash, cached));
+    }
+
+    let bytes = match data {
+        Some(bytes) => bytes,
+        None => read_all(storage, file_path).await?,
+    };
+    let preview = tokio::task::spawn_blocking(move || generate(kind, &bytes))
+        .await
+        .map_err(std::io::Error::other)??
+        .into_bytes();
+
+    write_cache(&cache_path, &preview).await?;
+    Ok((hash, preview))
+}
+
+/// The recorded content hash, if the file has not changed since it was taken.
+/// Files whose backend provides no ETag are hashed on every cache lookup.
+async fn stored_hash(pool: &SqlitePool, file_id: &str, meta: &ObjectMeta) -> Option<String> {
+    let etag = meta.etag.as_deref()?;
+    sqlx::query_scalar::<_, String>(
+        "SELECT content_hash FROM download_files WHERE id = ? AND content_etag = ? AND content_hash IS NOT NULL",
+    )
+    .bind(file_id)
+    .bind(etag)
+    .fetch_optional(pool)
+    .await
+    .unwrap_or_else(|e| {
+        tracing::error!("Database error: {}", e);
+        None
+    })
+}
+
+async fn store_hash(pool: &SqlitePool, file_id: &str, meta: &ObjectMeta, hash: &str) {
+    if let Err(e) = sqlx::query("UPDATE download_files SET content_hash = ?, content_etag = ? WHERE id = ?")
+        .bind(hash)
+        .bind(&meta.etag)
+        .bind(file_id)
+        .execute(pool)
+        .await
+    {
+        tracing::error!("Failed to record content hash: {}", e);
+    }
+}
+
+async fn read_all(storage: &dyn StorageBackend, file_path: &str) -> Result<Vec<u8>, PreviewError> {
+    let mut stream = storage.read(file_path, None).await?;
+    let mut data = Vec::new();
+    while let Some(chunk) = stream.next().await {
+        data.extend_from_slice(&chunk?);
+        if data.len() as u64 > MAX_SOURCE_BYTES {
+            return Err(PreviewError::TooLarge);
+        }
+    }
+    Ok(data)
+}
+
+fn cache_path(cache_dir: &Path, hash: &str, kind: SourceKind) -> PathBuf {
+    cache_dir.join(format!("{}.{}", hash, Preview::extension(kind)))
+}
+
+/// Writes through a temporary file so concurrent readers never see a partial preview.
+async fn write_cache(cache_path: &Path, preview: &[u8]) -> std::io::Result<()> {
+    if let Some(dir) = cache_path.parent() {
+        tokio::fs::create_dir_all(dir).await?;
+    }
+    let tmp = cache_path.with_extension(format!("{}.tmp", uuid::Uuid::new_v4()));
+    tokio::fs::write(&tmp, preview).await?;
+    tokio::fs::rename(&tmp, cache_path).await
+}
+
+fn generate(kind: SourceKind, data: &[u8]) -> Result<Preview, PreviewError> {
+    match kind {
+        SourceKind::Image => {
+            let thumbnail = image::load_from_memory(data)?.thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE);
+            let mut png = Vec::new();
+            thumbnail.write_to(&mut Cursor::new(&mut png), ImageFormat::Png)?;
+            Ok(Preview::Thumbnail(png))
+        }
+        SourceKind::Pdf => {
+            let document = lopdf::Document::load_mem(data)?;
+            Ok(Preview::Snippet(snippet(&document.extract_text(&[1])?)))
+        }
+        SourceKind::Text => Ok(Preview::Snippet(snippet(&String::from_utf8_lossy(data)))),
+    }
+}
+
+/// The first [`SNIPPET_CHARS`] characters of `text`, with surrounding whitespace trimmed.
+fn snippet(text: &str) -> String {
+    text.trim().chars().take(SNIPPET_CHARS).collect::<String>().trim_end().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let mut data = Vec::new();
+        image::RgbImage::new(width, height)
+            .write_to(&mut Cursor::new(&mut data), ImageFormat::Png)
+            .unwrap();
+        data
+    }
+
+    #[test]
+    fn test_source_kind_for_path() {
+        assert_eq!(SourceKind::for_path("shots/screen.PNG"), Some(SourceKind::Image));
+        assert_eq!(SourceKind::for_path("manual.pdf"), Some(SourceKind::Pdf));
+        assert_eq!(SourceKind::for_path("CHANGELOG.md"), Some(SourceKind::Text));
+        assert_eq!(SourceKind::for_path("app.tar.gz"), None);
+        assert_eq!(preview_url("id", "app.zip"), None);
+        assert_eq!(preview_url("id", "notes.txt").unwrap(), "/api/files/id/preview");
+    }
+
+    #[test]
+    fn test_generate_thumbnail_and_snippet() {
+        let Preview::Thumbnail(thumbnail) = generate(SourceKind::Image, &png(600, 300)).unwrap() else {
+            panic!("expected a thumbnail");
+        };
+        let thumbnail = image::load_from_memory(&thumbnail).unwrap();
+        assert_eq!((thumbnail.width(), thumbnail.height()), (256, 128));
+
+        let text = format!("  {}", "é".repeat(SNIPPET_CHARS + 10));
+        let Preview::Snippet(snippet) = generate(SourceKind::Text, text.as_bytes()).unwrap() else {
+            panic!("expected a snippet");
+        };
+        assert_eq!(snippet.chars().count(), SNIPPET_CHARS);
+
+        assert!(generate(SourceKind::Image, b"not an image").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_load_preview_caches_by_hash() {
+        let pool = crate::db::test_pool().await;
+        sqlx::query("INSERT INTO download_files (id, file_path, display_name) VALUES ('f', 'a.png', 'A')")
+            .execute(&pool)
+            .await
+            .unwrap();
+        let storage = MemoryStorage::default();
+        let cache_dir = std::env::temp_dir().join(format!("previews-{}", uuid::Uuid::new_v4()));
+
+        let mut hashes = Vec::new();
+        for size in [10, 20] {
+            storage.insert("a.png", png(size, size)).unwrap();
+            let meta = storage.metadata("a.png").await.unwrap();
+            let (hash, preview) = load_preview(&pool, &storage, &cache_dir, "f", "a.png", &meta, SourceKind::Image)
+                .await
+                .unwrap();
+            let cached = std::fs::read(cache_path(&cache_dir, &hash, SourceKind::Image)).unwrap();
+            assert_eq!(cached, preview);
+            hashes.push(hash);
+        }
+        // Changed content gets its own cache entry
+        assert_ne!(hashes[0], hashes[1]);
+
+        std::fs::remove_dir_all(&cache_dir).unwrap();
+    }
+}
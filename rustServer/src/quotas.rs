@@ -0,0 +1,188 @@
+use actix_web::HttpResponse;
+use bytes::Bytes;
+use futures_util::{ready, Stream, StreamExt};
+use sqlx::SqlitePool;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::time::{Instant, Sleep};
+
+use crate::config::Config;
+use crate::storage::ByteStream;
+
+/// Limits applied to one user or one client IP over rolling windows.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct QuotaLimits {
+    pub bytes_per_day: Option<u64>,
+    pub downloads_per_hour: Option<u32>,
+}
+
+impl QuotaLimits {
+    fn is_unlimited(&self) -> bool {
+        self.bytes_per_day.is_none() && self.downloads_per_hour.is_none()
+    }
+}
+
+/// Rejects the request with `429 Too Many Requests` when the user or the
+/// client IP has exhausted a configured quota. Usage is taken from recorded
+/// download events, so transfers still in flight are not yet counted.
+pub async fn check_quota(
+    pool: &SqlitePool,
+    config: &Config,
+    user_id: Option<&str>,
+    ip_hash: &str,
+) -> Result<(), HttpResponse> {
+    if let Some(user_id) = user_id {
+        check_limits(pool, "user_id", user_id, &config.user_quota).await?;
+    }
+    check_limits(pool, "ip_hash", ip_hash, &config.ip_quota).await
+}
+
+async fn check_limits(
+    pool: &SqlitePool,
+    column: &str,
+    value: &str,
+    limits: &QuotaLimits,
+) -> Result<(), HttpResponse> {
+    if limits.is_unlimited() {
+        return Ok(());
+    }
+
+    let usage = sqlx::query_as::<_, (i64, i64)>(&format!(
+        r#"
+        SELECT COALESCE(SUM(bytes_sent), 0),
+               COUNT(CASE WHEN created_at >= datetime('now', '-1 hour') THEN 1 END)
+        FROM download_events
+        WHERE {} = ? AND created_at >= datetime('now', '-1 day')
+        "#,
+        column
+    ))
+    .bind(value)
+    .fetch_one(pool)
+    .await;
+
+    let (bytes_today, downloads_this_hour) = match usage {
+        Ok(usage) => usage,
+        Err(e) => {
+            tracing::error!("Database error checking quota: {}", e);
+            return Err(HttpResponse::InternalServerError().body("Database error"));
+        }
+    };
+
+    if limits.downloads_per_hour.is_some_and(|max| downloads_this_hour >= max as i64) {
+        return Err(HttpResponse::TooManyRequests()
+            .insert_header(("Retry-After", "3600"))
+            .body("Hourly download limit reached"));
+    }
+
+    if limits.bytes_per_day.is_some_and(|max| bytes_today >= max as i64) {
+        return Err(HttpResponse::TooManyRequests()
+            .insert_header(("Retry-After", "86400"))
+            .body("Daily download volume limit reached"));
+    }
+
+    Ok(())
+}
+
+/// Caps a response body at `bytes_per_second` when a limit is configured.
+pub fn throttle(body: ByteStream, bytes_per_second: Option<u64>) -> ByteStream {
+    match bytes_per_second {
+        Some(rate) if rate > 0 => ThrottledStream {
+            inner: body,
+            rate,
+            started: Instant::now(),
+            sent: 0,
+            delay: None,
+        }
+        .boxed(),
+        _ => body,
+    }
+}
+
+/// Holds each chunk back until the bytes sent before it fit within the rate
+/// since the first chunk went out.
+struct ThrottledStream {
+    inner: ByteStream,
+    rate: u64,
+    started: Instant,
+    sent: u64,
+    delay: Option<(Pin<Box<Sleep>>, Bytes)>,
+}
+
+impl Stream for ThrottledStream {
+    type Item = std::io::Result<Bytes>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if let Some((delay, _)) = self.delay.as_mut() {
+            ready!(delay.as_mut().poll(cx));
+            let (_, chunk) = self.delay.take().expect("delayed chunk");
+            return Poll::Ready(Some(Ok(chunk)));
+        }
+
+        let item = ready!(self.inner.as_mut().poll_next(cx));
+        if let Some(Ok(chunk)) = item {
+            let due = self.started + Duration::from_secs_f64(self.sent as f64 / self.rate as f64);
+            self.sent += chunk.len() as u64;
+            if due > Instant::now() {
+                let mut delay = Box::pin(tokio::time::sleep_until(due));
+                if delay.as_mut().poll(cx).is_pending() {
+                    self.delay = Some((delay, chunk));
+                    return Poll::Pending;
+                }
+            }
+            return Poll::Ready(Some(Ok(chunk)));
+        }
+        Poll::Ready(item)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::stream;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_throttle_caps_rate() {
+        let body = stream::iter((0..4).map(|_| Ok(Bytes::from_static(&[0; 100])))).boxed();
+        let started = Instant::now();
+
+        let received: usize = throttle(body, Some(100))
+            .map(|chunk| chunk.unwrap().len())
+            .fold(0, |total, len| async move { total + len })
+            .await;
+
+        assert_eq!(received, 400);
+        // Each chunk waits for the ones before it; the stream ends without a trailing delay
+        assert_eq!(started.elapsed().as_secs(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_check_quota() {
+        let pool = crate::db::test_pool().await;
+        for bytes in [400, 500] {
+            sqlx::query(
+                "INSERT INTO download_events (file_path, user_id, ip_hash, bytes_sent, completed) VALUES ('a', 'u1', 'ip1', ?, 1)",
+            )
+            .bind(bytes)
+            .execute(&pool)
+            .await
+            .unwrap();
+        }
+
+        let mut config = Config::default();
+        assert!(check_quota(&pool, &config, Some("u1"), "ip1").await.is_ok());
+
+        config.user_quota.bytes_per_day = Some(1000);
+        assert!(check_quota(&pool, &config, Some("u1"), "ip1").await.is_ok());
+
+        config.user_quota.downloads_per_hour = Some(2);
+        let response = check_quota(&pool, &config, Some("u1"), "ip1").await.unwrap_err();
+        assert_eq!(response.status(), 429);
+        assert!(check_quota(&pool, &config, Some("u2"), "ip2").await.is_ok());
+
+        config.ip_quota.bytes_per_day = Some(900);
+        assert!(check_quota(&pool, &config, None, "ip1").await.is_err());
+        assert!(check_quota(&pool, &config, None, "ip2").await.is_ok());
+    }
+}
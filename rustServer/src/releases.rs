@@ -0,0 +1,350 @@
+use actix_session::Session;
+use actix_web::{web, HttpResponse};
+use chrono::{DateTime, Utc};
+use semver::Version;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use uuid::Uuid;
+
+use crate::auth::{get_user_id, require_admin};
+use crate::config::Config;
+use crate::downloads::{DownloadFile, FileRow};
+use crate::storage::StorageBackend;
+
+#[derive(Serialize)]
+pub struct Release {
+    pub id: String,
+    pub project: String,
+    pub version: String,
+    /// Release notes in Markdown
+    pub notes: Option<String>,
+    pub is_prerelease: bool,
+    pub published_at: String,
+    pub files: Vec<DownloadFile>,
+}
+
+#[derive(Deserialize)]
+pub struct LatestQuery {
+    #[serde(default)]
+    pub prerelease: bool,
+}
+
+#[derive(Deserialize)]
+pub struct CreateReleaseRequest {
+    pub project: String,
+    pub version: String,
+    pub notes: Option<String>,
+    /// Defaults to whether `version` carries a semver pre-release tag
+    pub prerelease: Option<bool>,
+    /// Defaults to now; a future date schedules the release
+    pub published_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub file_ids: Vec<String>,
+}
+
+#[derive(Deserialize, Default)]
+pub struct YankRequest {
+    /// Overrides the configured `YANK_REVOKES_TOKENS` policy
+    pub revoke_tokens: Option<bool>,
+}
+
+type ReleaseRow = (String, String, String, Option<String>, i32, String);
+
+/// Lists published, non-yanked releases of a project, newest version first.
+pub async fn list_releases(
+    pool: web::Data<SqlitePool>,
+    storage: web::Data<dyn StorageBackend>,
+    session: Session,
+    path: web::Path<String>,
+) -> HttpResponse {
+    let project = path.into_inner();
+    let rows = match fetch_releases(pool.get_ref(), &project).await {
+        Ok(rows) => rows,
+        Err(e) => {
+            tracing::error!("Database error listing releases: {}", e);
+            return HttpResponse::InternalServerError().body("Error listing releases");
+        }
+    };
+
+    let include_protected = get_user_id(&session).is_some();
+    let mut releases = Vec::with_capacity(rows.len());
+    for row in sort_by_version(rows) {
+        match build_release(pool.get_ref(), storage.get_ref(), row, include_protected).await {
+            Ok(release) => releases.push(release),
+            Err(e) => {
+                tracing::error!("Database error listing release files: {}", e);
+                return HttpResponse::InternalServerError().body("Error listing releases");
+            }
+        }
+    }
+
+    HttpResponse::Ok().json(releases)
+}
+
+/// Returns the highest published, non-yanked version of a project.
+/// Pre-releases are only considered when `?prerelease=true` is given.
+pub async fn latest_release(
+    pool: web::Data<SqlitePool>,
+    storage: web::Data<dyn StorageBackend>,
+    session: Session,
+    path: web::Path<String>,
+    query: web::Query<LatestQuery>,
+) -> HttpResponse {
+    let project = path.into_inner();
+    let rows = match fetch_releases(pool.get_ref(), &project).await {
+        Ok(rows) => rows,
+        Err(e) => {
+            tracing::error!("Database error fetching latest release: {}", e);
+            return HttpResponse::InternalServerError().body("Database error");
+        }
+    };
+
+    let latest = sort_by_version(rows)
+        .into_iter()
+        .find(|row| query.prerelease || row.4 == 0);
+
+    let row = match latest {
+        Some(row) => row,
+        None => return HttpResponse::NotFound().body("No release found"),
+    };
+
+    match build_release(pool.get_ref(), storage.get_ref(), row, get_user_id(&session).is_some()).await {
+        Ok(release) => HttpResponse::Ok().json(release),
+        Err(e) => {
+            tracing::error!("Database error fetching release files: {}", e);
+            HttpResponse::InternalServerError().body("Database error")
+        }
+    }
+}
+
+pub async fn create_release(
+    pool: web::Data<SqlitePool>,
+    storage: web::Data<dyn StorageBackend>,
+    session: Session,
+    body: web::Json<CreateReleaseRequest>,
+) -> HttpResponse {
+    if let Err(response) = require_admin(pool.get_ref(), &session).await {
+        return response;
+    }
+
+    let version = match Version::parse(&body.version) {
+        Ok(v) => v,
+        Err(_) => return HttpResponse::BadRequest().body("Version must be valid semver"),
+    };
+
+    if body.project.trim().is_empty() {
+        return HttpResponse::BadRequest().body("Project is required");
+    }
+
+    let id = Uuid::new_v4().to_string();
+    let is_prerelease = body.prerelease.unwrap_or(!version.pre.is_empty());
+    let published_at = body
+        .published_at
+        .unwrap_or_else(Utc::now)
+        .format("%Y-%m-%d %H:%M:%S")
+        .to_string();
+
+    let mut tx = match pool.begin().await {
+        Ok(tx) => tx,
+        Err(e) => {
+            tracing::error!("Database error: {}", e);
+            return HttpResponse::InternalServerError().body("Database error");
+        }
+    };
+
+    let result = sqlx::query(
+        "INSERT INTO releases (id, project, version, notes, is_prerelease, published_at) VALUES (?, ?, ?, ?, ?, ?)",
+    )
+    .bind(&id)
+    .bind(body.project.trim())
+    .bind(version.to_string())
+    .bind(&body.notes)
+    .bind(is_prerelease as i32)
+    .bind(&published_at)
+    .execute(&mut *tx)
+    .await;
+
+    if let Err(e) = result {
+        if let sqlx::Error::Database(db_err) = &e {
+            if db_err.is_unique_violation() {
+                return HttpResponse::Conflict().body("Release already exists");
+            }
+        }
+        tracing::error!("Failed to create release: {}", e);
+        return HttpResponse::InternalServerError().body("Failed to create release");
+    }
+
+    for file_id in &body.file_ids {
+        let attached = sqlx::query("UPDATE download_files SET release_id = ? WHERE id = ?")
+            .bind(&id)
+            .bind(file_id)
+            .execute(&mut *tx)
+            .await;
+
+        match attached {
+            Ok(r) if r.rows_affected() == 0 => {
+                return HttpResponse::NotFound().body(format!("File not found: {}", file_id));
+            }
+            Ok(_) => {}
+            Err(e) => {
+                tracing::error!("Failed to attach file to release: {}", e);
+                return HttpResponse::InternalServerError().body("Failed to create release");
+            }
+        }
+    }
+
+    if let Err(e) = tx.commit().await {
+        tracing::error!("Failed to commit release: {}", e);
+        return HttpResponse::InternalServerError().body("Failed to create release");
+    }
+
+    let row = (
+        id,
+        body.project.trim().to_string(),
+        version.to_string(),
+        body.notes.clone(),
+        is_prerelease as i32,
+        published_at,
+    );
+    match build_release(pool.get_ref(), storage.get_ref(), row, true).await {
+        Ok(release) => HttpResponse::Created().json(release),
+        Err(e) => {
+            tracing::error!("Database error fetching release files: {}", e);
+            HttpResponse::InternalServerError().body("Database error")
+        }
+    }
+}
+
+/// Hides a release from listings. Outstanding download tokens for its files
+/// keep working unless token revocation is requested or configured.
+pub async fn yank_release(
+    pool: web::Data<SqlitePool>,
+    config: web::Data<Config>,
+    session: Session,
+    path: web::Path<(String, String)>,
+    body: Option<web::Json<YankRequest>>,
+) -> HttpResponse {
+    if let Err(response) = require_admin(pool.get_ref(), &session).await {
+        return response;
+    }
+
+    let (project, version) = path.into_inner();
+    let revoke_tokens = body
+        .map(|b| b.into_inner())
+        .unwrap_or_default()
+        .revoke_tokens
+        .unwrap_or(config.yank_revokes_tokens);
+
+    let release_id = sqlx::query_scalar::<_, String>(
+        "UPDATE releases SET yanked_at = datetime('now') WHERE project = ? AND version = ? AND yanked_at IS NULL RETURNING id",
+    )
+    .bind(&project)
+    .bind(&version)
+    .fetch_optional(pool.get_ref())
+    .await;
+
+    let release_id = match release_id {
+        Ok(Some(id)) => id,
+        Ok(None) => return HttpResponse::NotFound().body("Release not found"),
+        Err(e) => {
+            tracing::error!("Database error yanking release: {}", e);
+            return HttpResponse::InternalServerError().body("Database error");
+        }
+    };
+
+    if revoke_tokens {
+        let revoked = sqlx::query(
+            r#"
+            UPDATE download_tokens SET revoked_at = datetime('now')
+            WHERE used = 0 AND revoked_at IS NULL
+              AND file_id IN (SELECT id FROM download_files WHERE release_id = ?)
+            "#,
+        )
+        .bind(&release_id)
+        .execute(pool.get_ref())
+        .await;
+
+        if let Err(e) = revoked {
+            tracing::error!("Failed to revoke tokens for yanked release: {}", e);
+            return HttpResponse::InternalServerError().body("Failed to revoke tokens");
+        }
+    }
+
+    tracing::info!("Yanked release {} {}", project, version);
+    HttpResponse::NoContent().finish()
+}
+
+async fn fetch_releases(pool: &SqlitePool, project: &str) -> Result<Vec<ReleaseRow>, sqlx::Error> {
+    sqlx::query_as::<_, ReleaseRow>(
+        r#"
+        SELECT id, project, version, notes, is_prerelease, published_at
+        FROM releases
+        WHERE project = ? AND yanked_at IS NULL AND published_at <= datetime('now')
+        "#,
+    )
+    .bind(project)
+    .fetch_all(pool)
+    .await
+}
+
+async fn build_release(
+    pool: &SqlitePool,
+    storage: &dyn StorageBackend,
+    (id, project, version, notes, is_prerelease, published_at): ReleaseRow,
+    include_protected: bool,
+) -> Result<Release, sqlx::Error> {
+    let files = sqlx::query_as::<_, FileRow>(
+        r#"
+        SELECT id, file_path, display_name, description, is_protected, size_bytes
+        FROM download_files
+        WHERE release_id = ? AND (is_protected = 0 OR ?)
+        "#,
+    )
+    .bind(&id)
+    .bind(include_protected)
+    .fetch_all(pool)
+    .await?;
+
+    let mut download_files = Vec::with_capacity(files.len());
+    for row in files {
+        download_files.push(DownloadFile::from_row(storage, row).await);
+    }
+
+    Ok(Release {
+        id,
+        project,
+        version,
+        notes,
+        is_prerelease: is_prerelease != 0,
+        published_at,
+        files: download_files,
+    })
+}
+
+/// Orders releases by descending semver precedence; unparsable versions sort last.
+fn sort_by_version(mut rows: Vec<ReleaseRow>) -> Vec<ReleaseRow> {
+    rows.sort_by_cached_key(|row| std::cmp::Reverse(Version::parse(&row.2).ok()));
+    rows
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(version: &str) -> ReleaseRow {
+        (String::new(), "app".into(), version.into(), None, 0, String::new())
+    }
+
+    #[test]
+    fn test_sort_by_version() {
+        let sorted = sort_by_version(vec![
+            row("1.2.0"),
+            row("1.10.0"),
+            row("not-semver"),
+            row("1.10.0-rc.1"),
+            row("0.9.9"),
+        ]);
+        let versions: Vec<&str> = sorted.iter().map(|r| r.2.as_str()).collect();
+        assert_eq!(versions, ["1.10.0", "1.10.0-rc.1", "1.2.0", "0.9.9", "not-semver"]);
+    }
+}
@@ -0,0 +1,469 @@
+use actix_session::Session;
+use actix_web::{web, Either, HttpRequest, HttpResponse, Result};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use std::time::Duration;
+use uuid::Uuid;
+
+use crate::analytics::{client_ip_hash, DownloadRecorder};
+use crate::auth::{get_user_id, hash_password, is_admin, verify_password};
+use crate::config::Config;
+use crate::downloads::{check_file_access, serve_file};
+use crate::quotas::check_quota;
+use crate::storage::StorageBackend;
+
+/// Longest lifetime a share link may be given.
+const MAX_SHARE_HOURS: u32 = 30 * 24;
+
+/// Wrong passwords allowed within `PASSWORD_FAILURE_WINDOW`, per link and
+/// per client IP, before further attempts are refused.
+const MAX_PASSWORD_FAILURES_PER_SHARE: i64 = 10;
+const MAX_PASSWORD_FAILURES_PER_IP: i64 = 5;
+const PASSWORD_FAILURE_WINDOW: Duration = Duration::from_secs(15 * 60);
+
+/// SQL condition for a share link `sl` that can still be used.
+const SHARE_ACTIVE: &str = "sl.revoked_at IS NULL AND sl.expires_at > datetime('now') \
+     AND (sl.max_downloads IS NULL OR sl.download_count < sl.max_downloads)";
+
+#[derive(Deserialize)]
+pub struct CreateShareRequest {
+    pub file_id: String,
+    pub expires_in_hours: u32,
+    pub password: Option<String>,
+    pub max_downloads: Option<u32>,
+}
+
+#[derive(Deserialize)]
+pub struct SharePassword {
+    pub password: String,
+}
+
+#[derive(Serialize)]
+pub struct ShareLink {
+    pub id: String,
+    pub file_id: String,
+    pub display_name: String,
+    pub url: String,
+    pub expires_at: String,
+    pub has_password: bool,
+    pub max_downloads: Option<i64>,
+    pub download_count: i64,
+    pub revoked: bool,
+    pub created_at: String,
+}
+
+type ShareRow = (String, String, String, String, String, i32, Option<i64>, i64, i32, String);
+
+const SHARE_COLUMNS: &str = "sl.id, sl.code, sl.file_id, df.display_name, sl.expires_at, \
+     sl.password_hash IS NOT NULL, sl.max_downloads, sl.download_count, sl.revoked_at IS NOT NULL, sl.created_at";
+
+impl From<ShareRow> for ShareLink {
+    fn from(
+        (id, code, file_id, display_name, expires_at, has_password, max_downloads, download_count, revoked, created_at): ShareRow,
+    ) -> Self {
+        ShareLink {
+            id,
+            file_id,
+            display_name,
+            url: format!("/s/{}", code),
+            expires_at,
+            has_password: has_password != 0,
+            max_downloads,
+            download_count,
+            revoked: revoked != 0,
+            created_at,
+        }
+    }
+}
+
+/// Creates a share link for a file. Requires the `can_share` permission
+/// (administrators always have it).
+pub async fn create_share(
+    pool: web::Data<SqlitePool>,
+    session: Session,
+    body: web::Json<CreateShareRequest>,
+) -> HttpResponse {
+    let user_id = match get_user_id(&session) {
+        Some(id) => id,
+        None => return HttpResponse::Unauthorized().body("Authentication required"),
+    };
+
+    let allowed = sqlx::query_scalar::<_, i32>("SELECT is_admin OR can_share FROM users WHERE id = ?")
+        .bind(&user_id)
+        .fetch_optional(pool.get_ref())
+        .await;
+    match allowed {
+        Ok(Some(flag)) if flag != 0 => {}
+        Ok(_) => return HttpResponse::Forbidden().body("Not allowed to create share links"),
+        Err(e) => {
+            tracing::error!("Database error: {}", e);
+            return HttpResponse::InternalServerError().body("Database error");
+        }
+    }
+
+    if body.expires_in_hours == 0 || body.expires_in_hours > MAX_SHARE_HOURS {
+        return HttpResponse::BadRequest()
+            .body(format!("expires_in_hours must be between 1 and {}", MAX_SHARE_HOURS));
+    }
+    if body.max_downloads == Some(0) {
+        return HttpResponse::BadRequest().body("max_downloads must be at least 1");
+    }
+
+    if let Err(response) = check_file_access(pool.get_ref(), &body.file_id).await {
+        return response;
+    }
+
+    let password_hash = match body.password.as_deref().filter(|p| !p.is_empty()) {
+        Some(password) => match hash_password(password) {
+            Ok(hash) => Some(hash),
+            Err(e) => {
+                tracing::error!("Failed to hash share password: {}", e);
+                return HttpResponse::InternalServerError().body("Failed to create share link");
+            }
+        },
+        None => None,
+    };
+
+    let id = Uuid::new_v4().to_string();
+    let result = sqlx::query(
+        r#"
+        INSERT INTO share_links (id, code, file_id, user_id, expires_at, password_hash, max_downloads)
+        VALUES (?, ?, ?, ?, datetime('now', ?), ?, ?)
+        "#,
+    )
+    .bind(&id)
+    .bind(generate_code())
+    .bind(&body.file_id)
+    .bind(&user_id)
+    .bind(format!("+{} hours", body.expires_in_hours))
+    .bind(&password_hash)
+    .bind(body.max_downloads)
+    .execute(pool.get_ref())
+    .await;
+
+    if let Err(e) = result {
+        tracing::error!("Failed to create share link: {}", e);
+        return HttpResponse::InternalServerError().body("Failed to create share link");
+    }
+
+    match fetch_share(pool.get_ref(), "sl.id = ?", &id).await {
+        Ok(Some(share)) => HttpResponse::Created().json(share),
+        Ok(None) => HttpResponse::InternalServerError().body("Failed to create share link"),
+        Err(e) => {
+            tracing::error!("Database error: {}", e);
+            HttpResponse::InternalServerError().body("Database error")
+        }
+    }
+}
+
+/// Lists the share links created by the current user, newest first.
+pub async fn list_shares(pool: web::Data<SqlitePool>, session: Session) -> HttpResponse {
+    let user_id = match get_user_id(&session) {
+        Some(id) => id,
+        None => return HttpResponse::Unauthorized().body("Authentication required"),
+    };
+
+    let rows = sqlx::query_as::<_, ShareRow>(&format!(
+        r#"
+        SELECT {}
+        FROM share_links sl
+        JOIN download_files df ON df.id = sl.file_id
+        WHERE sl.user_id = ?
+        ORDER BY sl.created_at DESC
+        "#,
+        SHARE_COLUMNS
+    ))
+    .bind(&user_id)
+    .fetch_all(pool.get_ref())
+    .await;
+
+    match rows {
+        Ok(rows) => {
+            let shares: Vec<ShareLink> = rows.into_iter().map(ShareLink::from).collect();
+            HttpResponse::Ok().json(shares)
+        }
+        Err(e) => {
+            tracing::error!("Database error listing shares: {}", e);
+            HttpResponse::InternalServerError().body("Error listing share links")
+        }
+    }
+}
+
+/// Revokes one of the current user's share links; administrators may revoke any.
+pub async fn revoke_share(
+    pool: web::Data<SqlitePool>,
+    session: Session,
+    path: web::Path<String>,
+) -> HttpResponse {
+    let user_id = match get_user_id(&session) {
+        Some(id) => id,
+        None => return HttpResponse::Unauthorized().body("Authentication required"),
+    };
+    let share_id = path.into_inner();
+
+    let admin = match is_admin(pool.get_ref(), &user_id).await {
+        Ok(admin) => admin,
+        Err(e) => {
+            tracing::error!("Database error: {}", e);
+            return HttpResponse::InternalServerError().body("Database error");
+        }
+    };
+
+    let result = sqlx::query(
+        "UPDATE share_links SET revoked_at = datetime('now') WHERE id = ? AND (user_id = ? OR ?) AND revoked_at IS NULL",
+    )
+    .bind(&share_id)
+    .bind(&user_id)
+    .bind(admin)
+    .execute(pool.get_ref())
+    .await;
+
+    match result {
+        Ok(r) if r.rows_affected() == 0 => HttpResponse::NotFound().body("Share link not found"),
+        Ok(_) => HttpResponse::NoContent().finish(),
+        Err(e) => {
+            tracing::error!("Failed to revoke share link: {}", e);
+            HttpResponse::InternalServerError().body("Failed to revoke share link")
+        }
+    }
+}
+
+/// Anonymous access to a share link without a password.
+pub async fn open_share(
+    pool: web::Data<SqlitePool>,
+    storage: web::Data<dyn StorageBackend>,
+    config: web::Data<Config>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse> {
+    serve_share(&pool, storage.get_ref(), &config, &req, &path.into_inner(), None).await
+}
+
+/// Anonymous access to a password-protected share link. The password is
+/// posted (JSON or form) so it never ends up in request logs.
+pub async fn open_share_with_password(
+    pool: web::Data<SqlitePool>,
+    storage: web::Data<dyn StorageBackend>,
+    config: web::Data<Config>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    body: Either<web::Json<SharePassword>, web::Form<SharePassword>>,
+) -> Result<HttpResponse> {
+    let password = match body {
+        Either::Left(json) => json.into_inner().password,
+        Either::Right(form) => form.into_inner().password,
+    };
+    serve_share(&pool, storage.get_ref(), &config, &req, &path.into_inner(), Some(&password)).await
+}
+
+async fn serve_share(
+    pool: &SqlitePool,
+    storage: &dyn StorageBackend,
+    config: &Config,
+    req: &HttpRequest,
+    code: &str,
+    password: Option<&str>,
+) -> Result<HttpResponse> {
+    let share = sqlx::query_as::<_, (String, String, String, Option<String>, i32)>(&format!(
+        r#"
+        SELECT sl.id, sl.file_id, df.file_path, sl.password_hash, ({}) AS active
+        FROM share_links sl
+        JOIN download_files df ON df.id = sl.file_id
+        WHERE sl.code = ?
+        "#,
+        SHARE_ACTIVE
+    ))
+    .bind(code)
+    .fetch_optional(pool)
+    .await;
+
+    let (share_id, file_id, file_path, password_hash, active) = match share {
+        Ok(Some(share)) => share,
+        Ok(None) => return Ok(HttpResponse::NotFound().body("Share link not found")),
+        Err(e) => {
+            tracing::error!("Database error: {}", e);
+            return Ok(HttpResponse::InternalServerError().body("Database error"));
+        }
+    };
+
+    if active == 0 {
+        return Ok(HttpResponse::Gone().body("Share link has expired"));
+    }
+
+    let ip_hash = client_ip_hash(req, config);
+    if let Some(hash) = password_hash {
+        let Some(password) = password else {
+            return Ok(HttpResponse::Unauthorized().body("Password required"));
+        };
+        match too_many_password_failures(pool, &share_id, &ip_hash).await {
+            Ok(false) => {}
+            Ok(true) => {
+                return Ok(HttpResponse::TooManyRequests()
+                    .insert_header(("Retry-After", PASSWORD_FAILURE_WINDOW.as_secs().to_string()))
+                    .body("Too many incorrect passwords, try again later"))
+            }
+            Err(e) => {
+                tracing::error!("Database error: {}", e);
+                return Ok(HttpResponse::InternalServerError().body("Database error"));
+            }
+        }
+        if !verify_password(password, &hash) {
+            if let Err(e) = record_password_failure(pool, &share_id, &ip_hash).await {
+                tracing::error!("Database error recording password failure: {}", e);
+            }
+            return Ok(HttpResponse::Forbidden().body("Incorrect password"));
+        }
+    }
+
+    if let Err(response) = check_quota(pool, config, None, &ip_hash).await {
+        return Ok(response);
+    }
+
+    // Every request serving content counts, ranged ones included, so
+    // partial fetches can't get around the limit
+    match claim_download(pool, &share_id).await {
+        Ok(true) => {}
+        Ok(false) => return Ok(HttpResponse::Gone().body("Share link has expired")),
+        Err(e) => {
+            tracing::error!("Database error: {}", e);
+            return Ok(HttpResponse::InternalServerError().body("Database error"));
+        }
+    }
+
+    let recorder = DownloadRecorder::new(pool.clone(), Some(file_id), &file_path, None, ip_hash);
+    serve_file(req, storage, config, &file_path, Some(recorder)).await
+}
+
+/// Whether the link or the client IP has had too many wrong passwords lately.
+async fn too_many_password_failures(pool: &SqlitePool, share_id: &str, ip_hash: &str) -> Result<bool, sqlx::Error> {
+    let (per_share, per_ip) = sqlx::query_as::<_, (i64, i64)>(
+        r#"
+        SELECT COUNT(CASE WHEN share_id = ? THEN 1 END), COUNT(CASE WHEN ip_hash = ? THEN 1 END)
+        FROM share_password_failures
+        WHERE (share_id = ? OR ip_hash = ?) AND failed_at > datetime('now', ?)
+        "#,
+    )
+    .bind(share_id)
+    .bind(ip_hash)
+    .bind(share_id)
+    .bind(ip_hash)
+    .bind(format!("-{} seconds", PASSWORD_FAILURE_WINDOW.as_secs()))
+    .fetch_one(pool)
+    .await?;
+    Ok(per_share >= MAX_PASSWORD_FAILURES_PER_SHARE || per_ip >= MAX_PASSWORD_FAILURES_PER_IP)
+}
+
+/// Remembers a wrong password, forgetting those older than the window.
+async fn record_password_failure(pool: &SqlitePool, share_id: &str, ip_hash: &str) -> Result<(), sqlx::Error> {
+    let window = format!("-{} seconds", PASSWORD_FAILURE_WINDOW.as_secs());
+    sqlx::query("DELETE FROM share_password_failures WHERE failed_at <= datetime('now', ?)")
+        .bind(&window)
+        .execute(pool)
+        .await?;
+    sqlx::query("INSERT INTO share_password_failures (share_id, ip_hash) VALUES (?, ?)")
+        .bind(share_id)
+        .bind(ip_hash)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Counts a download against the link's limit, failing if it is no longer active.
+async fn claim_download(pool: &SqlitePool, share_id: &str) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query(&format!(
+        "UPDATE share_links AS sl SET download_count = download_count + 1 WHERE sl.id = ? AND {}",
+        SHARE_ACTIVE
+    ))
+    .bind(share_id)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+async fn fetch_share(pool: &SqlitePool, condition: &str, value: &str) -> Result<Option<ShareLink>, sqlx::Error> {
+    let row = sqlx::query_as::<_, ShareRow>(&format!(
+        "SELECT {} FROM share_links sl JOIN download_files df ON df.id = sl.file_id WHERE {}",
+        SHARE_COLUMNS, condition
+    ))
+    .bind(value)
+    .fetch_optional(pool)
+    .await?;
+    Ok(row.map(ShareLink::from))
+}
+
+fn generate_code() -> String {
+    let mut bytes = [0u8; 16];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_claim_download_respects_limits() {
+        let pool = crate::db::test_pool().await;
+        sqlx::query("INSERT INTO users (id, username, password_hash) VALUES ('u', 'alice', 'x')")
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query("INSERT INTO download_files (id, file_path, display_name) VALUES ('f', 'f.zip', 'F')")
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query(
+            r#"
+            INSERT INTO share_links (id, code, file_id, user_id, expires_at, max_downloads)
+            VALUES ('limited', 'c1', 'f', 'u', datetime('now', '+1 hour'), 2),
+                   ('expired', 'c2', 'f', 'u', datetime('now', '-1 hour'), NULL)
+            "#,
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        assert!(claim_download(&pool, "limited").await.unwrap());
+        assert!(claim_download(&pool, "limited").await.unwrap());
+        assert!(!claim_download(&pool, "limited").await.unwrap());
+        assert!(!claim_download(&pool, "expired").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_password_failures_are_throttled() {
+        let pool = crate::db::test_pool().await;
+
+        for _ in 0..MAX_PASSWORD_FAILURES_PER_IP {
+            assert!(!too_many_password_failures(&pool, "s1", "ip1").await.unwrap());
+            record_password_failure(&pool, "s1", "ip1").await.unwrap();
+        }
+        assert!(too_many_password_failures(&pool, "s1", "ip1").await.unwrap());
+        // The same IP is refused on other links too
+        assert!(too_many_password_failures(&pool, "s2", "ip1").await.unwrap());
+
+        // Guesses spread over many IPs still hit the per-link limit
+        for i in MAX_PASSWORD_FAILURES_PER_IP..MAX_PASSWORD_FAILURES_PER_SHARE {
+            assert!(!too_many_password_failures(&pool, "s1", &format!("other{}", i)).await.unwrap());
+            record_password_failure(&pool, "s1", &format!("other{}", i)).await.unwrap();
+        }
+        assert!(too_many_password_failures(&pool, "s1", "fresh").await.unwrap());
+        assert!(!too_many_password_failures(&pool, "s2", "fresh").await.unwrap());
+
+        // Failures age out of the window
+        sqlx::query("UPDATE share_password_failures SET failed_at = datetime('now', '-1 hour')")
+            .execute(&pool)
+            .await
+            .unwrap();
+        assert!(!too_many_password_failures(&pool, "s1", "ip1").await.unwrap());
+    }
+
+    #[test]
+    fn test_generate_code_is_url_safe() {
+        let code = generate_code();
+        assert_eq!(code.len(), 22);
+        assert!(code.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_'));
+    }
+}
@@ -0,0 +1,526 @@
+use actix_session::Session;
+use actix_web::{web, HttpResponse};
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use sqlx::SqlitePool;
+use std::sync::OnceLock;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::auth::require_admin;
+use crate::config::Config;
+use crate::inbox;
+use crate::mail::Templates;
+use crate::outbox::Outbox;
+use crate::validation::{ContactForm, FieldError};
+
+/// How long a form token stays valid after it was issued.
+const MAX_FORM_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+const LINK_POINTS: u32 = 2;
+const KEYWORD_POINTS: u32 = 3;
+const EXPIRED_FORM: &str = "The form has expired, please reload the page";
+
+/// Contact form spam defenses (`SPAM_*`, `FORM_SECRET`).
+#[derive(Clone, Debug)]
+pub struct SpamConfig {
+    /// Key signing form tokens; random per process when unset
+    pub secret: Option<String>,
+    /// Reject submissions without a form token rather than quarantine them
+    pub require_challenge: bool,
+    /// Submissions sooner than this after the form token was issued are quarantined
+    pub min_submit_time: Duration,
+    /// Leading zero bits required of the proof-of-work hash; 0 disables it
+    pub pow_difficulty: u8,
+    /// Lowercase words or phrases that count towards the spam score
+    pub keywords: Vec<String>,
+    /// Score at which a submission is quarantined
+    pub score_threshold: u32,
+}
+
+impl Default for SpamConfig {
+    fn default() -> Self {
+        Self {
+            secret: None,
+            require_challenge: false,
+            min_submit_time: Duration::from_secs(3),
+            pow_difficulty: 16,
+            keywords: Vec::new(),
+            score_threshold: 5,
+        }
+    }
+}
+
+/// Anti-spam fields sent alongside the contact form.
+#[derive(Debug, Default)]
+pub struct SpamFields<'a> {
+    /// Hidden field real visitors leave empty
+    pub honeypot: Option<&'a str>,
+    pub form_token: Option<&'a str>,
+    pub pow_solution: Option<&'a str>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Verdict {
+    Accept,
+    /// Suspected spam, kept for review with the reasons and content score
+    Quarantine { reasons: Vec<&'static str>, score: u32 },
+    /// The form's anti-spam fields are missing or invalid
+    Reject(FieldError),
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Challenge {
+    pub form_token: String,
+    pub pow_difficulty: u8,
+}
+
+#[derive(Serialize)]
+pub struct QuarantinedMessage {
+    pub id: i64,
+    pub sender: String,
+    pub first_name: String,
+    pub last_name: String,
+    pub message: String,
+    pub reasons: Vec<String>,
+    pub score: i64,
+    pub created_at: String,
+}
+
+/// Issues a signed form token for the contact form. Clients prove work by
+/// finding a `powSolution` such that SHA-256 of `"{formToken}:{powSolution}"`
+/// starts with `powDifficulty` zero bits.
+pub async fn challenge(config: web::Data<Config>) -> HttpResponse {
+    HttpResponse::Ok()
+        .insert_header(("Cache-Control", "no-store"))
+        .json(Challenge {
+            form_token: issue_token(&config.spam, unix_now()),
+            pow_difficulty: config.spam.pow_difficulty,
+        })
+}
+
+/// Decides whether a validated submission is delivered, quarantined or rejected.
+pub async fn assess(
+    pool: &SqlitePool,
+    config: &SpamConfig,
+    form: &ContactForm,
+    fields: &SpamFields<'_>,
+) -> Result<Verdict, sqlx::Error> {
+    let mut reasons = Vec::new();
+
+    if fields.honeypot.is_some_and(|value| !value.trim().is_empty()) {
+        reasons.push("honeypot");
+    }
+
+    match fields.form_token {
+        Some(token) => {
+            let (issued, nonce) = match verify_token(config, token, unix_now()) {
+                Ok(parts) => parts,
+                Err(code) => return Ok(reject("formToken", code, EXPIRED_FORM)),
+            };
+            if !proof_of_work_valid(token, fields.pow_solution.unwrap_or_default(), config.pow_difficulty) {
+                return Ok(reject("powSolution", "invalid", "Verification failed, please try again"));
+            }
+            if !claim_nonce(pool, nonce).await? {
+                return Ok(reject("formToken", "used", EXPIRED_FORM));
+            }
+            if unix_now().saturating_sub(issued) < config.min_submit_time.as_secs() {
+                reasons.push("too_fast");
+            }
+        }
+        None if config.require_challenge => {
+            return Ok(reject("formToken", "required", EXPIRED_FORM));
+        }
+        // Without a token neither the proof of work nor the timing was checked
+        None => reasons.push("no_challenge"),
+    }
+
+    let score = content_score(config, form);
+    if score >= config.score_threshold {
+        reasons.push("content");
+    }
+
+    Ok(if reasons.is_empty() {
+        Verdict::Accept
+    } else {
+        Verdict::Quarantine { reasons, score }
+    })
+}
+
+/// Keeps a suspected spam submission for review instead of delivering it.
+pub async fn quarantine(
+    pool: &SqlitePool,
+    form: &ContactForm,
+    reasons: &[&str],
+    score: u32,
+    ip_hash: &str,
+    user_agent: Option<&str>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        INSERT INTO contact_quarantine (sender, first_name, last_name, message, topic, reasons, score, ip_hash, user_agent)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+        "#,
+    )
+    .bind(&form.sender)
+    .bind(&form.first_name)
+    .bind(&form.last_name)
+    .bind(&form.message)
+    .bind(&form.topic)
+    .bind(reasons.join(","))
+    .bind(score)
+    .bind(ip_hash)
+    .bind(user_agent)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Lists quarantined submissions, newest first.
+pub async fn list_quarantine(pool: web::Data<SqlitePool>, session: Session) -> HttpResponse {
+    if let Err(response) = require_admin(pool.get_ref(), &session).await {
+        return response;
+    }
+
+    let rows = sqlx::query_as::<_, (i64, String, String, String, String, String, i64, String)>(
+        r#"
+        SELECT id, sender, first_name, last_name, message, reasons, score, created_at
+        FROM contact_quarantine
+        ORDER BY created_at DESC, id DESC
+        "#,
+    )
+    .fetch_all(pool.get_ref())
+    .await;
+
+    match rows {
+        Ok(rows) => {
+            let messages: Vec<QuarantinedMessage> = rows
+                .into_iter()
+                .map(|(id, sender, first_name, last_name, message, reasons, score, created_at)| QuarantinedMessage {
+                    id,
+                    sender,
+                    first_name,
+                    last_name,
+                    message,
+                    reasons: reasons.split(',').filter(|r| !r.is_empty()).map(String::from).collect(),
+                    score,
+                    created_at,
+                })
+                .collect();
+            HttpResponse::Ok().json(messages)
+        }
+        Err(e) => {
+            tracing::error!("Database error listing quarantine: {}", e);
+            HttpResponse::InternalServerError().body("Database error")
+        }
+    }
+}
+
+/// Moves a quarantined submission that turned out to be genuine to the
+/// inbox and delivers it.
+pub async fn release_quarantined(
+    pool: web::Data<SqlitePool>,
+    config: web::Data<Config>,
+    outbox: web::Data<Outbox>,
+    templates: web::Data<Templates>,
+    session: Session,
+    path: web::Path<i64>,
+) -> HttpResponse {
+    if let Err(response) = require_admin(pool.get_ref(), &session).await {
+        return response;
+    }
+
+    let id = path.into_inner();
+    let row = sqlx::query_as::<_, (String, String, String, String, Option<String>, String, Option<String>)>(
+        "SELECT sender, first_name, last_name, message, topic, ip_hash, user_agent FROM contact_quarantine WHERE id = ?",
+    )
+    .bind(id)
+    .fetch_optional(pool.get_ref())
+    .await;
+
+    let (form, ip_hash, user_agent) = match row {
+        Ok(Some((sender, first_name, last_name, message, topic, ip_hash, user_agent))) => (
+            ContactForm {
+                sender,
+                first_name,
+                last_name,
+                message,
+                topic,
+                // Attachments are not kept in quarantine
+                attachments: Vec::new(),
+            },
+            ip_hash,
+            user_agent,
+        ),
+        Ok(None) => return HttpResponse::NotFound().body("Message not found"),
+        Err(e) => {
+            tracing::error!("Database error releasing message: {}", e);
+            return HttpResponse::InternalServerError().body("Database error");
+        }
+    };
+
+    if let Err(e) =
+        inbox::submit(pool.get_ref(), outbox.get_ref(), templates.get_ref(), &config.contact, &form, &ip_hash, user_agent.as_deref()).await
+    {
+        tracing::error!("Failed to store released message: {}", e);
+        return HttpResponse::InternalServerError().body("Failed to release message");
+    }
+
+    // Only removed from quarantine once it is safely in the inbox
+    match sqlx::query("DELETE FROM contact_quarantine WHERE id = ?").bind(id).execute(pool.get_ref()).await {
+        Ok(_) => HttpResponse::Accepted().finish(),
+        Err(e) => {
+            tracing::error!("Released message {} but could not remove it from quarantine: {}", id, e);
+            HttpResponse::InternalServerError().body("Database error")
+        }
+    }
+}
+
+/// Permanently deletes a quarantined submission.
+pub async fn delete_quarantined(
+    pool: web::Data<SqlitePool>,
+    session: Session,
+    path: web::Path<i64>,
+) -> HttpResponse {
+    if let Err(response) = require_admin(pool.get_ref(), &session).await {
+        return response;
+    }
+
+    match sqlx::query("DELETE FROM contact_quarantine WHERE id = ?")
+        .bind(path.into_inner())
+        .execute(pool.get_ref())
+        .await
+    {
+        Ok(r) if r.rows_affected() == 0 => HttpResponse::NotFound().body("Message not found"),
+        Ok(_) => HttpResponse::NoContent().finish(),
+        Err(e) => {
+            tracing::error!("Database error deleting message: {}", e);
+            HttpResponse::InternalServerError().body("Database error")
+        }
+    }
+}
+
+fn reject(field: &'static str, code: &'static str, message: &str) -> Verdict {
+    Verdict::Reject(FieldError {
+        field,
+        code,
+        message: message.to_string(),
+    })
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// The configured secret, or a random one for the lifetime of the process.
+fn secret(config: &SpamConfig) -> &[u8] {
+    static FALLBACK_SECRET: OnceLock<String> = OnceLock::new();
+    match config.secret.as_deref() {
+        Some(secret) => secret.as_bytes(),
+        None => FALLBACK_SECRET
+            .get_or_init(|| uuid::Uuid::new_v4().to_string())
+            .as_bytes(),
+    }
+}
+
+fn sign(config: &SpamConfig, payload: &str) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret(config)).expect("HMAC accepts any key length");
+    mac.update(payload.as_bytes());
+    mac
+}
+
+/// `{issued_at}.{nonce}.{signature}`, with the signature over the first two parts.
+fn issue_token(config: &SpamConfig, issued_at: u64) -> String {
+    let mut nonce = [0u8; 12];
+    rand::thread_rng().fill_bytes(&mut nonce);
+    let payload = format!("{}.{}", issued_at, hex::encode(nonce));
+    let signature = hex::encode(sign(config, &payload).finalize().into_bytes());
+    format!("{}.{}", payload, signature)
+}
+
+/// A token old enough to pass `min_submit_time`, for submitting the contact
+/// form in tests.
+#[cfg(test)]
+pub fn test_token(config: &SpamConfig) -> String {
+    issue_token(config, unix_now().saturating_sub(config.min_submit_time.as_secs()))
+}
+
+/// Returns the token's issue time and nonce, or an error code.
+fn verify_token<'a>(config: &SpamConfig, token: &'a str, now: u64) -> Result<(u64, &'a str), &'static str> {
+    let mut parts = token.splitn(3, '.');
+    let (Some(issued), Some(nonce), Some(signature)) = (parts.next(), parts.next(), parts.next()) else {
+        return Err("invalid");
+    };
+    let signature = hex::decode(signature).map_err(|_| "invalid")?;
+    sign(config, &format!("{}.{}", issued, nonce))
+        .verify_slice(&signature)
+        .map_err(|_| "invalid")?;
+
+    let issued: u64 = issued.parse().map_err(|_| "invalid")?;
+    if now.saturating_sub(issued) > MAX_FORM_AGE.as_secs() {
+        return Err("expired");
+    }
+    Ok((issued, nonce))
+}
+
+fn proof_of_work_valid(token: &str, solution: &str, difficulty: u8) -> bool {
+    if difficulty == 0 {
+        return true;
+    }
+    let hash = Sha256::digest(format!("{}:{}", token, solution).as_bytes());
+    let mut remaining = difficulty as u32;
+    for byte in hash {
+        let zeros = byte.leading_zeros();
+        if zeros >= remaining {
+            return true;
+        }
+        if zeros < 8 {
+            return false;
+        }
+        remaining -= 8;
+    }
+    false
+}
+
+/// Records a form token as used, so a solved challenge cannot be replayed.
+async fn claim_nonce(pool: &SqlitePool, nonce: &str) -> Result<bool, sqlx::Error> {
+    sqlx::query("DELETE FROM contact_form_nonces WHERE used_at < datetime('now', ?)")
+        .bind(format!("-{} seconds", MAX_FORM_AGE.as_secs()))
+        .execute(pool)
+        .await?;
+    let result = sqlx::query("INSERT OR IGNORE INTO contact_form_nonces (nonce) VALUES (?)")
+        .bind(nonce)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected() == 1)
+}
+
+/// Points for links and configured keywords found anywhere in the submission.
+fn content_score(config: &SpamConfig, form: &ContactForm) -> u32 {
+    let text = format!("{} {} {} {}", form.first_name, form.last_name, form.sender, form.message).to_lowercase();
+    let links = ["http://", "https://", "www."]
+        .iter()
+        .map(|marker| text.matches(marker).count() as u32)
+        .sum::<u32>();
+    let keywords = config
+        .keywords
+        .iter()
+        .filter(|keyword| text.contains(keyword.as_str()))
+        .count() as u32;
+    links * LINK_POINTS + keywords * KEYWORD_POINTS
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn form(message: &str) -> ContactForm {
+        ContactForm {
+            sender: "visitor@example.com".into(),
+            first_name: "Ada".into(),
+            last_name: "Lovelace".into(),
+            message: message.into(),
+            topic: None,
+            attachments: Vec::new(),
+        }
+    }
+
+    fn solve(token: &str, difficulty: u8) -> String {
+        (0u64..)
+            .map(|n| n.to_string())
+            .find(|candidate| proof_of_work_valid(token, candidate, difficulty))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_token_signature_and_expiry() {
+        let config = SpamConfig {
+            secret: Some("secret".into()),
+            ..Default::default()
+        };
+        let token = issue_token(&config, 1_000);
+        assert_eq!(verify_token(&config, &token, 1_010).unwrap().0, 1_000);
+        assert_eq!(verify_token(&config, &token, 1_000 + MAX_FORM_AGE.as_secs() + 1), Err("expired"));
+
+        let forged = token.replacen("1000", "2000", 1);
+        assert_eq!(verify_token(&config, &forged, 2_010), Err("invalid"));
+        let other = SpamConfig {
+            secret: Some("other".into()),
+            ..Default::default()
+        };
+        assert_eq!(verify_token(&other, &token, 1_010), Err("invalid"));
+    }
+
+    #[test]
+    fn test_content_score() {
+        let config = SpamConfig {
+            keywords: vec!["casino".into()],
+            ..Default::default()
+        };
+        assert_eq!(content_score(&config, &form("Hello there")), 0);
+        assert_eq!(content_score(&config, &form("See https://a.example and www.b.example")), 4);
+        assert_eq!(content_score(&config, &form("Best CASINO at http://x.example")), 5);
+    }
+
+    #[tokio::test]
+    async fn test_assess_layers() {
+        let pool = crate::db::test_pool().await;
+        let config = SpamConfig {
+            pow_difficulty: 8,
+            min_submit_time: Duration::ZERO,
+            ..Default::default()
+        };
+
+        let plain = SpamFields::default();
+        assert!(matches!(
+            assess(&pool, &config, &form("Hi"), &plain).await.unwrap(),
+            Verdict::Quarantine { reasons, .. } if reasons == ["no_challenge"]
+        ));
+
+        let trapped = SpamFields {
+            honeypot: Some("http://spam.example"),
+            ..Default::default()
+        };
+        assert!(matches!(
+            assess(&pool, &config, &form("Hi"), &trapped).await.unwrap(),
+            Verdict::Quarantine { reasons, .. } if reasons == ["honeypot", "no_challenge"]
+        ));
+
+        let token = issue_token(&config, unix_now());
+        let unsolved = SpamFields {
+            form_token: Some(&token),
+            pow_solution: Some("wrong"),
+            ..Default::default()
+        };
+        let verdict = assess(&pool, &config, &form("Hi"), &unsolved).await.unwrap();
+        // A wrong solution is only possible to pass by luck at difficulty 8
+        if !proof_of_work_valid(&token, "wrong", 8) {
+            assert!(matches!(verdict, Verdict::Reject(FieldError { field: "powSolution", code: "invalid", .. })));
+        }
+
+        let solution = solve(&token, 8);
+        let solved = SpamFields {
+            form_token: Some(&token),
+            pow_solution: Some(&solution),
+            ..Default::default()
+        };
+        assert_eq!(assess(&pool, &config, &form("Hi"), &solved).await.unwrap(), Verdict::Accept);
+        assert!(matches!(
+            assess(&pool, &config, &form("Hi"), &solved).await.unwrap(),
+            Verdict::Reject(FieldError { field: "formToken", code: "used", .. })
+        ));
+
+        let strict = SpamConfig {
+            require_challenge: true,
+            ..config
+        };
+        assert!(matches!(
+            assess(&pool, &strict, &form("Hi"), &plain).await.unwrap(),
+            Verdict::Reject(FieldError { field: "formToken", code: "required", .. })
+        ));
+    }
+}
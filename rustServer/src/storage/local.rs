@@ -0,0 +1,106 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use futures_util::StreamExt;
+use std::io::SeekFrom;
+use std::path::{Path, PathBuf};
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio_util::io::ReaderStream;
+
+use super::{sanitize_path, ByteRange, ByteStream, ObjectMeta, StorageBackend, StorageError};
+
+/// Serves objects from a directory on the local filesystem.
+pub struct LocalStorage {
+    root: PathBuf,
+}
+
+impl LocalStorage {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    /// Resolves `path` beneath the root, ensuring the canonical location
+    /// (after following symlinks) is still inside it.
+    fn resolve(&self, path: &str) -> Result<PathBuf, StorageError> {
+        let safe_path = sanitize_path(path).ok_or_else(|| StorageError::InvalidPath(path.to_string()))?;
+
+        let canonical_root = std::fs::canonicalize(&self.root).map_err(|_| {
+            StorageError::Backend(format!("Downloads directory not found: {:?}", self.root))
+        })?;
+
+        let canonical_file = std::fs::canonicalize(Path::new(&self.root).join(&safe_path))
+            .map_err(|_| StorageError::NotFound(path.to_string()))?;
+
+        // Security: Ensure the resolved path is still within the root directory
+        if !canonical_file.starts_with(&canonical_root) {
+            tracing::warn!("Path traversal attempt detected: {}", path);
+            return Err(StorageError::InvalidPath(path.to_string()));
+        }
+
+        Ok(canonical_file)
+    }
+}
+
+#[async_trait]
+impl StorageBackend for LocalStorage {
+    async fn metadata(&self, path: &str) -> Result<ObjectMeta, StorageError> {
+        let file_path = self.resolve(path)?;
+        let metadata = tokio::fs::metadata(&file_path).await?;
+
+        if !metadata.is_file() {
+            return Err(StorageError::NotFound(path.to_string()));
+        }
+
+        let last_modified = metadata.modified().ok().map(DateTime::<Utc>::from);
+        Ok(ObjectMeta {
+            size: metadata.len(),
+            etag: last_modified.map(|m| format!("\"{:x}-{:x}\"", m.timestamp(), metadata.len())),
+            last_modified,
+            content_type: None,
+        })
+    }
+
+    async fn read(&self, path: &str, range: Option<ByteRange>) -> Result<ByteStream, StorageError> {
+        let file_path = self.resolve(path)?;
+        let mut file = tokio::fs::File::open(&file_path).await?;
+
+        match range {
+            Some(range) => {
+                file.seek(SeekFrom::Start(range.start)).await?;
+                Ok(ReaderStream::new(file.take(range.length)).boxed())
+            }
+            None => Ok(ReaderStream::new(file).boxed()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::TryStreamExt;
+
+    #[tokio::test]
+    async fn test_local_storage_reads_ranges_within_root() {
+        let root = std::env::temp_dir().join(format!("local-storage-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(root.join("nested")).unwrap();
+        std::fs::write(root.join("nested/file.txt"), b"0123456789").unwrap();
+        let storage = LocalStorage::new(&root);
+
+        assert_eq!(storage.metadata("nested/file.txt").await.unwrap().size, 10);
+
+        let range = ByteRange { start: 2, length: 3 };
+        let chunks: Vec<_> = storage
+            .read("nested/file.txt", Some(range))
+            .await
+            .unwrap()
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(chunks.concat(), b"234");
+
+        assert!(matches!(storage.metadata("nested").await, Err(StorageError::NotFound(_))));
+        assert!(matches!(storage.metadata("missing.txt").await, Err(StorageError::NotFound(_))));
+        assert!(matches!(storage.metadata("../outside").await, Err(StorageError::InvalidPath(_))));
+
+        std::fs::remove_dir_all(&root).ok();
+    }
+}
@@ -0,0 +1,62 @@
+use async_trait::async_trait;
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use futures_util::{stream, StreamExt};
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+use super::{object_key, ByteRange, ByteStream, ObjectMeta, StorageBackend, StorageError};
+
+/// Keeps objects in process memory. Intended for tests and throwaway setups.
+#[derive(Default)]
+pub struct MemoryStorage {
+    objects: RwLock<HashMap<String, (Bytes, DateTime<Utc>)>>,
+}
+
+impl MemoryStorage {
+    #[allow(dead_code)]
+    pub fn insert(&self, path: &str, data: impl Into<Bytes>) -> Result<(), StorageError> {
+        let key = object_key(path)?;
+        self.objects
+            .write()
+            .expect("memory storage lock poisoned")
+            .insert(key, (data.into(), Utc::now()));
+        Ok(())
+    }
+
+    fn get(&self, path: &str) -> Result<(Bytes, DateTime<Utc>), StorageError> {
+        let key = object_key(path)?;
+        self.objects
+            .read()
+            .expect("memory storage lock poisoned")
+            .get(&key)
+            .cloned()
+            .ok_or_else(|| StorageError::NotFound(path.to_string()))
+    }
+}
+
+#[async_trait]
+impl StorageBackend for MemoryStorage {
+    async fn metadata(&self, path: &str) -> Result<ObjectMeta, StorageError> {
+        let (data, modified) = self.get(path)?;
+        Ok(ObjectMeta {
+            size: data.len() as u64,
+            last_modified: Some(modified),
+            content_type: None,
+            etag: None,
+        })
+    }
+
+    async fn read(&self, path: &str, range: Option<ByteRange>) -> Result<ByteStream, StorageError> {
+        let (data, _) = self.get(path)?;
+        let data = match range {
+            Some(range) => {
+                let start = (range.start as usize).min(data.len());
+                let end = start.saturating_add(range.length as usize).min(data.len());
+                data.slice(start..end)
+            }
+            None => data,
+        };
+        Ok(stream::once(async move { Ok(data) }).boxed())
+    }
+}
@@ -0,0 +1,145 @@
+mod local;
+mod memory;
+mod s3;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use futures_util::stream::BoxStream;
+use std::path::{Component, PathBuf};
+use std::sync::Arc;
+use thiserror::Error;
+
+use crate::config::{Config, StorageKind};
+
+pub use local::LocalStorage;
+pub use memory::MemoryStorage;
+pub use s3::{S3Config, S3Storage};
+
+#[derive(Error, Debug)]
+pub enum StorageError {
+    #[error("Invalid path: {0}")]
+    InvalidPath(String),
+    #[error("Object not found: {0}")]
+    NotFound(String),
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Storage backend error: {0}")]
+    Backend(String),
+}
+
+/// Size, modification time and type of a stored object.
+#[derive(Clone, Debug)]
+pub struct ObjectMeta {
+    pub size: u64,
+    pub last_modified: Option<DateTime<Utc>>,
+    pub content_type: Option<String>,
+    pub etag: Option<String>,
+}
+
+/// An inclusive-exclusive byte window `start..start + length` of an object.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ByteRange {
+    pub start: u64,
+    pub length: u64,
+}
+
+pub type ByteStream = BoxStream<'static, std::io::Result<Bytes>>;
+
+/// Where download files live. Paths are relative to the backend's root and
+/// are always passed through [`sanitize_path`] before reaching the backend.
+#[async_trait]
+pub trait StorageBackend: Send + Sync {
+    async fn metadata(&self, path: &str) -> Result<ObjectMeta, StorageError>;
+
+    /// Streams the object, or only `range` of it when given.
+    async fn read(&self, path: &str, range: Option<ByteRange>) -> Result<ByteStream, StorageError>;
+
+    async fn exists(&self, path: &str) -> bool {
+        self.metadata(path).await.is_ok()
+    }
+}
+
+/// Builds the backend selected by `STORAGE_BACKEND`.
+pub fn from_config(config: &Config) -> Result<Arc<dyn StorageBackend>, StorageError> {
+    Ok(match &config.storage_kind {
+        StorageKind::Local => Arc::new(LocalStorage::new(config.downloads_dir())),
+        StorageKind::Memory => Arc::new(MemoryStorage::default()),
+        StorageKind::S3(s3) => Arc::new(S3Storage::new(s3.clone())?),
+    })
+}
+
+/// Normalizes a requested path into a relative object key, rejecting
+/// anything that could escape the storage root.
+pub fn sanitize_path(path: &str) -> Option<PathBuf> {
+    let path = path.trim_start_matches('/');
+
+    // Reject empty paths
+    if path.is_empty() {
+        return None;
+    }
+
+    // Reject paths with null bytes
+    if path.contains('\0') {
+        return None;
+    }
+
+    let path_buf = PathBuf::from(path);
+
+    // Reject paths with parent directory references
+    for component in path_buf.components() {
+        match component {
+            Component::Normal(_) => continue,
+            Component::ParentDir => return None,
+            Component::CurDir => continue,
+            _ => return None,
+        }
+    }
+
+    Some(path_buf)
+}
+
+/// Object key form of a path: sanitized, with `/` separators and no `.` segments.
+pub fn object_key(path: &str) -> Result<String, StorageError> {
+    let safe_path = sanitize_path(path).ok_or_else(|| StorageError::InvalidPath(path.to_string()))?;
+    let segments: Vec<String> = safe_path
+        .components()
+        .filter_map(|c| match c {
+            Component::Normal(s) => Some(s.to_string_lossy().into_owned()),
+            _ => None,
+        })
+        .collect();
+
+    if segments.is_empty() {
+        return Err(StorageError::InvalidPath(path.to_string()));
+    }
+
+    Ok(segments.join("/"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sanitize_path_valid() {
+        assert!(sanitize_path("file.zip").is_some());
+        assert!(sanitize_path("projects/myapp.tar.gz").is_some());
+        assert!(sanitize_path("nested/deep/file.pdf").is_some());
+    }
+
+    #[test]
+    fn test_sanitize_path_invalid() {
+        assert!(sanitize_path("../etc/passwd").is_none());
+        assert!(sanitize_path("foo/../bar").is_none());
+        assert!(sanitize_path("/absolute/path").is_some()); // Leading slash is stripped
+        assert!(sanitize_path("").is_none());
+    }
+
+    #[test]
+    fn test_object_key() {
+        assert_eq!(object_key("/nested/./file.zip").unwrap(), "nested/file.zip");
+        assert!(object_key("./").is_err());
+        assert!(object_key("../secret").is_err());
+    }
+}
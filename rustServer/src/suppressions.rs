@@ -0,0 +1,129 @@
+use actix_session::Session;
+use actix_web::{web, HttpResponse};
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+
+use crate::auth::require_admin;
+use crate::validation::{single_line, ValidationErrors};
+
+/// An address no automatic mail is sent to.
+#[derive(Serialize)]
+pub struct Suppression {
+    pub address: String,
+    pub reason: String,
+    pub created_at: String,
+}
+
+#[derive(Deserialize)]
+pub struct AddSuppressionRequest {
+    pub address: String,
+    pub reason: Option<String>,
+}
+
+/// The bare address of `Name <address>` mailboxes, compared case-insensitively.
+pub fn normalize(address: &str) -> String {
+    match address.parse::<lettre::message::Mailbox>() {
+        Ok(mailbox) => mailbox.email.to_string().to_lowercase(),
+        Err(_) => address.trim().to_lowercase(),
+    }
+}
+
+pub async fn is_suppressed(pool: &SqlitePool, address: &str) -> Result<bool, sqlx::Error> {
+    sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM mail_suppressions WHERE address = ?)")
+        .bind(normalize(address))
+        .fetch_one(pool)
+        .await
+}
+
+/// Adds an address to the suppression list, keeping the original reason if it is already there.
+pub async fn suppress(pool: &SqlitePool, address: &str, reason: &str) -> Result<(), sqlx::Error> {
+    sqlx::query("INSERT OR IGNORE INTO mail_suppressions (address, reason) VALUES (?, ?)")
+        .bind(normalize(address))
+        .bind(reason)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Lists suppressed addresses, most recently added first.
+pub async fn list_suppressions(pool: web::Data<SqlitePool>, session: Session) -> HttpResponse {
+    if let Err(response) = require_admin(pool.get_ref(), &session).await {
+        return response;
+    }
+
+    let rows = sqlx::query_as::<_, (String, String, String)>(
+        "SELECT address, reason, created_at FROM mail_suppressions ORDER BY created_at DESC, address",
+    )
+    .fetch_all(pool.get_ref())
+    .await;
+
+    match rows {
+        Ok(rows) => {
+            let suppressions: Vec<Suppression> = rows
+                .into_iter()
+                .map(|(address, reason, created_at)| Suppression {
+                    address,
+                    reason,
+                    created_at,
+                })
+                .collect();
+            HttpResponse::Ok().json(suppressions)
+        }
+        Err(e) => {
+            tracing::error!("Database error listing suppressions: {}", e);
+            HttpResponse::InternalServerError().body("Database error")
+        }
+    }
+}
+
+/// Suppresses an address by hand, e.g. at its owner's request.
+pub async fn add_suppression(
+    pool: web::Data<SqlitePool>,
+    session: Session,
+    body: web::Json<AddSuppressionRequest>,
+) -> HttpResponse {
+    if let Err(response) = require_admin(pool.get_ref(), &session).await {
+        return response;
+    }
+
+    let address = single_line(&body.address);
+    let mut errors = ValidationErrors::default();
+    crate::validation::check_email(&mut errors, "address", &address);
+    if !errors.errors.is_empty() {
+        return errors.into_response();
+    }
+    let reason = body.reason.as_deref().map(single_line).unwrap_or_default();
+    let reason = if reason.is_empty() { "manual".to_string() } else { reason };
+
+    match suppress(pool.get_ref(), &address, &reason).await {
+        Ok(()) => HttpResponse::NoContent().finish(),
+        Err(e) => {
+            tracing::error!("Database error adding suppression: {}", e);
+            HttpResponse::InternalServerError().body("Database error")
+        }
+    }
+}
+
+/// Removes an address from the suppression list.
+pub async fn remove_suppression(
+    pool: web::Data<SqlitePool>,
+    session: Session,
+    path: web::Path<String>,
+) -> HttpResponse {
+    if let Err(response) = require_admin(pool.get_ref(), &session).await {
+        return response;
+    }
+
+    match sqlx::query("DELETE FROM mail_suppressions WHERE address = ?")
+        .bind(normalize(&path.into_inner()))
+        .execute(pool.get_ref())
+        .await
+    {
+        Ok(r) if r.rows_affected() == 0 => HttpResponse::NotFound().body("Address not suppressed"),
+        Ok(_) => HttpResponse::NoContent().finish(),
+        Err(e) => {
+            tracing::error!("Database error removing suppression: {}", e);
+            HttpResponse::InternalServerError().body("Database error")
+        }
+    }
+}
@@ -0,0 +1,525 @@
+use actix_session::Session;
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpResponse};
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+use crate::auth::{get_user_id, require_admin};
+use crate::downloads::NOT_YANKED;
+
+#[derive(Serialize)]
+pub struct Tag {
+    pub id: String,
+    pub name: String,
+}
+
+#[derive(Serialize)]
+pub struct Category {
+    pub id: String,
+    pub name: String,
+    pub slug: String,
+    pub parent_id: Option<String>,
+    /// Visible files directly in this category
+    pub file_count: i64,
+    /// Visible files in this category and all of its subcategories
+    pub total_file_count: i64,
+}
+
+#[derive(Deserialize)]
+pub struct CreateTagRequest {
+    pub name: String,
+}
+
+#[derive(Deserialize)]
+pub struct SetFileTagsRequest {
+    /// Tag names; unknown tags are created
+    pub tags: Vec<String>,
+}
+
+#[derive(Deserialize)]
+pub struct CategoryRequest {
+    pub name: String,
+    pub slug: String,
+    pub parent_id: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct SetFileCategoryRequest {
+    pub category_id: Option<String>,
+}
+
+pub async fn list_tags(pool: web::Data<SqlitePool>) -> HttpResponse {
+    let tags = sqlx::query_as::<_, (String, String)>("SELECT id, name FROM tags ORDER BY name")
+        .fetch_all(pool.get_ref())
+        .await;
+
+    match tags {
+        Ok(rows) => {
+            let tags: Vec<Tag> = rows.into_iter().map(|(id, name)| Tag { id, name }).collect();
+            HttpResponse::Ok().json(tags)
+        }
+        Err(e) => {
+            tracing::error!("Database error listing tags: {}", e);
+            HttpResponse::InternalServerError().body("Error listing tags")
+        }
+    }
+}
+
+pub async fn create_tag(
+    pool: web::Data<SqlitePool>,
+    session: Session,
+    body: web::Json<CreateTagRequest>,
+) -> HttpResponse {
+    if let Err(response) = require_admin(pool.get_ref(), &session).await {
+        return response;
+    }
+
+    let name = match normalize_tag(&body.name) {
+        Some(name) => name,
+        None => return HttpResponse::BadRequest().body("Tag name is required"),
+    };
+
+    let id = Uuid::new_v4().to_string();
+    let result = sqlx::query("INSERT INTO tags (id, name) VALUES (?, ?)")
+        .bind(&id)
+        .bind(&name)
+        .execute(pool.get_ref())
+        .await;
+
+    match result {
+        Ok(_) => HttpResponse::Created().json(Tag { id, name }),
+        Err(sqlx::Error::Database(e)) if e.is_unique_violation() => {
+            HttpResponse::Conflict().body("Tag already exists")
+        }
+        Err(e) => {
+            tracing::error!("Failed to create tag: {}", e);
+            HttpResponse::InternalServerError().body("Failed to create tag")
+        }
+    }
+}
+
+pub async fn delete_tag(
+    pool: web::Data<SqlitePool>,
+    session: Session,
+    path: web::Path<String>,
+) -> HttpResponse {
+    if let Err(response) = require_admin(pool.get_ref(), &session).await {
+        return response;
+    }
+
+    let tag_id = path.into_inner();
+    let result: Result<u64, sqlx::Error> = async {
+        let mut tx = pool.begin().await?;
+        sqlx::query("DELETE FROM file_tags WHERE tag_id = ?")
+            .bind(&tag_id)
+            .execute(&mut *tx)
+            .await?;
+        let deleted = sqlx::query("DELETE FROM tags WHERE id = ?")
+            .bind(&tag_id)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+        tx.commit().await?;
+        Ok(deleted)
+    }
+    .await;
+
+    match result {
+        Ok(0) => HttpResponse::NotFound().body("Tag not found"),
+        Ok(_) => HttpResponse::NoContent().finish(),
+        Err(e) => {
+            tracing::error!("Failed to delete tag: {}", e);
+            HttpResponse::InternalServerError().body("Failed to delete tag")
+        }
+    }
+}
+
+/// Replaces the tags of a file.
+pub async fn set_file_tags(
+    pool: web::Data<SqlitePool>,
+    session: Session,
+    path: web::Path<String>,
+    body: web::Json<SetFileTagsRequest>,
+) -> HttpResponse {
+    if let Err(response) = require_admin(pool.get_ref(), &session).await {
+        return response;
+    }
+
+    let file_id = path.into_inner();
+    let mut names: Vec<String> = body.tags.iter().filter_map(|t| normalize_tag(t)).collect();
+    names.sort();
+    names.dedup();
+
+    let result: Result<bool, sqlx::Error> = async {
+        let mut tx = pool.begin().await?;
+
+        let exists = sqlx::query_scalar::<_, i32>("SELECT 1 FROM download_files WHERE id = ?")
+            .bind(&file_id)
+            .fetch_optional(&mut *tx)
+            .await?
+            .is_some();
+        if !exists {
+            return Ok(false);
+        }
+
+        sqlx::query("DELETE FROM file_tags WHERE file_id = ?")
+            .bind(&file_id)
+            .execute(&mut *tx)
+            .await?;
+
+        for name in &names {
+            sqlx::query("INSERT INTO tags (id, name) VALUES (?, ?) ON CONFLICT (name) DO NOTHING")
+                .bind(Uuid::new_v4().to_string())
+                .bind(name)
+                .execute(&mut *tx)
+                .await?;
+            sqlx::query("INSERT INTO file_tags (file_id, tag_id) SELECT ?, id FROM tags WHERE name = ?")
+                .bind(&file_id)
+                .bind(name)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        tx.commit().await?;
+        Ok(true)
+    }
+    .await;
+
+    match result {
+        Ok(true) => HttpResponse::Ok().json(names),
+        Ok(false) => HttpResponse::NotFound().body("File not found"),
+        Err(e) => {
+            tracing::error!("Failed to set file tags: {}", e);
+            HttpResponse::InternalServerError().body("Failed to set tags")
+        }
+    }
+}
+
+/// Lists all categories with file counts, applying the same visibility
+/// rules as `list_files`: protected files only count for logged-in users.
+pub async fn list_categories(pool: web::Data<SqlitePool>, session: Session) -> HttpResponse {
+    let include_protected = get_user_id(&session).is_some();
+
+    let rows = sqlx::query_as::<_, (String, String, String, Option<String>, i64)>(&format!(
+        r#"
+        SELECT c.id, c.name, c.slug, c.parent_id, COUNT(df.id)
+        FROM categories c
+        LEFT JOIN download_files df
+            ON df.category_id = c.id AND (df.is_protected = 0 OR ?) AND {}
+        GROUP BY c.id
+        ORDER BY c.name
+        "#,
+        NOT_YANKED
+    ))
+    .bind(include_protected)
+    .fetch_all(pool.get_ref())
+    .await;
+
+    match rows {
+        Ok(rows) => HttpResponse::Ok().json(with_totals(rows)),
+        Err(e) => {
+            tracing::error!("Database error listing categories: {}", e);
+            HttpResponse::InternalServerError().body("Error listing categories")
+        }
+    }
+}
+
+pub async fn create_category(
+    pool: web::Data<SqlitePool>,
+    session: Session,
+    body: web::Json<CategoryRequest>,
+) -> HttpResponse {
+    if let Err(response) = require_admin(pool.get_ref(), &session).await {
+        return response;
+    }
+
+    if let Err(response) = validate_category(pool.get_ref(), None, &body).await {
+        return response;
+    }
+
+    let id = Uuid::new_v4().to_string();
+    let result = sqlx::query("INSERT INTO categories (id, name, slug, parent_id) VALUES (?, ?, ?, ?)")
+        .bind(&id)
+        .bind(body.name.trim())
+        .bind(&body.slug)
+        .bind(&body.parent_id)
+        .execute(pool.get_ref())
+        .await;
+
+    category_write_response(result, StatusCode::CREATED, id, body.into_inner())
+}
+
+pub async fn update_category(
+    pool: web::Data<SqlitePool>,
+    session: Session,
+    path: web::Path<String>,
+    body: web::Json<CategoryRequest>,
+) -> HttpResponse {
+    if let Err(response) = require_admin(pool.get_ref(), &session).await {
+        return response;
+    }
+
+    let id = path.into_inner();
+    if let Err(response) = validate_category(pool.get_ref(), Some(&id), &body).await {
+        return response;
+    }
+
+    let result = sqlx::query("UPDATE categories SET name = ?, slug = ?, parent_id = ? WHERE id = ?")
+        .bind(body.name.trim())
+        .bind(&body.slug)
+        .bind(&body.parent_id)
+        .bind(&id)
+        .execute(pool.get_ref())
+        .await;
+
+    match result {
+        Ok(r) if r.rows_affected() == 0 => HttpResponse::NotFound().body("Category not found"),
+        result => category_write_response(result, StatusCode::OK, id, body.into_inner()),
+    }
+}
+
+/// Deletes an empty-of-children category; its files become uncategorized.
+pub async fn delete_category(
+    pool: web::Data<SqlitePool>,
+    session: Session,
+    path: web::Path<String>,
+) -> HttpResponse {
+    if let Err(response) = require_admin(pool.get_ref(), &session).await {
+        return response;
+    }
+
+    let id = path.into_inner();
+    let result: Result<Option<u64>, sqlx::Error> = async {
+        let mut tx = pool.begin().await?;
+
+        let has_children = sqlx::query_scalar::<_, i32>("SELECT 1 FROM categories WHERE parent_id = ? LIMIT 1")
+            .bind(&id)
+            .fetch_optional(&mut *tx)
+            .await?
+            .is_some();
+        if has_children {
+            return Ok(None);
+        }
+
+        sqlx::query("UPDATE download_files SET category_id = NULL WHERE category_id = ?")
+            .bind(&id)
+            .execute(&mut *tx)
+            .await?;
+        let deleted = sqlx::query("DELETE FROM categories WHERE id = ?")
+            .bind(&id)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+
+        tx.commit().await?;
+        Ok(Some(deleted))
+    }
+    .await;
+
+    match result {
+        Ok(None) => HttpResponse::Conflict().body("Category has subcategories"),
+        Ok(Some(0)) => HttpResponse::NotFound().body("Category not found"),
+        Ok(Some(_)) => HttpResponse::NoContent().finish(),
+        Err(e) => {
+            tracing::error!("Failed to delete category: {}", e);
+            HttpResponse::InternalServerError().body("Failed to delete category")
+        }
+    }
+}
+
+pub async fn set_file_category(
+    pool: web::Data<SqlitePool>,
+    session: Session,
+    path: web::Path<String>,
+    body: web::Json<SetFileCategoryRequest>,
+) -> HttpResponse {
+    if let Err(response) = require_admin(pool.get_ref(), &session).await {
+        return response;
+    }
+
+    let file_id = path.into_inner();
+    if let Some(category_id) = &body.category_id {
+        match category_exists(pool.get_ref(), category_id).await {
+            Ok(true) => {}
+            Ok(false) => return HttpResponse::NotFound().body("Category not found"),
+            Err(response) => return response,
+        }
+    }
+
+    let result = sqlx::query("UPDATE download_files SET category_id = ? WHERE id = ?")
+        .bind(&body.category_id)
+        .bind(&file_id)
+        .execute(pool.get_ref())
+        .await;
+
+    match result {
+        Ok(r) if r.rows_affected() == 0 => HttpResponse::NotFound().body("File not found"),
+        Ok(_) => HttpResponse::NoContent().finish(),
+        Err(e) => {
+            tracing::error!("Failed to set file category: {}", e);
+            HttpResponse::InternalServerError().body("Failed to set category")
+        }
+    }
+}
+
+/// Checks slug format and that the parent exists and is not the category
+/// itself or one of its descendants.
+async fn validate_category(
+    pool: &SqlitePool,
+    id: Option<&str>,
+    body: &CategoryRequest,
+) -> Result<(), HttpResponse> {
+    if body.name.trim().is_empty() {
+        return Err(HttpResponse::BadRequest().body("Category name is required"));
+    }
+    if !is_valid_slug(&body.slug) {
+        return Err(HttpResponse::BadRequest()
+            .body("Slug may only contain lowercase letters, digits and hyphens"));
+    }
+
+    let Some(parent_id) = body.parent_id.as_deref() else {
+        return Ok(());
+    };
+
+    // Walk up from the new parent; reaching the category itself means a cycle
+    let mut current = Some(parent_id.to_string());
+    let mut first = true;
+    while let Some(ancestor) = current {
+        if Some(ancestor.as_str()) == id {
+            return Err(HttpResponse::BadRequest().body("A category cannot be its own ancestor"));
+        }
+
+        let parent = sqlx::query_scalar::<_, Option<String>>("SELECT parent_id FROM categories WHERE id = ?")
+            .bind(&ancestor)
+            .fetch_optional(pool)
+            .await;
+
+        current = match parent {
+            Ok(Some(parent)) => parent,
+            Ok(None) if first => return Err(HttpResponse::NotFound().body("Parent category not found")),
+            Ok(None) => None,
+            Err(e) => {
+                tracing::error!("Database error validating category: {}", e);
+                return Err(HttpResponse::InternalServerError().body("Database error"));
+            }
+        };
+        first = false;
+    }
+
+    Ok(())
+}
+
+async fn category_exists(pool: &SqlitePool, id: &str) -> Result<bool, HttpResponse> {
+    sqlx::query_scalar::<_, i32>("SELECT 1 FROM categories WHERE id = ?")
+        .bind(id)
+        .fetch_optional(pool)
+        .await
+        .map(|row| row.is_some())
+        .map_err(|e| {
+            tracing::error!("Database error: {}", e);
+            HttpResponse::InternalServerError().body("Database error")
+        })
+}
+
+fn category_write_response(
+    result: Result<sqlx::sqlite::SqliteQueryResult, sqlx::Error>,
+    status: StatusCode,
+    id: String,
+    body: CategoryRequest,
+) -> HttpResponse {
+    match result {
+        Ok(_) => HttpResponse::build(status).json(Category {
+            id,
+            name: body.name.trim().to_string(),
+            slug: body.slug,
+            parent_id: body.parent_id,
+            file_count: 0,
+            total_file_count: 0,
+        }),
+        Err(sqlx::Error::Database(e)) if e.is_unique_violation() => {
+            HttpResponse::Conflict().body("Slug already in use")
+        }
+        Err(e) => {
+            tracing::error!("Failed to save category: {}", e);
+            HttpResponse::InternalServerError().body("Failed to save category")
+        }
+    }
+}
+
+/// Adds each category's direct count to itself and all of its ancestors.
+fn with_totals(rows: Vec<(String, String, String, Option<String>, i64)>) -> Vec<Category> {
+    let parents: HashMap<String, Option<String>> =
+        rows.iter().map(|r| (r.0.clone(), r.3.clone())).collect();
+    let mut totals: HashMap<String, i64> = HashMap::new();
+
+    for (id, _, _, _, count) in &rows {
+        let mut current = Some(id.clone());
+        // Bounded by the number of categories in case stored data has a cycle
+        for _ in 0..rows.len() {
+            let Some(category) = current else { break };
+            *totals.entry(category.clone()).or_default() += count;
+            current = parents.get(&category).cloned().flatten();
+        }
+    }
+
+    rows.into_iter()
+        .map(|(id, name, slug, parent_id, file_count)| Category {
+            total_file_count: totals.get(&id).copied().unwrap_or_default(),
+            id,
+            name,
+            slug,
+            parent_id,
+            file_count,
+        })
+        .collect()
+}
+
+pub fn normalize_tag(name: &str) -> Option<String> {
+    let name = name.trim().to_lowercase();
+    (!name.is_empty()).then_some(name)
+}
+
+fn is_valid_slug(slug: &str) -> bool {
+    !slug.is_empty()
+        && slug
+            .bytes()
+            .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_with_totals_rolls_up_to_ancestors() {
+        let row = |id: &str, parent: Option<&str>, count| {
+            (id.to_string(), id.to_string(), id.to_string(), parent.map(str::to_string), count)
+        };
+        let categories = with_totals(vec![
+            row("software", None, 1),
+            row("linux", Some("software"), 2),
+            row("arm", Some("linux"), 3),
+            row("docs", None, 4),
+        ]);
+
+        let totals: Vec<(&str, i64, i64)> = categories
+            .iter()
+            .map(|c| (c.id.as_str(), c.file_count, c.total_file_count))
+            .collect();
+        assert_eq!(
+            totals,
+            [("software", 1, 6), ("linux", 2, 5), ("arm", 3, 3), ("docs", 4, 4)]
+        );
+    }
+
+    #[test]
+    fn test_slug_and_tag_normalization() {
+        assert!(is_valid_slug("linux-arm64"));
+        assert!(!is_valid_slug("Linux"));
+        assert!(!is_valid_slug(""));
+        assert_eq!(normalize_tag("  CLI ").as_deref(), Some("cli"));
+        assert_eq!(normalize_tag("   "), None);
+    }
+}
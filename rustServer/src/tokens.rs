@@ -0,0 +1,239 @@
+use actix_session::Session;
+use actix_web::{web, HttpResponse};
+use serde::Serialize;
+use sqlx::SqlitePool;
+
+use crate::auth::{get_user_id, require_admin};
+
+/// A download token as shown to its owner. The token value itself is not
+/// included; it was handed out once when the token was generated.
+#[derive(Serialize)]
+pub struct TokenInfo {
+    pub id: String,
+    pub file_id: String,
+    pub display_name: String,
+    pub created_at: String,
+    pub used: bool,
+    pub expires_at: Option<String>,
+    pub expired: bool,
+}
+
+#[derive(Serialize)]
+pub struct RevokedTokens {
+    pub revoked: u64,
+}
+
+type TokenRow = (String, String, String, String, i32, Option<String>, i32);
+
+/// Lists the current user's download tokens that have not been revoked, newest first.
+pub async fn list_tokens(pool: web::Data<SqlitePool>, session: Session) -> HttpResponse {
+    let user_id = match get_user_id(&session) {
+        Some(id) => id,
+        None => return HttpResponse::Unauthorized().body("Authentication required"),
+    };
+
+    let rows = sqlx::query_as::<_, TokenRow>(
+        r#"
+        SELECT dt.id, dt.file_id, df.display_name, dt.created_at, dt.used, dt.expires_at,
+               COALESCE(dt.expires_at <= datetime('now'), 0)
+        FROM download_tokens dt
+        JOIN download_files df ON df.id = dt.file_id
+        WHERE dt.user_id = ? AND dt.revoked_at IS NULL
+        ORDER BY dt.created_at DESC, dt.id
+        "#,
+    )
+    .bind(&user_id)
+    .fetch_all(pool.get_ref())
+    .await;
+
+    match rows {
+        Ok(rows) => {
+            let tokens: Vec<TokenInfo> = rows
+                .into_iter()
+                .map(|(id, file_id, display_name, created_at, used, expires_at, expired)| TokenInfo {
+                    id,
+                    file_id,
+                    display_name,
+                    created_at,
+                    used: used != 0,
+                    expires_at,
+                    expired: expired != 0,
+                })
+                .collect();
+            HttpResponse::Ok().json(tokens)
+        }
+        Err(e) => {
+            tracing::error!("Database error listing tokens: {}", e);
+            HttpResponse::InternalServerError().body("Error listing tokens")
+        }
+    }
+}
+
+/// Revokes one of the current user's download tokens.
+pub async fn revoke_token(
+    pool: web::Data<SqlitePool>,
+    session: Session,
+    path: web::Path<String>,
+) -> HttpResponse {
+    let user_id = match get_user_id(&session) {
+        Some(id) => id,
+        None => return HttpResponse::Unauthorized().body("Authentication required"),
+    };
+
+    let result = sqlx::query(
+        "UPDATE download_tokens SET revoked_at = datetime('now') WHERE id = ? AND user_id = ? AND revoked_at IS NULL",
+    )
+    .bind(path.into_inner())
+    .bind(&user_id)
+    .execute(pool.get_ref())
+    .await;
+
+    match result {
+        Ok(r) if r.rows_affected() == 0 => HttpResponse::NotFound().body("Token not found"),
+        Ok(_) => HttpResponse::NoContent().finish(),
+        Err(e) => {
+            tracing::error!("Failed to revoke token: {}", e);
+            HttpResponse::InternalServerError().body("Failed to revoke token")
+        }
+    }
+}
+
+/// Revokes every unused token and share link for a file, e.g. after the
+/// file was replaced.
+pub async fn revoke_file_tokens(
+    pool: web::Data<SqlitePool>,
+    session: Session,
+    path: web::Path<String>,
+) -> HttpResponse {
+    revoke_all(pool.get_ref(), &session, "file_id", &path.into_inner()).await
+}
+
+/// Revokes every unused token and share link held by a user.
+pub async fn revoke_user_tokens(
+    pool: web::Data<SqlitePool>,
+    session: Session,
+    path: web::Path<String>,
+) -> HttpResponse {
+    revoke_all(pool.get_ref(), &session, "user_id", &path.into_inner()).await
+}
+
+async fn revoke_all(pool: &SqlitePool, session: &Session, column: &str, value: &str) -> HttpResponse {
+    let admin_id = match require_admin(pool, session).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    match revoke_unused(pool, column, value).await {
+        Ok(revoked) => {
+            tracing::info!("Admin {} revoked {} tokens for {} {}", admin_id, revoked, column, value);
+            HttpResponse::Ok().json(RevokedTokens { revoked })
+        }
+        Err(e) => {
+            tracing::error!("Failed to revoke tokens: {}", e);
+            HttpResponse::InternalServerError().body("Failed to revoke tokens")
+        }
+    }
+}
+
+/// Revokes unused download and bundle tokens and active share links matching
+/// `column` (`file_id` or `user_id`), all or none of them.
+async fn revoke_unused(pool: &SqlitePool, column: &str, value: &str) -> Result<u64, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let tokens = sqlx::query(&format!(
+        "UPDATE download_tokens SET revoked_at = datetime('now') WHERE {} = ? AND used = 0 AND revoked_at IS NULL",
+        column
+    ))
+    .bind(value)
+    .execute(&mut *tx)
+    .await?;
+    // A bundle matches through any of its files, or through its owner
+    let bundles = sqlx::query(&format!(
+        r#"
+        UPDATE bundle_tokens SET revoked_at = datetime('now')
+        WHERE used = 0 AND revoked_at IS NULL AND id IN (
+            SELECT bf.bundle_id FROM bundle_files bf
+            JOIN bundle_tokens bt ON bt.id = bf.bundle_id
+            WHERE {} = ?
+        )
+        "#,
+        column
+    ))
+    .bind(value)
+    .execute(&mut *tx)
+    .await?;
+    let shares = sqlx::query(&format!(
+        "UPDATE share_links SET revoked_at = datetime('now') WHERE {} = ? AND revoked_at IS NULL",
+        column
+    ))
+    .bind(value)
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+    Ok(tokens.rows_affected() + bundles.rows_affected() + shares.rows_affected())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_revoke_unused_skips_used_and_other_files() {
+        let pool = crate::db::test_pool().await;
+        sqlx::query("INSERT INTO users (id, username, password_hash) VALUES ('u1', 'alice', 'x'), ('u2', 'bob', 'x')")
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query("INSERT INTO download_files (id, file_path, display_name) VALUES ('f1', 'a.zip', 'A'), ('f2', 'b.zip', 'B')")
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query(
+            r#"
+            INSERT INTO download_tokens (id, token, file_id, user_id, used) VALUES
+                ('t1', 'k1', 'f1', 'u1', 0),
+                ('t2', 'k2', 'f1', 'u2', 0),
+                ('t3', 'k3', 'f1', 'u1', 1),
+                ('t4', 'k4', 'f2', 'u1', 0)
+            "#,
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        sqlx::query(
+            r#"
+            INSERT INTO bundle_tokens (id, token, user_id, used) VALUES
+                ('b1', 'bk1', 'u2', 0),
+                ('b2', 'bk2', 'u1', 0),
+                ('b3', 'bk3', 'u2', 1);
+            INSERT INTO bundle_files (bundle_id, file_id) VALUES
+                ('b1', 'f1'), ('b1', 'f2'), ('b2', 'f2'), ('b3', 'f1');
+            INSERT INTO share_links (id, code, file_id, user_id, expires_at) VALUES
+                ('s1', 'c1', 'f1', 'u2', datetime('now', '+1 day')),
+                ('s2', 'c2', 'f2', 'u1', datetime('now', '+1 day'));
+            "#,
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        // t1, t2, b1 and s1
+        assert_eq!(revoke_unused(&pool, "file_id", "f1").await.unwrap(), 4);
+        assert_eq!(revoke_unused(&pool, "file_id", "f1").await.unwrap(), 0);
+        // t4, b2 and s2
+        assert_eq!(revoke_unused(&pool, "user_id", "u1").await.unwrap(), 3);
+
+        let active: Vec<String> = sqlx::query_scalar("SELECT id FROM download_tokens WHERE revoked_at IS NULL ORDER BY id")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        assert_eq!(active, ["t3"]);
+        let active: Vec<String> = sqlx::query_scalar(
+            "SELECT id FROM bundle_tokens WHERE revoked_at IS NULL UNION ALL SELECT id FROM share_links WHERE revoked_at IS NULL",
+        )
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+        assert_eq!(active, ["b3"]);
+    }
+}
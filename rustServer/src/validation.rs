@@ -0,0 +1,195 @@
+use actix_web::HttpResponse;
+use email_address::EmailAddress;
+use serde::Serialize;
+use unicode_normalization::UnicodeNormalization;
+
+use crate::mail::Attachment;
+
+/// Longest address allowed by RFC 5321's path limit.
+const MAX_EMAIL_LEN: usize = 254;
+const MAX_NAME_LEN: usize = 100;
+const MAX_MESSAGE_LEN: usize = 5000;
+const MAX_TOPIC_LEN: usize = 50;
+
+#[derive(Serialize, Debug, PartialEq, Eq)]
+pub struct FieldError {
+    pub field: &'static str,
+    pub code: &'static str,
+    pub message: String,
+}
+
+/// Field errors returned as `422 Unprocessable Entity`, in the order the
+/// fields appear in the form.
+#[derive(Serialize, Debug, Default)]
+pub struct ValidationErrors {
+    pub errors: Vec<FieldError>,
+}
+
+impl ValidationErrors {
+    fn add(&mut self, field: &'static str, code: &'static str, message: impl Into<String>) {
+        self.errors.push(FieldError {
+            field,
+            code,
+            message: message.into(),
+        });
+    }
+
+    pub fn single(error: FieldError) -> Self {
+        Self { errors: vec![error] }
+    }
+
+    pub fn into_response(self) -> HttpResponse {
+        HttpResponse::UnprocessableEntity().json(self)
+    }
+}
+
+/// NFC-normalized single-line text with control characters (including CR
+/// and LF, which could otherwise inject mail headers) removed.
+pub fn single_line(input: &str) -> String {
+    input
+        .nfc()
+        .filter(|c| !c.is_control())
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
+
+/// NFC-normalized multi-line text with `\n` line endings; other control
+/// characters apart from tabs are removed.
+pub fn multi_line(input: &str) -> String {
+    input
+        .replace("\r\n", "\n")
+        .nfc()
+        .filter(|&c| c == '\n' || c == '\t' || !c.is_control())
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
+
+/// Checks a required text field's length in characters, recording an error if it fails.
+pub fn check_length(
+    errors: &mut ValidationErrors,
+    field: &'static str,
+    value: &str,
+    max: usize,
+) {
+    let len = value.chars().count();
+    if len == 0 {
+        errors.add(field, "required", "This field is required");
+    } else if len > max {
+        errors.add(field, "too_long", format!("Must be at most {} characters", max));
+    }
+}
+
+/// Checks an email address against the RFC 5322 `addr-spec` grammar.
+pub fn check_email(errors: &mut ValidationErrors, field: &'static str, value: &str) {
+    if value.is_empty() {
+        errors.add(field, "required", "This field is required");
+    } else if value.len() > MAX_EMAIL_LEN {
+        errors.add(field, "too_long", format!("Must be at most {} characters", MAX_EMAIL_LEN));
+    } else if !EmailAddress::is_valid(value) {
+        errors.add(field, "invalid_email", "Must be a valid email address");
+    }
+}
+
+/// A contact form submission that passed validation, with every field normalized.
+#[derive(Debug, PartialEq, Eq)]
+pub struct ContactForm {
+    pub sender: String,
+    pub first_name: String,
+    pub last_name: String,
+    pub message: String,
+    /// Lowercase topic used to route the message, if one was chosen
+    pub topic: Option<String>,
+    /// Files checked by [`crate::attachments`]
+    pub attachments: Vec<Attachment>,
+}
+
+impl ContactForm {
+    pub fn validate(
+        sender: &str,
+        first_name: &str,
+        last_name: &str,
+        message: &str,
+        topic: Option<&str>,
+    ) -> Result<Self, ValidationErrors> {
+        let form = ContactForm {
+            sender: single_line(sender),
+            first_name: single_line(first_name),
+            last_name: single_line(last_name),
+            message: multi_line(message),
+            topic: topic
+                .map(|topic| single_line(topic).to_lowercase())
+                .filter(|topic| !topic.is_empty()),
+            attachments: Vec::new(),
+        };
+
+        let mut errors = ValidationErrors::default();
+        check_email(&mut errors, "sender", &form.sender);
+        check_length(&mut errors, "firstName", &form.first_name, MAX_NAME_LEN);
+        check_length(&mut errors, "lastName", &form.last_name, MAX_NAME_LEN);
+        check_length(&mut errors, "message", &form.message, MAX_MESSAGE_LEN);
+        if let Some(topic) = &form.topic {
+            check_length(&mut errors, "topic", topic, MAX_TOPIC_LEN);
+        }
+
+        if errors.errors.is_empty() {
+            Ok(form)
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn codes(errors: &ValidationErrors) -> Vec<(&str, &str)> {
+        errors.errors.iter().map(|e| (e.field, e.code)).collect()
+    }
+
+    #[test]
+    fn test_contact_form_normalizes_fields() {
+        let form = ContactForm::validate(
+            " visitor@example.com ",
+            "Ade\u{301}le",
+            "Smith\r\nBcc: spam@example.com",
+            "Line one\r\nLine two\u{0}",
+            Some(" Support "),
+        )
+        .unwrap();
+
+        assert_eq!(form.sender, "visitor@example.com");
+        assert_eq!(form.first_name, "Ad\u{e9}le");
+        assert_eq!(form.last_name, "SmithBcc: spam@example.com");
+        assert_eq!(form.message, "Line one\nLine two");
+        assert_eq!(form.topic.as_deref(), Some("support"));
+    }
+
+    #[test]
+    fn test_contact_form_reports_field_errors() {
+        let errors = ContactForm::validate(
+            "visitor@example.com\r\nBcc: x@example.com",
+            "",
+            &"x".repeat(MAX_NAME_LEN + 1),
+            " \n ",
+            Some(" "),
+        )
+        .unwrap_err();
+
+        assert_eq!(
+            codes(&errors),
+            [
+                ("sender", "invalid_email"),
+                ("firstName", "required"),
+                ("lastName", "too_long"),
+                ("message", "required"),
+            ]
+        );
+
+        let long_address = format!("{}@example.com", "a".repeat(MAX_EMAIL_LEN));
+        let errors = ContactForm::validate(&long_address, "A", "B", "Hi", None).unwrap_err();
+        assert_eq!(codes(&errors), [("sender", "too_long")]);
+    }
+}